@@ -15,6 +15,13 @@ export class Http {
       "Custom-User-Agent": `orimboard/v0.1.0`,
     };
 
+    // requests are made while rendering on the server, so the token stays
+    // out of the bundle sent to browsers
+    const token = process.env.API_TOKEN;
+    if (token) {
+      headers["Authorization"] = `Bearer ${token}`;
+    }
+
     return headers;
   }
 
@@ -27,18 +27,18 @@ const CraneModel: FC<Props> = ({ state, dimensions }) => {
 
     useFrame((_, delta) => {
         setCurrentState(prev => ({
-            swingDeg: state.swingDeg,
-            liftMm: state.liftMm,
-            elbowDeg: state.elbowDeg,
-            wristDeg: state.wristDeg,
-            gripperMm: state.gripperMm,
+            swingRad: state.swingRad,
+            liftM: state.liftM,
+            elbowRad: state.elbowRad,
+            wristRad: state.wristRad,
+            gripperM: state.gripperM,
         }));
     });
 
-    const swingRads = currentState.swingDeg * (Math.PI / 180);
-    const elbowRads = currentState.elbowDeg * (Math.PI / 180);
-    const wristRads = currentState.wristDeg * (Math.PI / 180);
-    const liftMm = currentState.liftMm;
+    const swingRads = currentState.swingRad;
+    const elbowRads = currentState.elbowRad;
+    const wristRads = currentState.wristRad;
+    const liftM = currentState.liftM;
 
     return (
         <>
@@ -58,7 +58,7 @@ const CraneModel: FC<Props> = ({ state, dimensions }) => {
                 />
 
                 {/* Arm Group - positioned at column edge */}
-                <group position={[0, liftMm / 1000, 0]}>
+                <group position={[0, liftM, 0]}>
                     {/* Lift Join: assuming always centered with the thickness of the upper arm */}
                     <Joint
                         position={[0, dimensions.upperArmThickness / 2, 0]}
@@ -103,7 +103,7 @@ const CraneModel: FC<Props> = ({ state, dimensions }) => {
                             <group position={[(dimensions.wristJointRadius / 2) + (dimensions.gripperLength / 2) - dimensions.gripperThickness, -((dimensions.wristJointHeight / 2) + (dimensions.gripperThickness / 2)), 0]}>
                                 <Gripper
                                     position={[0, 0, 0]}
-                                    jaw={state.gripperMm / 1000}
+                                    jaw={state.gripperM}
                                     length={dimensions.gripperLength}
                                     width={dimensions.gripperWidth}
                                     thickness={dimensions.gripperThickness} />
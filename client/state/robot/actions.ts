@@ -1,4 +1,4 @@
-import { CraneState } from "@/types/crane";
+import { CraneSnapshot, CraneState, Motion } from "@/types/crane";
 
 export type Command =
     "LiftUp" |
@@ -40,7 +40,96 @@ type CraneStateUpdate = {
 
 type MoveCrane = {
     type: "move",
-    payload: { x: number, y: number, z: number }
+    payload: { x: number, y: number, z: number, headingRad?: number },
+    clamp?: boolean
+}
+
+type MoveCraneLinear = {
+    type: "moveLinear",
+    payload: { x: number, y: number, z: number, headingRad?: number }
+}
+
+type Waypoint =
+    | { type: "joint", payload: CraneState }
+    | { type: "location", payload: { x: number, y: number, z: number, headingRad?: number } }
+    | { type: "linear", payload: { x: number, y: number, z: number, headingRad?: number } }
+
+type MoveCranePath = {
+    type: "movePath",
+    payload: Waypoint[],
+    blendRadius?: number
+}
+
+type WaypointReached = {
+    type: "waypointReached",
+    index: number
+}
+
+type PathComplete = {
+    type: "pathComplete"
+}
+
+type Ack = {
+    type: "ack",
+    payload: string
+}
+
+type Rejected = {
+    type: "rejected",
+    payload: { requestId?: string, code: string, reason: string }
+}
+
+type Snapshot = {
+    type: "snapshot",
+    payload: CraneSnapshot
+}
+
+type MotionStarted = {
+    type: "motion",
+    payload: Motion
+}
+
+type Stop = {
+    type: "stop"
+}
+
+type EmergencyStop = {
+    type: "emergencyStop"
+}
+
+type Reset = {
+    type: "reset"
+}
+
+type Resume = {
+    type: "resume"
+}
+
+type RequestControl = {
+    type: "requestControl"
+}
+
+type ReleaseControl = {
+    type: "releaseControl"
+}
+
+type ControlChanged = {
+    type: "controlChanged",
+    payload: string | null
+}
+
+type Kick = {
+    type: "kick",
+    payload: string
+}
+
+type ReloadConfig = {
+    type: "reloadConfig"
+}
+
+type ConfigChanged = {
+    type: "configChanged",
+    payload: CraneSnapshot
 }
 
 type ToggleDebugMode = {
@@ -55,4 +144,22 @@ export type CraneAction =
     | CommandAction
     | CraneStateUpdate
     | ToggleDebugMode
-    | MoveCrane;
\ No newline at end of file
+    | MoveCrane
+    | MoveCraneLinear
+    | MoveCranePath
+    | WaypointReached
+    | PathComplete
+    | Ack
+    | Rejected
+    | Snapshot
+    | MotionStarted
+    | Stop
+    | EmergencyStop
+    | Reset
+    | Resume
+    | RequestControl
+    | ReleaseControl
+    | ControlChanged
+    | Kick
+    | ReloadConfig
+    | ConfigChanged;
\ No newline at end of file
@@ -9,6 +9,21 @@ const reducer = (state: CraneSceneState, action: CraneAction): CraneSceneState =
                 ...state,
                 ...{ crane: action.payload }
             }
+        case 'snapshot':
+        case 'configChanged':
+            return {
+                ...state,
+                ...{
+                    crane: action.payload.robot.state,
+                    users: action.payload.users,
+                    controller: action.payload.controller
+                }
+            }
+        case 'controlChanged':
+            return {
+                ...state,
+                ...{ controller: action.payload }
+            }
         case 'debug-mode': {
             return {
                 ...state,
@@ -30,5 +45,6 @@ export const useCraneState = (initialState: CraneState) => useReducer(reducer, {
     connected: true, // default to true to avoid flicker
     crane: initialState,
     users: [],
+    controller: null,
     debugMode: false
 });
\ No newline at end of file
@@ -15,11 +15,17 @@ export const useSocket = (
   const retryTimeout = useRef<NodeJS.Timeout>(null);
 
   const connect = () => {
-    const serverURL = `ws://localhost:7777/v1/robot/${id}/connect`;
+    // browsers cannot set headers on websockets, so the token goes in the query.
+    // it is the operator's own token, kept in the session rather than the bundle
+    const token = sessionStorage.getItem("robotixToken");
+    const query = token ? `?token=${encodeURIComponent(token)}` : "";
+    const serverURL = `ws://localhost:7777/v1/robot/${id}/connect${query}`;
     ws.current = new WebSocket(serverURL);
 
     ws.current.onopen = () => {
       retryCount.current = 0;
+      // only the user in control may move the crane, take it if it is free
+      ws.current?.send(JSON.stringify({ type: "requestControl" }));
       dispatch({
         type: "connect",
         payload: true,
@@ -38,22 +38,94 @@ export type CraneDimensions = {
     gripperMaxOpen: number;   // Maximum opening of the gripper
 }
 
+// Revolute joints are in radians, prismatic joints in meters
 export type CraneState = {
-    swingDeg: number;
-    liftMm: number;
-    elbowDeg: number;
-    wristDeg: number;
-    gripperMm: number;
+    swingRad: number;
+    liftM: number;
+    elbowRad: number;
+    wristRad: number;
+    gripperM: number;
+}
+
+export type Vector3 = {
+    x: number;
+    y: number;
+    z: number;
+}
+
+export type Pose = {
+    position: Vector3;                                          // World position in meters
+    orientation: { x: number, y: number, z: number, w: number }; // Unit quaternion
+    yawRad: number;                                             // Heading about the vertical axis in radians
+}
+
+export type CranePose = {
+    base: Pose;
+    column: Pose;
+    upperArm: Pose;
+    elbow: Pose;
+    wrist: Pose;
+    gripperTip: Pose;
 }
 
 export type CraneDetails = {
     id: string,
     state: CraneState,
-    dimensions: CraneDimensions
+    dimensions: CraneDimensions,
+    pose: CranePose
+}
+
+export type Range = {
+    min: number;
+    max: number;
+}
+
+// revolute joints without a range are continuous
+export type CraneLimits = {
+    swing: Range | null;
+    lift: Range;
+    elbow: Range | null;
+    wrist: Range | null;
+    gripper: Range;
+}
+
+export type Motion = {
+    userId: string;
+    target: CraneState;
+    startedAt: string;
+    durationMs: number;   // Planned duration of the move
+}
+
+export type JointDynamics = {
+    maxVelocity: number;
+    maxAcceleration: number;
+    maxJerk: number;
+}
+
+export type MotionLimits = {
+    profile: "trapezoidal" | "s_curve";
+    swing: JointDynamics;
+    lift: JointDynamics;
+    elbow: JointDynamics;
+    wrist: JointDynamics;
+    gripper: JointDynamics;
+}
+
+export type CraneSnapshot = {
+    robot: CraneDetails;
+    limits: CraneLimits;
+    motionLimits: MotionLimits;
+    users: string[];
+    motion: Motion | null;
+    emergencyStop: boolean;
+    /** motions restored after a restart, waiting for a `resume` */
+    paused: boolean;
+    controller: string | null;
 }
 
 export type CraneSceneState = {
     users: string[],
+    controller: string | null,
     debugMode: boolean,
     crane: CraneState
     connected: boolean,
@@ -0,0 +1,240 @@
+//! # auth
+//!
+//! authenticates clients from the bearer token they present. A token is
+//! either listed in a TOML file along with the user it identifies and
+//! the role it grants:
+//!
+//! ```toml
+//! [[tokens]]
+//! token = "change-me"
+//! user = "alice"
+//! role = "admin"
+//! ```
+//!
+//! or is a JWT signed with HS256 using the configured secret, carrying
+//! the user in its `sub` claim and the role in its `role` claim. Either
+//! way the user is given the same id every time they authenticate.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::robot::Role;
+
+/// Namespace of the ids derived from user names
+const USER_NAMESPACE: Uuid = Uuid::from_u128(0x6b1d5f3e_2a4c_4e8b_9f0d_7c3a1e5b2d94);
+
+/// Who a client is and what it is allowed to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub user: Uuid,
+    pub name: String,
+    pub role: Role,
+}
+
+impl Identity {
+    fn named(name: String, role: Role) -> Self {
+        Self {
+            user: Uuid::new_v5(&USER_NAMESPACE, name.as_bytes()),
+            name,
+            role,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("a token is required")]
+    Missing,
+
+    #[error("the token is not valid: {0}")]
+    Invalid(String),
+}
+
+#[derive(Debug, Deserialize)]
+struct TokensConfig {
+    #[serde(default)]
+    tokens: Vec<TokenConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenConfig {
+    token: String,
+    user: String,
+    role: Role,
+}
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    sub: String,
+    role: Role,
+}
+
+pub struct Authenticator {
+    tokens: HashMap<String, Identity>,
+    jwt: Option<(DecodingKey, Validation)>,
+    /// role granted to clients that do not present a token, when they
+    /// are allowed in at all
+    anonymous_role: Option<Role>,
+}
+
+impl std::fmt::Debug for Authenticator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Authenticator")
+            .field("tokens", &self.tokens.len())
+            .field("jwt", &self.jwt.is_some())
+            .field("anonymous_role", &self.anonymous_role)
+            .finish()
+    }
+}
+
+impl Authenticator {
+    pub fn setup(
+        tokens_file: Option<&str>,
+        jwt_secret: Option<&str>,
+        anonymous_role: Option<Role>,
+    ) -> Result<Self> {
+        let tokens = match tokens_file {
+            Some(path) => load_tokens(Path::new(path))?,
+            None => HashMap::new(),
+        };
+
+        let jwt = jwt_secret.map(|secret| {
+            let mut validation = Validation::new(Algorithm::HS256);
+            validation.set_required_spec_claims(&["exp", "sub"]);
+            (DecodingKey::from_secret(secret.as_bytes()), validation)
+        });
+
+        Ok(Self {
+            tokens,
+            jwt,
+            anonymous_role,
+        })
+    }
+
+    /// Identifies the client presenting a token. Clients without a token
+    /// are given a new anonymous identity if anonymous access is allowed.
+    pub fn authenticate(&self, token: Option<&str>) -> Result<Identity, AuthError> {
+        let Some(token) = token else {
+            return match self.anonymous_role {
+                Some(role) => Ok(Identity {
+                    user: Uuid::new_v4(),
+                    name: "anonymous".to_string(),
+                    role,
+                }),
+                None => Err(AuthError::Missing),
+            };
+        };
+
+        if let Some(identity) = self.tokens.get(token) {
+            return Ok(identity.clone());
+        }
+
+        match &self.jwt {
+            Some((key, validation)) => jsonwebtoken::decode::<Claims>(token, key, validation)
+                .map(|data| Identity::named(data.claims.sub, data.claims.role))
+                .map_err(|e| AuthError::Invalid(e.to_string())),
+            None => Err(AuthError::Invalid("the token is not known".to_string())),
+        }
+    }
+}
+
+fn load_tokens(path: &Path) -> Result<HashMap<String, Identity>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read tokens file: {}", path.display()))?;
+    let config: TokensConfig = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse tokens file: {}", path.display()))?;
+
+    let mut tokens = HashMap::new();
+    for entry in config.tokens {
+        let identity = Identity::named(entry.user, entry.role);
+        if tokens.insert(entry.token, identity).is_some() {
+            anyhow::bail!("Duplicate token in tokens file: {}", path.display());
+        }
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde_json::json;
+
+    use super::*;
+
+    const SECRET: &str = "secret";
+
+    fn authenticator() -> Authenticator {
+        Authenticator::setup(None, Some(SECRET), None).unwrap()
+    }
+
+    fn token(claims: serde_json::Value, secret: &str) -> String {
+        let key = EncodingKey::from_secret(secret.as_bytes());
+        jsonwebtoken::encode(&Header::default(), &claims, &key).unwrap()
+    }
+
+    fn expires() -> i64 {
+        chrono::Utc::now().timestamp() + 3600
+    }
+
+    #[test]
+    fn a_signed_token_identifies_its_user() {
+        let claims = json!({ "sub": "alice", "role": "operator", "exp": expires() });
+        let identity = authenticator()
+            .authenticate(Some(&token(claims.clone(), SECRET)))
+            .unwrap();
+        assert_eq!(identity.name, "alice");
+        assert_eq!(identity.role, Role::Operator);
+
+        // under the same id every time
+        let again = authenticator()
+            .authenticate(Some(&token(claims, SECRET)))
+            .unwrap();
+        assert_eq!(again.user, identity.user);
+    }
+
+    #[test]
+    fn tokens_without_an_expiry_or_user_are_rejected() {
+        let rejected = |claims| {
+            matches!(
+                authenticator().authenticate(Some(&token(claims, SECRET))),
+                Err(AuthError::Invalid(_))
+            )
+        };
+        assert!(rejected(json!({ "sub": "alice", "role": "admin" })));
+        assert!(rejected(json!({ "role": "admin", "exp": expires() })));
+        assert!(rejected(
+            json!({ "sub": "alice", "role": "admin", "exp": expires() - 7200 })
+        ));
+    }
+
+    #[test]
+    fn tokens_signed_with_another_secret_are_rejected() {
+        let claims = json!({ "sub": "alice", "role": "admin", "exp": expires() });
+        let forged = token(claims, "another secret");
+        assert!(matches!(
+            authenticator().authenticate(Some(&forged)),
+            Err(AuthError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn clients_without_a_token_are_anonymous_only_when_allowed() {
+        assert!(matches!(
+            authenticator().authenticate(None),
+            Err(AuthError::Missing)
+        ));
+
+        let anonymous = Authenticator::setup(None, None, Some(Role::Viewer)).unwrap();
+        assert_eq!(anonymous.authenticate(None).unwrap().role, Role::Viewer);
+        assert!(matches!(
+            anonymous.authenticate(Some("unknown")),
+            Err(AuthError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,130 @@
+//! # commands
+//!
+//! subcommands working on the robot configs without starting the server,
+//! so that config changes can be checked ahead of deploying them.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::robot::{
+    chain::{Frame, JointPositions, KinematicChain},
+    kinematics::{self, CranePose, ReachEnvelope},
+    models::{CraneDimensions, CraneLimits, CraneState, MotionLimits},
+    Robot, RobotKind,
+};
+use crate::storage;
+
+/// A robot as it is loaded from its config
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Inspection {
+    id: String,
+    source: Option<String>,
+    dimensions: CraneDimensions,
+    limits: CraneLimits,
+    motion_limits: MotionLimits,
+    reach_envelope: ReachEnvelope,
+    /// the crane as a chain of joints, as chain robots are described
+    chain: KinematicChain,
+    default_state: CraneState,
+    default_pose: CranePose,
+}
+
+/// A chain robot as it is loaded from its config
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ChainInspection {
+    id: String,
+    kind: &'static str,
+    source: Option<String>,
+    chain: KinematicChain,
+    default_state: JointPositions,
+    default_frames: Vec<Frame>,
+}
+
+/// Validates every robot config in `dir`, printing each problem found.
+/// Returns whether every config is valid.
+pub fn validate_config(dir: &str) -> Result<bool> {
+    let (robots, diagnostics) = storage::load_robot_configs(Path::new(dir))?;
+
+    for diagnostic in &diagnostics {
+        eprintln!("{diagnostic}");
+    }
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    println!(
+        "{} valid robot config(s), {errors} problem(s) and {} warning(s) found in {dir}",
+        robots.len(),
+        diagnostics.len() - errors
+    );
+    Ok(errors == 0)
+}
+
+/// Prints a robot from the configs in `dir` as JSON
+pub fn inspect(dir: &str, id: &str) -> Result<()> {
+    let crane = match load(dir, id)? {
+        Robot::Crane(crane) => *crane,
+        Robot::Chain(robot) => {
+            let info = robot.info();
+            let inspection = ChainInspection {
+                id: info.id,
+                kind: robot.kind(),
+                source: robot.source().map(|source| source.display().to_string()),
+                chain: info.chain,
+                default_state: info.state,
+                default_frames: info.frames,
+            };
+            println!("{}", serde_json::to_string_pretty(&inspection)?);
+            return Ok(());
+        }
+    };
+
+    let info = crane.info();
+    let inspection = Inspection {
+        id: info.id,
+        source: crane.source().map(|source| source.display().to_string()),
+        dimensions: info.dimensions,
+        limits: crane.limits().clone(),
+        motion_limits: crane.motion_limits().clone(),
+        reach_envelope: kinematics::reach_envelope(crane.dimensions(), crane.limits()),
+        chain: crane.chain(),
+        default_state: info.state,
+        default_pose: info.pose,
+    };
+    println!("{}", serde_json::to_string_pretty(&inspection)?);
+    Ok(())
+}
+
+/// Prints a robot from the configs in `dir` as URDF, or writes it to
+/// `output`
+pub fn export_urdf(dir: &str, id: &str, output: Option<&str>) -> Result<()> {
+    let crane = match load(dir, id)? {
+        Robot::Crane(crane) => *crane,
+        robot => anyhow::bail!(
+            "robot `{id}` is a {} robot, only cranes can be exported",
+            robot.kind()
+        ),
+    };
+    let urdf = storage::export_urdf(&crane);
+    match output {
+        Some(output) => fs::write(output, urdf)
+            .with_context(|| format!("Failed to write URDF file: {output}"))?,
+        None => print!("{urdf}"),
+    }
+    Ok(())
+}
+
+/// Loads a robot from the configs in `dir`, printing the problems found
+/// in any of them
+fn load(dir: &str, id: &str) -> Result<Robot> {
+    let (robots, diagnostics) = storage::load_robot_configs(Path::new(dir))?;
+    for diagnostic in &diagnostics {
+        eprintln!("{diagnostic}");
+    }
+    robots
+        .into_iter()
+        .find(|robot| robot.id() == id)
+        .with_context(|| format!("no valid config for robot `{id}` in {dir}"))
+}
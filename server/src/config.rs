@@ -1,5 +1,8 @@
+#[allow(unused_imports)]
 use clap::{command, Parser, Subcommand};
 
+use crate::robot::Role;
+
 pub const LOGO: &str = r#"
     ▌   ▗ ▘  
 ▛▘▛▌▛▌▛▌▜▘▌▚▘
@@ -27,6 +30,62 @@ variables or command line flags.
 Use --help to view available configuration options."
     )]
     Start(Settings),
+    #[command(
+        about = "check every robot config in a directory",
+        long_about = "
+
+Validate Config:
+
+Validates every robot config in the directory and reports each
+problem found with its file, TOML key and the rule it breaks.
+Exits with a non-zero status when any config is invalid."
+    )]
+    ValidateConfig(ValidateArgs),
+    #[command(
+        about = "print a robot as it is loaded from its config",
+        long_about = "
+
+Inspect:
+
+Prints the dimensions, limits, reach envelope and default pose of a
+robot, as JSON, exactly as the server loads it from its config."
+    )]
+    Inspect(InspectArgs),
+    #[command(
+        about = "print a robot as a URDF document",
+        long_about = "
+
+Export URDF:
+
+Prints a robot from its config as a URDF document, with box and cylinder
+geometry for its links, so it can be opened in RViz or Gazebo."
+    )]
+    ExportUrdf(ExportArgs),
+}
+
+#[derive(Parser, Debug)]
+pub struct ValidateArgs {
+    #[arg(long, env = "ROBOT_CONFIG_DIR", default_value = "config")]
+    pub dir: String,
+}
+
+#[derive(Parser, Debug)]
+pub struct InspectArgs {
+    /// id of the robot to inspect
+    pub id: String,
+    #[arg(long, env = "ROBOT_CONFIG_DIR", default_value = "config")]
+    pub dir: String,
+}
+
+#[derive(Parser, Debug)]
+pub struct ExportArgs {
+    /// id of the robot to export
+    pub id: String,
+    #[arg(long, env = "ROBOT_CONFIG_DIR", default_value = "config")]
+    pub dir: String,
+    /// file to write the document to instead of stdout
+    #[arg(long, short)]
+    pub output: Option<String>,
 }
 
 #[derive(Parser, Debug)]
@@ -44,10 +103,63 @@ pub struct Settings {
         default_value = "http://localhost:3000"
     )]
     pub cors_allow_origin: String,
+    #[arg(long, env = "ROBOT_CONFIG_DIR", default_value = "config")]
+    pub robot_config_dir: String,
     #[arg(
         long,
-        env = "ROBOT_CONFIG_DIR",
-        default_value = "config"
+        env = "SIMULATION_TICK_HZ",
+        default_value = "40",
+        value_parser = clap::value_parser!(u32).range(1..=1000),
+        help = "rate at which each robot simulation advances and broadcasts updates"
     )]
-    pub robot_config_dir: String,
+    pub simulation_tick_hz: u32,
+    #[arg(
+        long,
+        env = "WATCH_CONFIG",
+        default_value = "true",
+        action = clap::ArgAction::Set,
+        help = "reload robot configs as the files in the config directory change"
+    )]
+    pub watch_config: bool,
+    #[arg(
+        long,
+        env = "STATE_DIR",
+        help = "directory the state of robots is persisted to, kept in memory only when unset"
+    )]
+    pub state_dir: Option<String>,
+    #[arg(
+        long,
+        env = "IDLE_TIMEOUT_SECS",
+        default_value = "300",
+        help = "seconds a robot without users or motion keeps running, 0 keeps robots running forever"
+    )]
+    pub idle_timeout_secs: u64,
+    #[arg(
+        long,
+        env = "AUTH_TOKENS_FILE",
+        help = "TOML file listing the user and role of each token clients may present"
+    )]
+    pub auth_tokens_file: Option<String>,
+    #[arg(
+        long,
+        env = "AUTH_JWT_SECRET",
+        hide_env_values = true,
+        help = "secret verifying HS256 signed JWTs presented as tokens"
+    )]
+    pub auth_jwt_secret: Option<String>,
+    #[arg(
+        long,
+        env = "ANONYMOUS_ROLE",
+        value_enum,
+        help = "role granted to clients without a token, they are refused when unset"
+    )]
+    pub anonymous_role: Option<Role>,
+    #[arg(
+        long,
+        env = "ALLOW_SPAWN",
+        default_value = "false",
+        action = clap::ArgAction::Set,
+        help = "allow new robots to be spawned from the configured ones while the server runs"
+    )]
+    pub allow_spawn: bool,
 }
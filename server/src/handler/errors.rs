@@ -5,9 +5,13 @@ use actix_web::{
 };
 use serde::Serialize;
 
+use crate::storage::{Diagnostic, InvalidConfig};
+
 #[derive(Serialize)]
-struct Response {
+struct Response<'a> {
     message: String,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    diagnostics: &'a [Diagnostic],
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -18,9 +22,36 @@ pub enum ServerError {
     #[error("the robot with id `{0}` was not found")]
     RobotNotFound(String),
 
+    #[error("a robot with id `{0}` already exists")]
+    RobotExists(String),
+
+    #[error("the id `{1}` in the config does not match the robot `{0}`")]
+    RobotIdMismatch(String, String),
+
+    #[error("the robot `{0}` was spawned and has no config to change")]
+    RobotNotConfigured(String),
+
+    #[error("the robot `{0}` is imported from URDF and its config can not be rewritten")]
+    RobotImported(String),
+
+    #[error("the robot `{0}` is a {1} robot, which does not support this")]
+    KindUnsupported(String, &'static str),
+
+    #[error("the robot config is not valid")]
+    InvalidConfig(InvalidConfig),
+
+    #[error("spawning robots from templates is disabled on this server")]
+    SpawnDisabled,
+
     #[error("there are currently no robots available at this time")]
     NoRobotsAvailable,
 
+    #[error("a valid token is required to access this robot")]
+    Unauthorized,
+
+    #[error("the role granted by the token does not allow this action")]
+    Forbidden,
+
     #[error("the server encountered an unexpected error: `{0}`")]
     SystemFailure(String),
 }
@@ -32,14 +63,28 @@ impl ResponseError for ServerError {
             ServerError::RobotIdInvalid => StatusCode::BAD_REQUEST,
             ServerError::SystemFailure(_) => StatusCode::INTERNAL_SERVER_ERROR,
             ServerError::NoRobotsAvailable => StatusCode::NOT_FOUND,
+            ServerError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ServerError::Forbidden => StatusCode::FORBIDDEN,
+            ServerError::RobotExists(_) => StatusCode::CONFLICT,
+            ServerError::SpawnDisabled => StatusCode::FORBIDDEN,
+            ServerError::RobotIdMismatch(..) => StatusCode::BAD_REQUEST,
+            ServerError::RobotNotConfigured(_) => StatusCode::CONFLICT,
+            ServerError::RobotImported(_) => StatusCode::CONFLICT,
+            ServerError::KindUnsupported(..) => StatusCode::CONFLICT,
+            ServerError::InvalidConfig(_) => StatusCode::UNPROCESSABLE_ENTITY,
         }
     }
 
     fn error_response(&self) -> HttpResponse<BoxBody> {
+        let diagnostics = match self {
+            ServerError::InvalidConfig(invalid) => invalid.diagnostics.as_slice(),
+            _ => &[],
+        };
         HttpResponse::build(self.status_code())
             .insert_header(ContentType::json())
             .json(Response {
                 message: self.to_string(),
+                diagnostics,
             })
     }
 }
@@ -1,5 +1,5 @@
-use chrono::{DateTime, Utc};
 use actix_web::HttpResponse;
+use chrono::{DateTime, Utc};
 use serde::Serialize;
 
 #[derive(Serialize)]
@@ -1,10 +1,71 @@
+use std::collections::HashMap;
+
 use actix_cors::Cors;
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header,
+    middleware::{Logger, Next},
+    web::{self, Data},
+    Error, HttpMessage, HttpRequest,
+};
+
+use crate::auth::Authenticator;
+
+use super::errors::ServerError;
 
 pub fn cors_config(allow_origin: &str) -> Cors {
     Cors::default()
         .allowed_origin(allow_origin)
-        .allowed_methods(vec!["GET", "PUT"])
+        .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
         .allow_any_header()
         .supports_credentials()
         .max_age(3600)
 }
+
+/// Logs every request like the default logger, but with the request line
+/// stripped of its query string so `token` parameters never reach the logs
+pub fn access_logger() -> Logger {
+    Logger::new(r#"%a "%{request}xi" %s %b "%{Referer}i" "%{User-Agent}i" %T"#)
+        .custom_request_replace("request", |req| {
+            format!("{} {} {:?}", req.method(), req.path(), req.version())
+        })
+}
+
+/// Authenticates every request from its bearer token, making the
+/// client's `Identity` available to the handlers behind it
+pub async fn authenticate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let authenticator = req
+        .app_data::<Data<Authenticator>>()
+        .ok_or_else(|| ServerError::SystemFailure("authentication is not set up".to_string()))?;
+
+    let identity = authenticator
+        .authenticate(token_from(req.request()).as_deref())
+        .map_err(|e| {
+            tracing::warn!("rejected unauthenticated request: {}", e);
+            ServerError::Unauthorized
+        })?;
+
+    req.extensions_mut().insert(identity);
+    next.call(req).await
+}
+
+/// The token presented by a client, either as a bearer token or as a
+/// `token` query parameter since browsers cannot set headers on websockets
+fn token_from(req: &HttpRequest) -> Option<String> {
+    let bearer = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string());
+
+    bearer.or_else(|| {
+        web::Query::<HashMap<String, String>>::from_query(req.query_string())
+            .ok()
+            .and_then(|mut query| query.remove("token"))
+    })
+}
@@ -8,4 +8,4 @@ pub mod robot_crane;
 
 mod errors;
 mod health_check;
-pub use self::health_check::health_check;
\ No newline at end of file
+pub use self::health_check::health_check;
@@ -1,14 +1,13 @@
-use crate::robot::{
-    self, crane,
-    User,
-};
+use crate::auth::Identity;
+use crate::robot::{self, Action, DefinitionError, Robot, RobotKind, SpawnError, User};
+use crate::storage::{self, RobotConfig};
 use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
-use uuid::Uuid;
+use serde::Deserialize;
 
 use super::errors::ServerError;
 
-fn crane_id_from(req: &HttpRequest) -> Result<crane::ID, ServerError> {
+fn crane_id_from(req: &HttpRequest) -> Result<robot::ID, ServerError> {
     match req.match_info().get("id") {
         Some(id) => Ok(id.to_string()),
         None => Err(ServerError::RobotIdInvalid),
@@ -21,18 +20,198 @@ pub async fn get(
     robot_registry: web::Data<robot::Registry>,
 ) -> Result<HttpResponse, ServerError> {
     let id = crane_id_from(&req)?;
-    match robot_registry.get_crane_details(&id).await {
+    match robot_registry.get_details(&id).await {
         Some(details) => Ok(HttpResponse::Ok().json(details)),
         None => Err(ServerError::RobotNotFound(id)),
     }
 }
 
+#[tracing::instrument(name = "get_pose", skip(req, robot_registry))]
+pub async fn get_pose(
+    req: HttpRequest,
+    robot_registry: web::Data<robot::Registry>,
+) -> Result<HttpResponse, ServerError> {
+    let id = crane_id_from(&req)?;
+    match robot_registry.get_pose(&id).await {
+        Some(pose) => Ok(HttpResponse::Ok().json(pose)),
+        None => Err(ServerError::RobotNotFound(id)),
+    }
+}
+
+async fn send_action(
+    req: &HttpRequest,
+    robot_registry: &robot::Registry,
+    identity: &Identity,
+    action: Action,
+) -> Result<HttpResponse, ServerError> {
+    let id = crane_id_from(req)?;
+    if identity.role < action.required_role() {
+        return Err(ServerError::Forbidden);
+    }
+    tracing::info!("{} issuing {:?} to robot `{}`", identity.name, action, id);
+    match robot_registry.send_action(&id, action).await {
+        Some(true) => Ok(HttpResponse::Accepted().finish()),
+        Some(false) => Err(ServerError::SystemFailure(format!(
+            "robot `{id}` did not accept the action"
+        ))),
+        None => Err(ServerError::RobotNotFound(id)),
+    }
+}
+
+#[tracing::instrument(name = "stop", skip(req, robot_registry, identity))]
+pub async fn stop(
+    req: HttpRequest,
+    robot_registry: web::Data<robot::Registry>,
+    identity: web::ReqData<Identity>,
+) -> Result<HttpResponse, ServerError> {
+    send_action(&req, &robot_registry, &identity, Action::Stop).await
+}
+
+#[tracing::instrument(name = "emergency_stop", skip(req, robot_registry, identity))]
+pub async fn emergency_stop(
+    req: HttpRequest,
+    robot_registry: web::Data<robot::Registry>,
+    identity: web::ReqData<Identity>,
+) -> Result<HttpResponse, ServerError> {
+    send_action(&req, &robot_registry, &identity, Action::EmergencyStop).await
+}
+
+#[tracing::instrument(name = "reset", skip(req, robot_registry, identity))]
+pub async fn reset(
+    req: HttpRequest,
+    robot_registry: web::Data<robot::Registry>,
+    identity: web::ReqData<Identity>,
+) -> Result<HttpResponse, ServerError> {
+    send_action(&req, &robot_registry, &identity, Action::Reset).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SpawnRequest {
+    id: robot::ID,
+}
+
+/// Spawns a new robot using the robot in the path as its template
+#[tracing::instrument(name = "spawn", skip(req, robot_registry, identity))]
+pub async fn spawn(
+    req: HttpRequest,
+    robot_registry: web::Data<robot::Registry>,
+    identity: web::ReqData<Identity>,
+    body: web::Json<SpawnRequest>,
+) -> Result<HttpResponse, ServerError> {
+    let template = crane_id_from(&req)?;
+    if identity.role < robot::Role::Operator {
+        return Err(ServerError::Forbidden);
+    }
+    // the id names the robot's state file and appears in its urls
+    if body.id.trim().is_empty() || !storage::plain_id(&body.id) {
+        return Err(ServerError::RobotIdInvalid);
+    }
+
+    let id = body.into_inner().id;
+    robot_registry
+        .spawn(&template, id.clone())
+        .await
+        .map_err(|e| match e {
+            SpawnError::Disabled => ServerError::SpawnDisabled,
+            SpawnError::TemplateNotFound(template) => ServerError::RobotNotFound(template),
+            SpawnError::AlreadyExists(id) => ServerError::RobotExists(id),
+        })?;
+
+    match robot_registry.get_details(&id).await {
+        Some(details) => Ok(HttpResponse::Created().json(details)),
+        None => Err(ServerError::SystemFailure(format!(
+            "robot `{id}` did not start"
+        ))),
+    }
+}
+
+impl From<DefinitionError> for ServerError {
+    fn from(e: DefinitionError) -> Self {
+        match e {
+            DefinitionError::NotFound(id) => ServerError::RobotNotFound(id),
+            DefinitionError::AlreadyExists(id) => ServerError::RobotExists(id),
+            DefinitionError::Spawned(id) => ServerError::RobotNotConfigured(id),
+            DefinitionError::Imported(id) => ServerError::RobotImported(id),
+            DefinitionError::KindUnsupported(id, kind) => ServerError::KindUnsupported(id, kind),
+            DefinitionError::Invalid(invalid) => ServerError::InvalidConfig(invalid),
+            DefinitionError::Storage(e) => ServerError::SystemFailure(format!("{e:#}")),
+        }
+    }
+}
+
+/// Adds a robot from a config matching the TOML config format
+#[tracing::instrument(name = "create", skip(robot_registry, identity, body))]
+pub async fn create(
+    robot_registry: web::Data<robot::Registry>,
+    identity: web::ReqData<Identity>,
+    body: web::Json<RobotConfig>,
+) -> Result<HttpResponse, ServerError> {
+    if identity.role < robot::Role::Admin {
+        return Err(ServerError::Forbidden);
+    }
+
+    let config = body.into_inner();
+    let id = config.id().to_string();
+    robot_registry.create_robot(config).await?;
+    tracing::info!("robot {} created by {}", id, identity.name);
+
+    match robot_registry.get_details(&id).await {
+        Some(details) => Ok(HttpResponse::Created().json(details)),
+        None => Err(ServerError::SystemFailure(format!(
+            "robot `{id}` did not start"
+        ))),
+    }
+}
+
+/// Replaces the config of the robot in the path
+#[tracing::instrument(name = "update", skip(req, robot_registry, identity, body))]
+pub async fn update(
+    req: HttpRequest,
+    robot_registry: web::Data<robot::Registry>,
+    identity: web::ReqData<Identity>,
+    body: web::Json<RobotConfig>,
+) -> Result<HttpResponse, ServerError> {
+    let id = crane_id_from(&req)?;
+    if identity.role < robot::Role::Admin {
+        return Err(ServerError::Forbidden);
+    }
+
+    let config = body.into_inner();
+    if config.id() != id {
+        return Err(ServerError::RobotIdMismatch(id, config.id().to_string()));
+    }
+    robot_registry.update_robot(&id, config).await?;
+    tracing::info!("robot {} updated by {}", id, identity.name);
+
+    match robot_registry.get_details(&id).await {
+        Some(details) => Ok(HttpResponse::Ok().json(details)),
+        None => Err(ServerError::RobotNotFound(id)),
+    }
+}
+
+/// Removes the robot in the path along with its config
+#[tracing::instrument(name = "delete", skip(req, robot_registry, identity))]
+pub async fn delete(
+    req: HttpRequest,
+    robot_registry: web::Data<robot::Registry>,
+    identity: web::ReqData<Identity>,
+) -> Result<HttpResponse, ServerError> {
+    let id = crane_id_from(&req)?;
+    if identity.role < robot::Role::Admin {
+        return Err(ServerError::Forbidden);
+    }
+
+    robot_registry.delete_robot(&id).await?;
+    tracing::info!("robot {} deleted by {}", id, identity.name);
+    Ok(HttpResponse::NoContent().finish())
+}
+
 #[tracing::instrument(name = "get_all", skip(_req, robot_registry))]
 pub async fn get_all(
     _req: HttpRequest,
     robot_registry: web::Data<robot::Registry>,
 ) -> Result<HttpResponse, ServerError> {
-    let robots = robot_registry.get_all_crane_details().await;
+    let robots = robot_registry.get_all_details().await;
     if !robots.is_empty() {
         Ok(HttpResponse::Ok().json(robots))
     } else {
@@ -40,15 +219,39 @@ pub async fn get_all(
     }
 }
 
-#[tracing::instrument(name = "connect", skip(req, stream, robot_registry))]
+/// Describes a robot as a URDF document, for use in other tools
+#[tracing::instrument(name = "get_urdf", skip(req, robot_registry))]
+pub async fn get_urdf(
+    req: HttpRequest,
+    robot_registry: web::Data<robot::Registry>,
+) -> Result<HttpResponse, ServerError> {
+    let id = crane_id_from(&req)?;
+    match robot_registry.definition(&id) {
+        Some(Robot::Crane(crane)) => Ok(HttpResponse::Ok()
+            .content_type("application/xml")
+            .body(storage::export_urdf(&crane))),
+        Some(robot) => Err(ServerError::KindUnsupported(id, robot.kind())),
+        None => Err(ServerError::RobotNotFound(id)),
+    }
+}
+
+#[tracing::instrument(name = "connect", skip(req, stream, robot_registry, identity))]
 pub async fn connect(
     req: HttpRequest,
     stream: web::Payload,
     robot_registry: web::Data<robot::Registry>,
+    identity: web::ReqData<Identity>,
 ) -> Result<HttpResponse, ServerError> {
-    let user_id = Uuid::new_v4();
     let crane_id = crane_id_from(&req)?;
-    let robot_crane = robot_registry.get_or_create(&crane_id).await;
-    ws::start(User::new(user_id, robot_crane), &req, stream)
-        .map_err(|e| ServerError::SystemFailure(e.to_string()))
+    tracing::info!("{} connecting as user {}", identity.name, identity.user);
+    let robot_crane = robot_registry
+        .get_or_start(&crane_id)
+        .await
+        .ok_or(ServerError::RobotNotFound(crane_id))?;
+    ws::start(
+        User::new(identity.user, identity.role, robot_crane),
+        &req,
+        stream,
+    )
+    .map_err(|e| ServerError::SystemFailure(e.to_string()))
 }
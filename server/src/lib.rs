@@ -1,13 +1,22 @@
+use std::time::Duration;
+
 use actix_web::{
-    middleware::Logger,
+    middleware::from_fn,
     web::{self, Data},
     App, HttpServer,
 };
 use anyhow::{Context, Error};
-use handler::{health_check, middleware::cors_config, robot_crane};
+use auth::Authenticator;
+use handler::{
+    health_check,
+    middleware::{access_logger, authenticate, cors_config},
+    robot_crane,
+};
 use robot::Registry;
-use storage::Database;
+use storage::{ConfigWatcher, Database, StateStore};
 
+pub mod auth;
+pub mod commands;
 pub mod config;
 pub mod robot;
 pub mod storage;
@@ -23,20 +32,64 @@ pub async fn start(config: config::Settings) -> Result<(), Error> {
     let config_dir = &config.robot_config_dir;
     let crane_db = Database::setup(config_dir)?;
 
-    let robot_registry = Data::new(Registry::new(crane_db));
+    let tick = Duration::from_secs_f64(1. / config.simulation_tick_hz as f64);
+    let idle_timeout = match config.idle_timeout_secs {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    };
+    let state_store = match &config.state_dir {
+        Some(dir) => StateStore::open(dir)?,
+        None => StateStore::default(),
+    };
+    let robot_registry = Data::new(
+        Registry::new(crane_db, tick)
+            .with_state_store(state_store)
+            .with_idle_timeout(idle_timeout)
+            .with_spawn(config.allow_spawn),
+    );
+    let authenticator = Data::new(Authenticator::setup(
+        config.auth_tokens_file.as_deref(),
+        config.auth_jwt_secret.as_deref(),
+        config.anonymous_role,
+    )?);
 
-    HttpServer::new(move || {
-        let logger = Logger::default();
+    if config.watch_config {
+        let mut watcher = ConfigWatcher::watch(config_dir)?;
+        let registry = robot_registry.clone();
+        tokio::spawn(async move {
+            while let Some(events) = watcher.next().await {
+                for event in events {
+                    registry.update_config(event).await;
+                }
+            }
+        });
+    }
 
+    let registry = robot_registry.clone();
+    HttpServer::new(move || {
         App::new()
             .wrap(cors_config(&config.cors_allow_origin))
-            .wrap(logger)
+            .wrap(access_logger())
             .route("/healthz", web::get().to(health_check))
             .service(
                 web::scope("/v1/robot")
                     .app_data(robot_registry.clone())
+                    .app_data(authenticator.clone())
+                    .wrap(from_fn(authenticate))
                     .route("", web::get().to(robot_crane::get_all))
+                    .route("", web::post().to(robot_crane::create))
                     .route("/{id}", web::get().to(robot_crane::get))
+                    .route("/{id}", web::put().to(robot_crane::update))
+                    .route("/{id}", web::delete().to(robot_crane::delete))
+                    .route("/{id}/pose", web::get().to(robot_crane::get_pose))
+                    .route("/{id}/urdf", web::get().to(robot_crane::get_urdf))
+                    .route("/{id}/stop", web::post().to(robot_crane::stop))
+                    .route(
+                        "/{id}/emergency-stop",
+                        web::post().to(robot_crane::emergency_stop),
+                    )
+                    .route("/{id}/reset", web::post().to(robot_crane::reset))
+                    .route("/{id}/spawn", web::post().to(robot_crane::spawn))
                     .route("/{id}/connect", web::get().to(robot_crane::connect)),
             )
     })
@@ -45,5 +98,7 @@ pub async fn start(config: config::Settings) -> Result<(), Error> {
     .await
     .context("failed to start up the robotix web server")?;
 
+    registry.persist_all().await;
+
     Ok(())
 }
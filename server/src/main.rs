@@ -8,14 +8,24 @@ use anyhow::Result;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    print!("{LOGO}");
-    println!("v{}", env!("CARGO_PKG_VERSION"));
-    
     let _ = dotenv();
     let args = Arguments::parse();
 
     match args.command {
-        Commands::Start(config) => server::start(config).await?,
+        Commands::Start(config) => {
+            print!("{LOGO}");
+            println!("v{}", env!("CARGO_PKG_VERSION"));
+            server::start(config).await?
+        }
+        Commands::ValidateConfig(args) => {
+            if !server::commands::validate_config(&args.dir)? {
+                std::process::exit(1);
+            }
+        }
+        Commands::Inspect(args) => server::commands::inspect(&args.dir, &args.id)?,
+        Commands::ExportUrdf(args) => {
+            server::commands::export_urdf(&args.dir, &args.id, args.output.as_deref())?
+        }
     }
     Ok(())
 }
@@ -0,0 +1,221 @@
+//! # chain
+//!
+//! a generic robot model: an ordered chain of revolute and prismatic
+//! joints from the base outwards. Every joint is placed relative to the
+//! frame of the joint before it, either by classic or modified
+//! Denavit–Hartenberg parameters or by a fixed transform followed by the
+//! axis it moves about.
+//!
+//! Chain frames are z-up as is usual for robot arms, lengths are in
+//! meters and angles in radians, as they are for the crane.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    kinematics::{Quaternion, Transform, Vector3},
+    models::{wrap_angle, JointDynamics},
+    trajectory::ProfileKind,
+};
+
+/// Joint positions by joint name
+pub type JointPositions = BTreeMap<String, f64>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JointKind {
+    Revolute,
+    Prismatic,
+}
+
+/// Where a joint sits relative to the frame of the joint before it,
+/// angles are in radians
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Placement {
+    /// classic Denavit–Hartenberg parameters: a rotation of `theta` about
+    /// z, an offset of `d` along z, a length of `a` along the new x axis
+    /// and a twist of `alpha` about it. Revolute joints add their position
+    /// to `theta` and prismatic joints to `d`.
+    Dh {
+        a: f64,
+        alpha: f64,
+        d: f64,
+        theta: f64,
+    },
+    /// modified (Craig) Denavit–Hartenberg parameters: a twist of `alpha`
+    /// about the x axis of the joint before, a length of `a` along it, then
+    /// a rotation of `theta` about the new z axis and an offset of `d`
+    /// along it. Joints add their position as they do to classic ones.
+    ModifiedDh {
+        a: f64,
+        alpha: f64,
+        d: f64,
+        theta: f64,
+    },
+    /// a fixed offset and roll, pitch and yaw, after which the joint turns
+    /// about or slides along `axis`
+    Transform {
+        xyz: Vector3,
+        rpy: Vector3,
+        axis: Vector3,
+    },
+}
+
+/// Inclusive range a chain joint may travel within, in radians or
+/// meters
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Bounds {
+    pub min: f64,
+    pub max: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainJoint {
+    pub name: String,
+    pub kind: JointKind,
+    pub placement: Placement,
+    /// none for a revolute joint that turns freely
+    pub range: Option<Bounds>,
+    pub dynamics: JointDynamics,
+}
+
+impl ChainJoint {
+    /// The frame of this joint in the frame of the joint before it, with
+    /// the joint at `position`
+    fn transform(&self, position: f64) -> Transform {
+        let (angle, offset) = match self.kind {
+            JointKind::Revolute => (position, 0.),
+            JointKind::Prismatic => (0., position),
+        };
+
+        match self.placement {
+            Placement::Dh { a, alpha, d, theta } => Transform::translation(0., 0., d + offset)
+                .then(&Transform::rotation(
+                    Vector3::new(0., 0., 1.),
+                    theta + angle,
+                ))
+                .then(&Transform::translation(a, 0., 0.))
+                .then(&Transform::rotation(Vector3::new(1., 0., 0.), alpha)),
+            Placement::ModifiedDh { a, alpha, d, theta } => {
+                Transform::rotation(Vector3::new(1., 0., 0.), alpha)
+                    .then(&Transform::translation(a, 0., 0.))
+                    .then(&Transform::rotation(
+                        Vector3::new(0., 0., 1.),
+                        theta + angle,
+                    ))
+                    .then(&Transform::translation(0., 0., d + offset))
+            }
+            Placement::Transform { xyz, rpy, axis } => {
+                let length = axis.length();
+                let slide = match length > 0. {
+                    true => axis * (offset / length),
+                    false => Vector3::default(),
+                };
+                Transform::translation(xyz.x, xyz.y, xyz.z)
+                    .then(&Transform::rpy(rpy.x, rpy.y, rpy.z))
+                    .then(&Transform::translation(slide.x, slide.y, slide.z))
+                    .then(&Transform::rotation(axis, angle))
+            }
+        }
+    }
+
+    pub fn is_continuous(&self) -> bool {
+        self.range.is_none()
+    }
+
+    /// Brings a position within limits, wrapping continuous joints and
+    /// clamping bounded ones
+    pub fn clamp(&self, value: f64) -> f64 {
+        match self.range {
+            Some(range) => value.clamp(range.min, range.max),
+            None => wrap_angle(value),
+        }
+    }
+
+    /// Signed distance the joint has to travel between two positions,
+    /// taking the shorter way around for continuous joints
+    pub fn travel(&self, from: f64, to: f64) -> f64 {
+        match self.range {
+            Some(_) => to - from,
+            None => wrap_angle(to - from),
+        }
+    }
+}
+
+/// A robot described as a chain of joints
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KinematicChain {
+    pub joints: Vec<ChainJoint>,
+    /// velocity profile used to plan moves
+    pub profile: ProfileKind,
+}
+
+/// World position (in meters) and orientation of the frame of a joint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Frame {
+    pub joint: String,
+    pub position: Vector3,
+    pub orientation: Quaternion,
+}
+
+impl KinematicChain {
+    /// The position in the chain of the joint with the given name
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|joint| joint.name == name)
+    }
+
+    /// Every joint at zero, or as close to it as its range allows
+    pub fn home(&self) -> Vec<f64> {
+        self.joints.iter().map(|joint| joint.clamp(0.)).collect()
+    }
+
+    /// Brings every position within the limits of its joint, joints
+    /// without a position are taken to be at home
+    pub fn constrain(&self, positions: &[f64]) -> Vec<f64> {
+        self.joints
+            .iter()
+            .enumerate()
+            .map(|(n, joint)| joint.clamp(positions.get(n).copied().unwrap_or(0.)))
+            .collect()
+    }
+
+    /// Names every position
+    pub fn named(&self, positions: &[f64]) -> JointPositions {
+        self.joints
+            .iter()
+            .zip(positions)
+            .map(|(joint, position)| (joint.name.clone(), *position))
+            .collect()
+    }
+
+    /// The position of every joint from positions by name, within the
+    /// limits of the joint. Joints without a position are at zero.
+    pub fn unnamed(&self, named: &JointPositions) -> Vec<f64> {
+        self.joints
+            .iter()
+            .map(|joint| joint.clamp(named.get(&joint.name).copied().unwrap_or(0.)))
+            .collect()
+    }
+
+    /// Computes the world frame of every joint
+    pub fn forward_kinematics(&self, positions: &[f64]) -> Vec<Frame> {
+        let mut frame = Transform::identity();
+        self.joints
+            .iter()
+            .zip(positions)
+            .map(|(joint, position)| {
+                frame = frame.then(&joint.transform(*position));
+                Frame {
+                    joint: joint.name.clone(),
+                    position: frame.position(),
+                    orientation: frame.orientation(),
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,628 @@
+//! # chain_robot
+//!
+//! a robot of any topology described by a kinematic chain, such as a six
+//! axis arm or a gantry. Its joints are jogged and moved by name and
+//! every change of their positions is broadcast to the users connected
+//! to it. Chain robots plan joint space moves only, they have no inverse
+//! kinematics yet.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use actix::{Actor, ActorContext, AsyncContext, Context, Handler};
+use chrono::Utc;
+
+use crate::storage::{self, SavedChain, SavedRobot, StateStore};
+
+use super::{
+    chain::KinematicChain,
+    core::{Core, ID, PERSIST_INTERVAL},
+    kind::Robot,
+    message::{
+        Action, ChainInfo, ChainInfoRequest, ChainSnapshot, Connect, Disconnect, ErrorCode,
+        Heartbeat, Operation, Persist, Reconfigure, Retire,
+    },
+    models::JointMotion,
+    trajectory::{Profile, Timing},
+    user,
+};
+
+/// A synchronised move of every joint from one set of positions to
+/// another, all joints following the same timing scaled by their travel
+#[derive(Debug, Clone)]
+struct JointMove {
+    start: Vec<f64>,
+    travel: Vec<f64>,
+    timing: Timing,
+    started: Instant,
+}
+
+impl JointMove {
+    /// Plans the fastest move to `target` within the dynamic limits of
+    /// every joint, none if nothing has to move
+    fn plan(chain: &KinematicChain, start: &[f64], target: &[f64]) -> Option<Self> {
+        let travel: Vec<f64> = chain
+            .joints
+            .iter()
+            .zip(start.iter().zip(target))
+            .map(|(joint, (from, to))| joint.travel(*from, *to))
+            .collect();
+
+        // scale each joint's limits down to the unit distance of the
+        // profile and keep the most restrictive of them
+        let (mut velocity, mut acceleration, mut jerk) = (f64::MAX, f64::MAX, f64::MAX);
+        for (joint, travel) in chain.joints.iter().zip(&travel) {
+            let distance = travel.abs();
+            if distance < f64::EPSILON {
+                continue;
+            }
+            velocity = velocity.min(joint.dynamics.max_velocity / distance);
+            acceleration = acceleration.min(joint.dynamics.max_acceleration / distance);
+            jerk = jerk.min(joint.dynamics.max_jerk / distance);
+        }
+        if velocity == f64::MAX {
+            return None;
+        }
+
+        let profile = Profile::plan(chain.profile, velocity, acceleration, jerk);
+        Some(Self {
+            start: start.to_vec(),
+            travel,
+            timing: Timing::Profile(profile),
+            started: Instant::now(),
+        })
+    }
+
+    /// Plans how to bring this move to a halt from where it is now,
+    /// slowing every joint down as fast as its acceleration limit allows
+    /// while keeping them synchronised
+    fn stop(&self, chain: &KinematicChain) -> Self {
+        let rate = self.timing.velocity(self.started.elapsed().as_secs_f64());
+        let velocities: Vec<f64> = self.travel.iter().map(|travel| travel * rate).collect();
+        let accelerations = chain
+            .joints
+            .iter()
+            .map(|joint| joint.dynamics.max_acceleration);
+        let (timing, travel) = Timing::decelerate(&velocities, accelerations);
+
+        Self {
+            start: self.sample(chain),
+            travel,
+            timing,
+            started: Instant::now(),
+        }
+    }
+
+    fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.timing.duration())
+    }
+
+    fn is_complete(&self) -> bool {
+        self.started.elapsed() >= self.duration()
+    }
+
+    /// The positions of the joints so far into the move
+    fn sample(&self, chain: &KinematicChain) -> Vec<f64> {
+        self.at(
+            chain,
+            self.timing.position(self.started.elapsed().as_secs_f64()),
+        )
+    }
+
+    /// The positions of the joints once the move is complete
+    fn end(&self, chain: &KinematicChain) -> Vec<f64> {
+        self.at(chain, 1.)
+    }
+
+    fn at(&self, chain: &KinematicChain, progress: f64) -> Vec<f64> {
+        chain
+            .joints
+            .iter()
+            .zip(self.start.iter().zip(&self.travel))
+            .map(|(joint, (start, travel))| joint.clamp(start + travel * progress))
+            .collect()
+    }
+}
+
+/// A move in progress along with what was announced of it
+#[derive(Debug, Clone)]
+struct ActiveMove {
+    motion: JointMotion,
+    joint_move: JointMove,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChainRobot {
+    pub id: ID,
+    chain: KinematicChain,
+    positions: Vec<f64>,
+    /// joints are addressed by their position in the chain
+    core: Core<usize>,
+    /// the config file the robot was loaded from, if any
+    source: Option<PathBuf>,
+    motion: Option<ActiveMove>,
+    /// the positions as of the last update broadcast
+    broadcast_positions: Vec<f64>,
+}
+
+impl ChainRobot {
+    pub fn new(id: ID, chain: KinematicChain) -> Self {
+        let positions = chain.home();
+        ChainRobot {
+            id,
+            chain,
+            broadcast_positions: positions.clone(),
+            positions,
+            core: Default::default(),
+            source: None,
+            motion: None,
+        }
+    }
+
+    /// Sets the period of the robot's simulation loop
+    pub fn with_tick(mut self, tick: Duration) -> Self {
+        self.core.tick = tick;
+        self
+    }
+
+    /// Keeps the joint positions in a store while the robot is not
+    /// running, resuming from what is already kept there
+    pub fn with_store(mut self, store: Arc<StateStore>) -> Self {
+        if let Some(SavedRobot::Chain(saved)) = self.core.set_store(&self.id, store) {
+            self.positions = self.chain.unnamed(&saved.positions);
+            self.broadcast_positions = self.positions.clone();
+            self.core.emergency_stop = saved.emergency_stop;
+        }
+        self
+    }
+
+    /// Stops the robot once it has gone without users or motion for the
+    /// given period
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.core.set_idle_timeout(idle_timeout);
+        self
+    }
+
+    /// Records the config file the robot was loaded from
+    pub fn with_source(mut self, source: PathBuf) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// The config file the robot was loaded from, if any
+    pub fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+
+    pub fn chain(&self) -> &KinematicChain {
+        &self.chain
+    }
+
+    /// A new robot with the same chain, which is not tied to the config
+    /// file of this one
+    pub fn instantiate(&self, id: ID) -> ChainRobot {
+        ChainRobot::new(id, self.chain.clone())
+    }
+
+    pub fn info(&self) -> ChainInfo {
+        ChainInfo {
+            id: self.id.clone(),
+            chain: self.chain.clone(),
+            state: self.chain.named(&self.positions),
+            frames: self.chain.forward_kinematics(&self.positions),
+        }
+    }
+
+    fn snapshot(&self) -> ChainSnapshot {
+        ChainSnapshot {
+            robot: self.info(),
+            users: self.core.session.users(),
+            motion: self.motion.as_ref().map(|active| active.motion.clone()),
+            emergency_stop: self.core.emergency_stop,
+            controller: self.core.session.controller(),
+        }
+    }
+
+    /// Reloads the chain from the robot's config file
+    fn reload_config(&mut self) -> anyhow::Result<()> {
+        let Some(source) = &self.source else {
+            anyhow::bail!("the robot was not loaded from a config file");
+        };
+        let config = match storage::load_robot(source)? {
+            Robot::Chain(config) => *config,
+            Robot::Crane(_) => anyhow::bail!("the config file now describes a crane"),
+        };
+        if config.id != self.id {
+            anyhow::bail!(
+                "the config file now describes robot `{}` rather than `{}`",
+                config.id,
+                self.id
+            );
+        }
+
+        self.apply_config(config);
+        Ok(())
+    }
+
+    /// Takes on the chain of another config of the robot. Joints keep
+    /// their positions by name, within their new limits, and anything in
+    /// motion is cancelled.
+    fn apply_config(&mut self, config: ChainRobot) {
+        self.cancel_motion();
+        let named = self.chain.named(&self.positions);
+        self.chain = config.chain;
+        self.positions = self.chain.unnamed(&named);
+    }
+
+    /// Looks up the position in the chain of values addressed by joint name
+    fn named_joints<T: Copy>(
+        &self,
+        values: &HashMap<String, T>,
+    ) -> Result<Vec<(usize, T)>, String> {
+        values
+            .iter()
+            .map(|(name, value)| match self.chain.index(name) {
+                Some(index) => Ok((index, *value)),
+                None => Err(format!("the robot has no joint named `{name}`")),
+            })
+            .collect()
+    }
+
+    /// Releases control held by a user who is no longer around, bringing
+    /// anything they set in motion to a halt
+    fn revoke_control(&mut self, user_id: user::ID) {
+        tracing::info!("robot {} control released from {}", self.id, user_id);
+        self.stop(user_id);
+        self.core.release_control();
+    }
+
+    /// Starts, keeps or stops jogging joints in the direction of the sign
+    /// of their value
+    fn process_jogs(&mut self, directions: Vec<(usize, i64)>, user_id: user::ID) {
+        if self.motion.is_some() {
+            self.cancel_motion();
+        }
+        let chain = &self.chain;
+        self.core
+            .jog(directions, |joint| chain.joints[joint].dynamics, user_id);
+    }
+
+    /// Moves the given joints to their targets from the next tick, the
+    /// other joints stay where they are
+    fn move_joints(&mut self, targets: Vec<(usize, f64)>, user_id: user::ID) {
+        self.cancel_motion();
+        let mut target = self.positions.clone();
+        for (joint, position) in targets {
+            target[joint] = position;
+        }
+        let target = self.chain.constrain(&target);
+        if let Some(joint_move) = JointMove::plan(&self.chain, &self.positions, &target) {
+            self.follow(joint_move, user_id);
+        }
+    }
+
+    /// Starts a move, pre-empting any move in progress, and lets everyone
+    /// know where it goes and how long it takes
+    fn follow(&mut self, joint_move: JointMove, user_id: user::ID) {
+        let motion = JointMotion {
+            user_id,
+            target: self.chain.named(&joint_move.end(&self.chain)),
+            started_at: Utc::now(),
+            duration_ms: joint_move.duration().as_millis() as u64,
+        };
+        self.core.session.broadcast(Operation::new(
+            user_id,
+            Action::JointMotion {
+                payload: motion.clone(),
+            },
+        ));
+
+        self.core.last_user = user_id;
+        self.motion = Some(ActiveMove { motion, joint_move });
+    }
+
+    /// Advances the simulation by one tick and broadcasts a single update
+    /// if the positions changed
+    fn tick(&mut self, ctx: &mut Context<Self>) {
+        if self.core.idle_expired(self.motion.is_some()) {
+            tracing::info!("robot {} is idle, stopping", self.id);
+            ctx.stop();
+            return;
+        }
+
+        if let Some(holder) = self.core.session.expired_lease() {
+            self.revoke_control(holder);
+        }
+
+        match &self.motion {
+            Some(active) => {
+                self.positions = active.joint_move.sample(&self.chain);
+                if active.joint_move.is_complete() {
+                    self.motion = None;
+                }
+            }
+            None => self.integrate_jogs(),
+        }
+
+        if self.positions != self.broadcast_positions {
+            self.broadcast_positions = self.positions.clone();
+            let op = Operation::new(
+                self.core.last_user,
+                Action::JointUpdate {
+                    payload: self.chain.named(&self.positions),
+                },
+            );
+            self.core.session.broadcast(op);
+        }
+    }
+
+    fn integrate_jogs(&mut self) {
+        let (chain, positions) = (&self.chain, &mut self.positions);
+        self.core.integrate_jogs(
+            |joint| chain.joints[joint].dynamics,
+            |joint, delta| positions[joint] = chain.joints[joint].clamp(positions[joint] + delta),
+        );
+    }
+
+    /// Writes the robot to its store if it changed since it was last written
+    fn persist(&mut self) {
+        let saved = SavedChain {
+            positions: self.chain.named(&self.positions),
+            emergency_stop: self.core.emergency_stop,
+        };
+        self.core.persist(&self.id, saved.into());
+    }
+
+    /// Drops any active or jog motion, leaving the robot where it is
+    fn cancel_motion(&mut self) {
+        self.core.jogs.clear();
+        if let Some(active) = self.motion.take() {
+            self.positions = active.joint_move.sample(&self.chain);
+        }
+    }
+
+    /// Brings the active move to a controlled halt
+    fn stop(&mut self, user_id: user::ID) {
+        self.core.jogs.clear();
+        let Some(active) = &self.motion else {
+            return;
+        };
+
+        let stopping = active.joint_move.stop(&self.chain);
+        self.follow(stopping, user_id);
+    }
+}
+
+impl Actor for ChainRobot {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        tracing::info!("robot starting up: name {}", self.id);
+        ctx.run_interval(self.core.tick, |actor, ctx| actor.tick(ctx));
+        ctx.run_interval(PERSIST_INTERVAL, |actor, _ctx| actor.persist());
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.persist();
+        tracing::info!("robot stopped: name {}", self.id);
+    }
+}
+
+impl Handler<Reconfigure<ChainRobot>> for ChainRobot {
+    type Result = ();
+
+    fn handle(&mut self, msg: Reconfigure<ChainRobot>, _ctx: &mut Self::Context) -> Self::Result {
+        let config = msg.config;
+        if config.chain == self.chain {
+            return;
+        }
+
+        tracing::info!("robot {} config changed", self.id);
+        self.source = config.source.clone();
+        self.apply_config(config);
+        let changed = Action::ChainConfigChanged {
+            payload: Box::new(self.snapshot()),
+        };
+        self.core.reconfigured(user::SYSTEM, changed);
+    }
+}
+
+impl Handler<Retire> for ChainRobot {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Retire, ctx: &mut Self::Context) -> Self::Result {
+        tracing::info!("robot {} config removed, stopping", self.id);
+        self.cancel_motion();
+        self.core.forget();
+        self.core.session.kick_all();
+        ctx.stop();
+    }
+}
+
+impl Handler<Persist> for ChainRobot {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Persist, _ctx: &mut Self::Context) -> Self::Result {
+        self.persist();
+    }
+}
+
+impl Handler<Connect> for ChainRobot {
+    type Result = ();
+
+    fn handle(&mut self, msg: Connect, _ctx: &mut Self::Context) -> Self::Result {
+        tracing::info!("user {} connecting to robot {}", &msg.user, &self.id);
+        let snapshot = Action::ChainSnapshot {
+            payload: Box::new(self.snapshot()),
+        };
+        self.core.connect(msg, snapshot);
+    }
+}
+
+impl Handler<Disconnect> for ChainRobot {
+    type Result = ();
+
+    fn handle(&mut self, msg: Disconnect, _ctx: &mut Self::Context) -> Self::Result {
+        tracing::info!("user {} disconnecting from robot {}", &msg.user, &self.id);
+        if self.core.disconnect(&msg) {
+            self.revoke_control(msg.user);
+        }
+    }
+}
+
+impl Handler<Heartbeat> for ChainRobot {
+    type Result = ();
+
+    fn handle(&mut self, msg: Heartbeat, _ctx: &mut Self::Context) -> Self::Result {
+        self.core.session.renew_lease(&msg.user);
+    }
+}
+
+impl Handler<Operation> for ChainRobot {
+    type Result = ();
+
+    fn handle(&mut self, msg: Operation, _ctx: &mut Self::Context) -> Self::Result {
+        if !self.core.admit(&msg, &self.id) {
+            return;
+        }
+
+        match &msg.action {
+            Action::Jog { payload } => match self.named_joints(payload) {
+                Ok(directions) => {
+                    self.process_jogs(directions, msg.user_id);
+                    self.core.session.acknowledge(&msg);
+                }
+                Err(reason) => self
+                    .core
+                    .session
+                    .reject(&msg, ErrorCode::InvalidAction, reason),
+            },
+            Action::MoveJoints { payload } => match self.named_joints(payload) {
+                Ok(targets) => {
+                    self.core.session.acknowledge(&msg);
+                    self.move_joints(targets, msg.user_id);
+                }
+                Err(reason) => self
+                    .core
+                    .session
+                    .reject(&msg, ErrorCode::InvalidAction, reason),
+            },
+            Action::Stop => {
+                self.stop(msg.user_id);
+                self.core.session.acknowledge(&msg);
+                self.core
+                    .session
+                    .broadcast(Operation::new(msg.user_id, Action::Stop));
+            }
+            Action::EmergencyStop => {
+                tracing::warn!("robot {} emergency stopped by {}", self.id, msg.user_id);
+                self.cancel_motion();
+                self.core.emergency_stopped(&msg);
+            }
+            Action::Reset => {
+                tracing::info!("robot {} reset by {}", self.id, msg.user_id);
+                self.core.reset(&msg);
+            }
+            Action::ReloadConfig => match self.reload_config() {
+                Ok(()) => {
+                    tracing::info!("robot {} config reloaded by {}", self.id, msg.user_id);
+                    self.core.session.acknowledge(&msg);
+                    let changed = Action::ChainConfigChanged {
+                        payload: Box::new(self.snapshot()),
+                    };
+                    self.core.reconfigured(msg.user_id, changed);
+                }
+                Err(e) => {
+                    tracing::error!("failed to reload robot config: {:?}", e);
+                    self.core
+                        .session
+                        .reject(&msg, ErrorCode::InvalidConfig, format!("{e:#}"));
+                }
+            },
+            _ => {
+                tracing::warn!(
+                    "robot action not supported by chain robots: {:?}",
+                    msg.action
+                );
+                self.core.session.reject(
+                    &msg,
+                    ErrorCode::Unsupported,
+                    "the action is not supported",
+                );
+            }
+        }
+    }
+}
+
+impl Handler<ChainInfoRequest> for ChainRobot {
+    type Result = ChainInfo;
+
+    fn handle(&mut self, _msg: ChainInfoRequest, _ctx: &mut Self::Context) -> Self::Result {
+        self.info()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::robot::{
+        crane::Crane,
+        kind::RobotKind,
+        models::{CraneDimensions, CraneLimits, MotionLimits},
+    };
+
+    /// The default crane as a chain robot
+    fn robot() -> ChainRobot {
+        let crane = Crane::new(
+            "crane".to_string(),
+            CraneDimensions::default(),
+            CraneLimits::default(),
+            MotionLimits::default(),
+        );
+        ChainRobot::new("chain".to_string(), crane.chain())
+    }
+
+    fn swing(robot: &ChainRobot) -> usize {
+        robot.chain.index("swing").unwrap()
+    }
+
+    #[test]
+    fn moves_are_announced_with_their_target_and_duration() {
+        let mut robot = robot();
+        let swing = swing(&robot);
+        robot.move_joints(vec![(swing, 1.)], user::SYSTEM);
+
+        let active = robot.motion.as_ref().unwrap();
+        let motion = robot.snapshot().motion.unwrap();
+        assert_eq!(motion.target["swing"], 1.);
+        assert_eq!(
+            motion.duration_ms,
+            active.joint_move.duration().as_millis() as u64
+        );
+        assert!(motion.duration_ms > 0);
+    }
+
+    #[test]
+    fn stopping_slows_down_to_a_halt() {
+        let mut robot = robot();
+        let swing = swing(&robot);
+        robot.move_joints(vec![(swing, 1.)], user::SYSTEM);
+        // part way into the move, at speed
+        let active = robot.motion.as_mut().unwrap();
+        active.joint_move.started -= Duration::from_millis(500);
+        let position = active.joint_move.sample(&robot.chain)[swing];
+
+        robot.stop(user::SYSTEM);
+
+        let stopping = &robot.motion.as_ref().unwrap().joint_move;
+        assert!(matches!(stopping.timing, Timing::Decelerate { .. }));
+        assert!(stopping.duration() > Duration::ZERO);
+        assert!((stopping.start[swing] - position).abs() < 1e-2);
+        // the joint carries on the way it was going before it stops
+        let end = stopping.end(&robot.chain)[swing];
+        assert!(end > stopping.start[swing] && end < 1.);
+        assert_eq!(robot.snapshot().motion.unwrap().target["swing"], end);
+    }
+}
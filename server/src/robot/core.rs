@@ -0,0 +1,231 @@
+//! # core
+//!
+//! what every kind of robot runs the same way: its users and the lease of
+//! the one in control, jogging joints, going idle, the emergency stop and
+//! keeping its state in the store. Each kind holds a `Core` keyed by the
+//! way it addresses its joints, and only carries out the motions it
+//! supports itself.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::storage::{SavedRobot, StateStore};
+
+use super::{
+    message::{Action, Connect, Disconnect, Operation},
+    models::JointDynamics,
+    session::Session,
+    user,
+};
+
+pub type ID = String;
+
+/// Default period of the simulation loop
+pub(super) const DEFAULT_TICK: Duration = Duration::from_millis(25);
+
+/// How often a robot writes its state to the store while it changes
+pub(super) const PERSIST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A joint being driven at its maximum velocity in the given direction
+/// until it has covered what is left of its jog increment
+#[derive(Debug, Clone)]
+pub(super) struct Jog {
+    pub direction: i64,
+    pub remaining: f64,
+}
+
+impl Jog {
+    /// A jog in the direction of the sign of `direction` that moves the
+    /// joint by its jog increment. A command for a joint that is already
+    /// jogging starts its increment over, so that a held control keeps
+    /// the joint moving.
+    pub fn new(direction: i64, dynamics: &JointDynamics) -> Self {
+        Jog {
+            direction: direction.signum(),
+            remaining: dynamics.jog_increment,
+        }
+    }
+
+    /// How far the joint moves over a tick of `dt` seconds, taking it no
+    /// further than the increment allows
+    pub fn advance(&mut self, dynamics: &JointDynamics, dt: f64) -> f64 {
+        let step = f64::min(dynamics.max_velocity * dt, self.remaining);
+        self.remaining -= step;
+        self.direction as f64 * step
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.remaining <= 0.
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct Core<J> {
+    pub session: Session,
+    pub tick: Duration,
+    pub jogs: HashMap<J, Jog>,
+    pub emergency_stop: bool,
+    /// who last moved the robot
+    pub last_user: user::ID,
+    /// how long the robot keeps running without users or motion
+    idle_timeout: Option<Duration>,
+    idle_since: Option<Instant>,
+    /// where the state is kept while the robot is not running
+    store: Option<Arc<StateStore>>,
+    /// what was last written to the store
+    persisted: Option<SavedRobot>,
+}
+
+impl<J> Default for Core<J> {
+    fn default() -> Self {
+        Core {
+            session: Default::default(),
+            tick: DEFAULT_TICK,
+            jogs: HashMap::new(),
+            emergency_stop: false,
+            last_user: user::SYSTEM,
+            idle_timeout: None,
+            idle_since: None,
+            store: None,
+            persisted: None,
+        }
+    }
+}
+
+impl<J: Copy + Eq + Hash> Core<J> {
+    pub fn set_idle_timeout(&mut self, idle_timeout: Duration) {
+        self.idle_timeout = Some(idle_timeout);
+    }
+
+    /// Keeps the state of the robot in a store, returning what is already
+    /// kept there
+    pub fn set_store(&mut self, id: &ID, store: Arc<StateStore>) -> Option<SavedRobot> {
+        let saved = store.load(id);
+        self.persisted = saved.clone();
+        self.store = Some(store);
+        saved
+    }
+
+    /// Stops keeping the robot in its store, for a robot that is removed
+    /// and must not write its state again as it stops
+    pub fn forget(&mut self) {
+        self.store = None;
+    }
+
+    /// Writes the robot to its store if it changed since it was last written
+    pub fn persist(&mut self, id: &ID, saved: SavedRobot) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        if self.persisted.as_ref() != Some(&saved) {
+            store.save(id, &saved);
+            self.persisted = Some(saved);
+        }
+    }
+
+    /// Whether the robot has gone without users or motion for longer than
+    /// its idle timeout, `moving` telling whether anything besides jogs is
+    /// under way. An emergency stopped robot is never idle so that the stop
+    /// is not forgotten.
+    pub fn idle_expired(&mut self, moving: bool) -> bool {
+        let Some(idle_timeout) = self.idle_timeout else {
+            return false;
+        };
+
+        let idle =
+            self.session.is_empty() && !moving && self.jogs.is_empty() && !self.emergency_stop;
+        if !idle {
+            self.idle_since = None;
+            return false;
+        }
+
+        let since = *self.idle_since.get_or_insert_with(Instant::now);
+        since.elapsed() >= idle_timeout
+    }
+
+    /// Starts, keeps or stops jogging joints in the direction of the sign
+    /// of their value
+    pub fn jog(
+        &mut self,
+        directions: impl IntoIterator<Item = (J, i64)>,
+        dynamics: impl Fn(J) -> JointDynamics,
+        user_id: user::ID,
+    ) {
+        for (joint, direction) in directions {
+            if direction == 0 {
+                self.jogs.remove(&joint);
+            } else {
+                self.jogs
+                    .insert(joint, Jog::new(direction, &dynamics(joint)));
+            }
+        }
+        self.last_user = user_id;
+    }
+
+    /// Moves every jogging joint on by a tick, handing `apply` the joint
+    /// and how far it moves
+    pub fn integrate_jogs(
+        &mut self,
+        dynamics: impl Fn(J) -> JointDynamics,
+        mut apply: impl FnMut(J, f64),
+    ) {
+        let dt = self.tick.as_secs_f64();
+        for (joint, jog) in self.jogs.iter_mut() {
+            apply(*joint, jog.advance(&dynamics(*joint), dt));
+        }
+        self.jogs.retain(|_, jog| !jog.is_done());
+    }
+
+    /// Adds a user, sending them the `snapshot` of the robot and letting
+    /// everyone know they joined
+    pub fn connect(&mut self, msg: Connect, snapshot: Action) {
+        let user = msg.user;
+        self.session.join(msg);
+        self.session.reply(&user, Operation::new(user, snapshot));
+
+        let op = Operation::new(user, Action::Join { payload: user });
+        self.session.broadcast(op);
+    }
+
+    /// Removes a user, returning whether their control of the robot has to
+    /// be revoked
+    pub fn disconnect(&mut self, msg: &Disconnect) -> bool {
+        self.session.leave(msg) && self.session.controller() == Some(msg.user)
+    }
+
+    /// Releases control of the robot, once whatever its holder set in
+    /// motion has been brought to a halt
+    pub fn release_control(&mut self) {
+        self.session.set_controller(None, user::SYSTEM);
+    }
+
+    /// Checks that the sender of an operation may carry it out and handles
+    /// the actions about control of the robot and its users, returning
+    /// whether the robot itself has to act on it
+    pub fn admit(&mut self, msg: &Operation, robot: &str) -> bool {
+        self.session.authorize(msg, self.emergency_stop) && !self.session.handle(msg, robot)
+    }
+
+    /// Refuses to move until reset, once the robot has halted
+    pub fn emergency_stopped(&mut self, msg: &Operation) {
+        self.jogs.clear();
+        self.emergency_stop = true;
+        self.session.acknowledge(msg);
+        self.session
+            .broadcast(Operation::new(msg.user_id, Action::EmergencyStop));
+    }
+
+    pub fn reset(&mut self, msg: &Operation) {
+        self.emergency_stop = false;
+        self.session.acknowledge(msg);
+        self.session
+            .broadcast(Operation::new(msg.user_id, Action::Reset));
+    }
+
+    /// Lets everyone know the robot was reconfigured by `user_id`
+    pub fn reconfigured(&self, user_id: user::ID, changed: Action) {
+        self.session.broadcast(Operation::new(user_id, changed));
+    }
+}
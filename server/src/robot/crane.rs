@@ -1,20 +1,48 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use actix::{Actor, AsyncContext, Context, Handler, Recipient};
+use actix::{Actor, ActorContext, AsyncContext, Context, Handler};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{self, SavedCrane, SavedRobot, StateStore};
 
 use super::{
+    core::{Core, ID, PERSIST_INTERVAL},
+    kind::Robot,
+    kinematics,
     message::{
-        Action, Command, Connect, Disconnect, KinematicError, Location, Operation, RobotCraneInfo,
-        RobotCraneInfoRequest,
+        Action, Command, Connect, Disconnect, ErrorCode, Heartbeat, KinematicError, Location,
+        Operation, Persist, Reconfigure, Retire, RobotCraneInfo, RobotCraneInfoRequest, Snapshot,
     },
-    models::{CraneDimensions, CraneLimits, CraneState},
+    models::{CraneDimensions, CraneLimits, CraneState, Joint, Motion, MotionLimits},
+    path::{self, Marker, Segment},
+    trajectory::Trajectory,
     user,
 };
 
-pub type ID = String;
+/// A motion in progress along the trajectory it follows
+#[derive(Debug, Clone)]
+struct ActiveMotion {
+    motion: Motion,
+    trajectory: Trajectory,
+    started: Instant,
+    /// waypoints of a path yet to be passed, in order
+    markers: Vec<Marker>,
+    completes_path: bool,
+}
 
-const MOVEMENT_SPEED: Duration = Duration::from_millis(10);
+/// States to pass through from the current one, waiting in the motion
+/// queue to be planned once the motions ahead of it are complete
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Setpoint {
+    segment: Segment,
+    /// whether this is the last segment of a path queued with `MovePath`
+    completes_path: bool,
+    user_id: user::ID,
+}
 
 #[derive(Debug, Clone)]
 pub struct Crane {
@@ -22,270 +50,555 @@ pub struct Crane {
     state: CraneState,
     dimensions: CraneDimensions,
     limits: CraneLimits,
-    recipients: HashMap<user::ID, Recipient<Operation>>,
-    last_update: HashMap<Command, Instant>,
+    motion_limits: MotionLimits,
+    core: Core<Joint>,
+    /// the config file the crane was loaded from, if any
+    source: Option<PathBuf>,
+    queue: VecDeque<Setpoint>,
+    /// whether the queue was restored from the store and waits for the
+    /// user in control to resume it
+    paused: bool,
+    motion: Option<ActiveMotion>,
+    /// the state as of the last update broadcast
+    broadcast_state: CraneState,
 }
 
 impl Crane {
-    pub fn new(id: ID, dimensions: CraneDimensions, limits: CraneLimits) -> Self {
+    pub fn new(
+        id: ID,
+        dimensions: CraneDimensions,
+        limits: CraneLimits,
+        motion_limits: MotionLimits,
+    ) -> Self {
+        let state = limits.constrain(&CraneState::default());
         Crane {
             id,
-            recipients: Default::default(),
-            state: Default::default(),
+            core: Default::default(),
+            source: None,
+            broadcast_state: state.clone(),
+            state,
             limits,
+            motion_limits,
             dimensions,
-            last_update: Default::default(),
+            queue: Default::default(),
+            paused: false,
+            motion: None,
         }
     }
 
-    fn broadcast(&self, msg: Operation) {
-        for (_, user) in self.recipients.iter() {
-            user.do_send(msg.clone())
-        }
+    /// Sets the period of the crane's simulation loop
+    pub fn with_tick(mut self, tick: Duration) -> Self {
+        self.core.tick = tick;
+        self
     }
 
-    fn can_move(&mut self, cmd: &Command) -> bool {
-        let now = Instant::now();
-        match self.last_update.get(cmd) {
-            Some(last) => {
-                if now.duration_since(*last) >= MOVEMENT_SPEED {
-                    self.last_update.insert(cmd.clone(), now);
-                    true
-                } else {
-                    false
-                }
-            }
-            None => {
-                self.last_update.insert(cmd.clone(), now);
-                true
-            }
+    /// Keeps the state and motion queue in a store while the crane is
+    /// not running, resuming from what is already kept there. A restored
+    /// queue is paused, nobody is in control of the crane to watch it move
+    /// until a user takes control and resumes it.
+    pub fn with_store(mut self, store: Arc<StateStore>) -> Self {
+        if let Some(SavedRobot::Crane(saved)) = self.core.set_store(&self.id, store) {
+            self.state = self.limits.constrain(&saved.state);
+            self.broadcast_state = self.state.clone();
+            self.queue = saved.queue.into_iter().collect();
+            self.paused = !self.queue.is_empty();
+            self.core.emergency_stop = saved.emergency_stop;
         }
+        self
     }
 
-    fn process_commands(&mut self, commands: HashSet<Command>) -> CraneState {
-        for cmd in commands {
-            if !self.can_move(&cmd) {
-                continue;
-            }
+    /// Stops the crane once it has gone without users or motion for the
+    /// given period
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.core.set_idle_timeout(idle_timeout);
+        self
+    }
 
-            match cmd {
-                Command::LiftUp => {
-                    self.state.lift_mm = (self.state.lift_mm + 5).min(self.limits.lift_max);
-                }
-                Command::LiftDown => {
-                    self.state.lift_mm = (self.state.lift_mm - 5).max(self.limits.lift_min);
-                }
-                Command::SwingRight => {
-                    self.state.swing_deg = (self.state.swing_deg + 1) % 360;
-                }
-                Command::SwingLeft => {
-                    self.state.swing_deg = (self.state.swing_deg - 1).rem_euclid(360);
-                }
-                Command::ElbowLeft => {
-                    if self.limits.elbow_max == 0 {
-                        self.state.elbow_deg = (self.state.elbow_deg + 1) % 360;
-                    } else {
-                        self.state.elbow_deg =
-                            (self.state.elbow_deg + 1).min(self.limits.elbow_max);
-                    }
-                }
-                Command::ElbowRight => {
-                    if self.limits.elbow_min == 0 {
-                        self.state.elbow_deg = (self.state.elbow_deg - 1).rem_euclid(360);
-                    } else {
-                        self.state.elbow_deg =
-                            (self.state.elbow_deg - 1).max(self.limits.elbow_min);
-                    }
-                }
-                Command::WristLeft => {
-                    if self.limits.wrist_max == 0 {
-                        self.state.wrist_deg = (self.state.wrist_deg + 1) % 360;
-                    } else {
-                        self.state.wrist_deg =
-                            (self.state.wrist_deg + 1).min(self.limits.wrist_max);
-                    }
-                }
-                Command::WristRight => {
-                    if self.limits.wrist_min == 0 {
-                        self.state.wrist_deg = (self.state.wrist_deg - 1).rem_euclid(360);
-                    } else {
-                        self.state.wrist_deg =
-                            (self.state.wrist_deg - 1).max(self.limits.wrist_min);
-                    }
-                }
-                Command::GripperOpen => {
-                    self.state.gripper_mm =
-                        (self.state.gripper_mm + 2).min(self.limits.gripper_max);
-                }
-                Command::GripperClose => {
-                    self.state.gripper_mm =
-                        (self.state.gripper_mm - 2).max(self.limits.gripper_min);
-                }
-            }
-        }
-        self.state.clone()
-    }
-
-    fn calculate_inverse_kinematics(
-        &self,
-        target: &Location,
-    ) -> Result<CraneState, KinematicError> {
-        // Convert all dimensions to millimeters for consistency
-        let base_height_mm = self.dimensions.base_height * 1000.;
-        let upper_arm_mm =
-            (self.dimensions.upper_arm_length + self.dimensions.upper_arm_thickness) * 1000.0;
-        let lower_arm_mm =
-            (self.dimensions.lower_arm_length + self.dimensions.lower_arm_thickness) * 1000.0;
-
-        // Target position in millimeters
-        let x = target.x as f64;
-        let y = target.y as f64; // y is vertical
-        let z = target.z as f64; // z is forward/backward
-
-        // 1. Calculate vertical distance from base to target and clamp it within limits
-        let y_offset = (self.dimensions.wrist_joint_height
-            + self.dimensions.lower_arm_thickness
-            + self.dimensions.elbow_joint_height
-            + self.dimensions.upper_arm_thickness
-            + self.dimensions.gripper_thickness)
-            * 1000.;
-        let dy = y + y_offset - base_height_mm;
-        let lift_mm = dy
-            .clamp(self.limits.lift_min as f64, self.limits.lift_max as f64)
-            .round() as i64;
-
-        // 2. Calculate the extension (lower arm length)
-        let extension = lower_arm_mm;
-        let mut total_extension = extension;
-
-        // Adjust extension direction based on quadrant :(
-        if x < 0. {
-            total_extension = -extension;
-        } else if z >= 0. {
-            total_extension = extension;
-        }
+    /// Records the config file the crane was loaded from
+    pub fn with_source(mut self, source: PathBuf) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// The config file the crane was loaded from, if any
+    pub fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+
+    /// A new crane with the same dimensions and limits, which is not
+    /// tied to the config file of this one
+    pub fn instantiate(&self, id: ID) -> Crane {
+        Crane::new(
+            id,
+            self.dimensions.clone(),
+            self.limits.clone(),
+            self.motion_limits.clone(),
+        )
+    }
 
-        // 3. Calculate wrist position in the XZ plane
-        let wrist_x = x;
-        let wrist_z = z;
+    pub fn dimensions(&self) -> &CraneDimensions {
+        &self.dimensions
+    }
 
-        // 4. Calculate swing angle based on wrist position and extension
-        let swing_rad = wrist_z.atan2(wrist_x - total_extension);
-        let swing_deg = swing_rad.to_degrees().round() as i64;
+    pub fn limits(&self) -> &CraneLimits {
+        &self.limits
+    }
 
-        // 5. Calculate the distance from the base to the wrist position
-        let wrist_r = (wrist_x.powi(2) + wrist_z.powi(2)).sqrt();
-        let wrist_y = 0.0f64; // Since we're handling lift separately
+    pub fn motion_limits(&self) -> &MotionLimits {
+        &self.motion_limits
+    }
 
-        // 6. Calculate elbow angle using law of cosines
-        let cos_angle_elbow = ((upper_arm_mm.powi(2) + lower_arm_mm.powi(2) - wrist_r.powi(2))
-            / (2.0 * upper_arm_mm * lower_arm_mm))
-            .clamp(-1.0, 1.0);
-        let angle_elbow = f64::acos(cos_angle_elbow);
-        let mut final_angle_elbow = angle_elbow;
+    pub fn info(&self) -> RobotCraneInfo {
+        RobotCraneInfo {
+            id: self.id.clone(),
+            state: self.state.clone(),
+            dimensions: self.dimensions.clone(),
+            pose: kinematics::forward_kinematics(&self.dimensions, &self.state),
+        }
+    }
 
-        // sad hack to adjust arm rotation based on quadrant :(
-        if x < 0. {
-            final_angle_elbow = -angle_elbow;
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            robot: self.info(),
+            limits: self.limits.clone(),
+            motion_limits: self.motion_limits.clone(),
+            users: self.core.session.users(),
+            motion: self.motion.as_ref().map(|active| active.motion.clone()),
+            emergency_stop: self.core.emergency_stop,
+            paused: self.paused,
+            controller: self.core.session.controller(),
         }
-        let elbow_deg = if z >= 0. {
-            (180.0 + final_angle_elbow.to_degrees()).round() as i64
-        } else {
-            (180.0 - final_angle_elbow.to_degrees()).round() as i64
+    }
+
+    /// Reloads the dimensions and limits from the crane's config file
+    fn reload_config(&mut self) -> anyhow::Result<()> {
+        let Some(source) = &self.source else {
+            anyhow::bail!("the robot was not loaded from a config file");
+        };
+        let config = match storage::load_robot(source)? {
+            Robot::Crane(config) => *config,
+            Robot::Chain(_) => anyhow::bail!("the config file now describes a chain robot"),
         };
+        if config.id != self.id {
+            anyhow::bail!(
+                "the config file now describes robot `{}` rather than `{}`",
+                config.id,
+                self.id
+            );
+        }
 
-        // 7. Calculate angle from base to wrist
-        let angle_to_wrist = wrist_y.atan2(wrist_r);
+        self.apply_config(config);
+        Ok(())
+    }
 
-        // 8. Calculate wrist angle
-        let wrist_deg = ((angle_to_wrist - angle_elbow).to_degrees().round() / 2.) as i64;
+    /// Takes on the dimensions and limits of another config of the crane.
+    /// Anything in motion is cancelled since it was planned against the
+    /// old limits, and the state is brought within the new ones.
+    fn apply_config(&mut self, config: Crane) {
+        self.cancel_motion();
+        self.dimensions = config.dimensions;
+        self.limits = config.limits;
+        self.motion_limits = config.motion_limits;
+        self.state = self.limits.constrain(&self.state);
+    }
 
-        Ok(CraneState {
-            swing_deg,
-            lift_mm,
-            elbow_deg,
-            wrist_deg,
-            gripper_mm: self.state.gripper_mm, // Keep gripper position unchanged
-        })
+    /// Releases control held by a user who is no longer around, bringing
+    /// anything they set in motion to a halt
+    fn revoke_control(&mut self, user_id: user::ID) {
+        tracing::info!("robot crane {} control released from {}", self.id, user_id);
+        self.stop(user_id);
+        self.core.release_control();
     }
 
-    fn interpolate_to_state(
-        &mut self,
-        target_state: CraneState,
-        user_id: user::ID,
-        delay: Duration,
-        ctx: &mut Context<Self>,
-    ) {
-        let current = self.state.clone();
-
-        // Calculate total motion distance
-        let total_distance = {
-            let swing_dist = (target_state.swing_deg - current.swing_deg).abs() as f64;
-            let lift_dist = (target_state.lift_mm - current.lift_mm).abs() as f64;
-            let elbow_dist = (target_state.elbow_deg - current.elbow_deg).abs() as f64;
-            let wrist_dist = (target_state.wrist_deg - current.wrist_deg).abs() as f64;
-            let gripper_dist = (target_state.gripper_mm - current.gripper_mm).abs() as f64;
-
-            // Sum all distances, with appropriate scaling for different units
-            swing_dist + lift_dist + elbow_dist + wrist_dist + gripper_dist
-        };
+    /// Starts or keeps jogging the joints driven by the commands. Commands
+    /// only set joint velocities, the positions change on the next ticks.
+    fn process_commands(&mut self, commands: HashSet<Command>, user_id: user::ID) {
+        let mut directions: HashMap<Joint, i64> = HashMap::new();
+        for cmd in commands {
+            let (joint, direction) = cmd.jog();
+            *directions.entry(joint).or_default() += direction;
+        }
+        self.process_jogs(directions, user_id);
+    }
+
+    /// Starts, keeps or stops jogging joints in the direction of the sign
+    /// of their value
+    fn process_jogs(&mut self, directions: HashMap<Joint, i64>, user_id: user::ID) {
+        if self.motion.is_some() || !self.queue.is_empty() {
+            self.cancel_motion();
+        }
+        let motion_limits = &self.motion_limits;
+        self.core
+            .jog(directions, |joint| motion_limits.joint(joint), user_id);
+    }
+
+    /// Moves the given joints to their targets from the next tick, the
+    /// other joints stay where they are
+    fn move_joints(&mut self, targets: HashMap<Joint, f64>, user_id: user::ID) {
+        self.cancel_motion();
+        let mut target = self.state.clone();
+        for (joint, position) in targets {
+            target.set(joint, position);
+        }
+        self.interpolate_to_state(target, user_id);
+    }
+
+    /// Whether the crane is moving or has motions queued to run, paused
+    /// motions are kept in the store while the crane is not running
+    fn is_moving(&self) -> bool {
+        self.motion.is_some() || (!self.queue.is_empty() && !self.paused)
+    }
 
-        let steps = (10.0 + total_distance / 10.0).round() as usize;
+    /// Advances the simulation by one tick: starts the next queued motion
+    /// once the crane is idle, moves along the active trajectory or
+    /// integrates jog velocities, and broadcasts a single update if the
+    /// state changed.
+    fn tick(&mut self, ctx: &mut Context<Self>) {
+        if self.core.idle_expired(self.is_moving()) {
+            tracing::info!("robot crane {} is idle, stopping", self.id);
+            ctx.stop();
+            return;
+        }
 
-        // Compute the difference for each field
-        let delta = |start, end| (end - start) as f64 / steps as f64;
+        if let Some(holder) = self.core.session.expired_lease() {
+            self.revoke_control(holder);
+        }
 
-        let swing_step = delta(current.swing_deg, target_state.swing_deg);
-        let lift_step = delta(current.lift_mm, target_state.lift_mm);
-        let elbow_step = delta(current.elbow_deg, target_state.elbow_deg);
-        let wrist_step = delta(current.wrist_deg, target_state.wrist_deg);
-        let gripper_step = delta(current.gripper_mm, target_state.gripper_mm);
+        if self.motion.is_none() && !self.paused {
+            if let Some(setpoint) = self.queue.pop_front() {
+                self.start(setpoint);
+            }
+        }
 
-        for i in 1..=steps {
-            let state = CraneState {
-                swing_deg: (current.swing_deg as f64 + swing_step * i as f64).round() as i64,
-                lift_mm: (current.lift_mm as f64 + lift_step * i as f64).round() as i64,
-                elbow_deg: (current.elbow_deg as f64 + elbow_step * i as f64).round() as i64,
-                wrist_deg: (current.wrist_deg as f64 + wrist_step * i as f64).round() as i64,
-                gripper_mm: (current.gripper_mm as f64 + gripper_step * i as f64).round() as i64,
-            };
+        if self.motion.is_some() {
+            self.advance_motion();
+        } else {
+            self.integrate_jogs();
+        }
 
+        if self.state != self.broadcast_state {
+            self.broadcast_state = self.state.clone();
             let op = Operation::new(
-                user_id,
+                self.core.last_user,
                 Action::Update {
-                    payload: state.clone(),
+                    payload: self.state.clone(),
                 },
             );
+            self.core.session.broadcast(op);
+        }
+    }
 
-            // Schedule the message with a delay for animation effect
-            ctx.run_later(delay * i as u32, move |actor, _ctx| {
-                actor.state = state; // Update the actor's state
-                actor.broadcast(op.clone());
+    /// What to keep of the crane, the motion in progress is kept as the
+    /// first entry of the queue so that it resumes from where it was
+    fn saved(&self) -> SavedCrane {
+        let mut queue = Vec::with_capacity(self.queue.len() + 1);
+        if let Some(active) = &self.motion {
+            let elapsed = active.started.elapsed();
+            let passed = active.trajectory.progress(elapsed).floor();
+            let markers = active
+                .markers
+                .iter()
+                .map(|marker| Marker {
+                    index: marker.index,
+                    position: marker.position - passed,
+                })
+                .collect();
+            queue.push(Setpoint {
+                segment: Segment {
+                    states: active.trajectory.remaining(elapsed, &self.limits),
+                    markers,
+                },
+                completes_path: active.completes_path,
+                user_id: active.motion.user_id,
             });
         }
+        queue.extend(self.queue.iter().cloned());
+
+        SavedCrane {
+            state: self.state.clone(),
+            queue,
+            emergency_stop: self.core.emergency_stop,
+        }
+    }
+
+    /// Writes the crane to its store if it changed since it was last written
+    fn persist(&mut self) {
+        let saved = self.saved().into();
+        self.core.persist(&self.id, saved);
+    }
+
+    fn integrate_jogs(&mut self) {
+        let (limits, state) = (&self.limits, &mut self.state);
+        self.core.integrate_jogs(
+            |joint| self.motion_limits.joint(joint),
+            |joint, delta| state.set(joint, limits.jog(joint, state.get(joint), delta)),
+        );
     }
+
+    /// Resolves a target location into the joint state closest to the
+    /// current one. Unreachable targets are rejected unless `clamp` is
+    /// set, in which case the crane settles for the closest reachable state.
+    fn resolve_target(&self, target: &Location, clamp: bool) -> Result<CraneState, KinematicError> {
+        let solutions =
+            kinematics::inverse_kinematics(&self.dimensions, &self.limits, &self.state, target);
+
+        match solutions {
+            Ok(solutions) => Ok(
+                kinematics::nearest_solution(solutions, &self.limits, &self.state)
+                    .unwrap_or_else(|| self.state.clone()),
+            ),
+            Err(KinematicError::Unreachable { closest }) if clamp => Ok(closest),
+            Err(_) if clamp => Ok(kinematics::closest_state(
+                &self.dimensions,
+                &self.limits,
+                &self.state,
+                target,
+            )),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Moves to a target state from the next tick, pre-empting any motion
+    /// in progress and dropping the motion queue
+    fn interpolate_to_state(&mut self, target_state: CraneState, user_id: user::ID) {
+        self.follow_path(vec![target_state], user_id);
+    }
+
+    /// Follows a path of states from the next tick, in the same way as a
+    /// single target state
+    fn follow_path(&mut self, path: Vec<CraneState>, user_id: user::ID) {
+        self.cancel_motion();
+        let states = path
+            .iter()
+            .map(|state| self.limits.constrain(state))
+            .collect();
+        self.queue.push_back(Setpoint {
+            segment: Segment {
+                states,
+                markers: Vec::new(),
+            },
+            completes_path: false,
+            user_id,
+        });
+    }
+
+    /// The state the crane will be in once every queued motion is complete
+    fn queue_end(&self) -> CraneState {
+        if let Some(state) = self
+            .queue
+            .back()
+            .and_then(|setpoint| setpoint.segment.states.last())
+        {
+            return state.clone();
+        }
+        match &self.motion {
+            Some(active) => active.trajectory.end(&self.limits),
+            None => self.state.clone(),
+        }
+    }
+
+    /// Appends the segments of a path to the motion queue, to be followed
+    /// once the motions queued before them are complete
+    fn enqueue(&mut self, segments: Vec<Segment>, user_id: user::ID) {
+        self.core.jogs.clear();
+        let last = segments.len().saturating_sub(1);
+        for (n, segment) in segments.into_iter().enumerate() {
+            self.queue.push_back(Setpoint {
+                segment,
+                completes_path: n == last,
+                user_id,
+            });
+        }
+    }
+
+    /// Plans a trajectory from the current state through a setpoint and starts following it
+    fn start(&mut self, setpoint: Setpoint) {
+        let path: Vec<CraneState> = std::iter::once(self.state.clone())
+            .chain(setpoint.segment.states)
+            .collect();
+        let trajectory = Trajectory::through(&path, &self.limits, &self.motion_limits);
+        self.follow(trajectory, setpoint.user_id);
+
+        if let Some(active) = &mut self.motion {
+            active.markers = setpoint.segment.markers;
+            active.completes_path = setpoint.completes_path;
+        }
+    }
+
+    /// Starts moving along a trajectory, pre-empting any motion in progress
+    fn follow(&mut self, trajectory: Trajectory, user_id: user::ID) {
+        let motion = Motion {
+            user_id,
+            target: trajectory.end(&self.limits),
+            started_at: Utc::now(),
+            duration_ms: trajectory.duration().as_millis() as u64,
+        };
+        self.core.session.broadcast(Operation::new(
+            user_id,
+            Action::Motion {
+                payload: motion.clone(),
+            },
+        ));
+
+        self.core.last_user = user_id;
+        self.motion = Some(ActiveMotion {
+            motion,
+            trajectory,
+            started: Instant::now(),
+            markers: Vec::new(),
+            completes_path: false,
+        });
+    }
+
+    /// Moves the crane to where the active trajectory should be by now,
+    /// finishing the motion once its planned duration has elapsed and
+    /// reporting the waypoints of a path it has passed
+    fn advance_motion(&mut self) {
+        let Some(active) = &mut self.motion else {
+            return;
+        };
+
+        let elapsed = active.started.elapsed();
+        let finished = elapsed >= active.trajectory.duration();
+        let progress = active.trajectory.progress(elapsed);
+        let passed = active
+            .markers
+            .iter()
+            .take_while(|marker| finished || marker.position <= progress)
+            .count();
+        let reached: Vec<usize> = active
+            .markers
+            .drain(..passed)
+            .map(|marker| marker.index)
+            .collect();
+        let completes_path = finished && active.completes_path;
+        let user_id = active.motion.user_id;
+
+        if finished {
+            self.state = active.trajectory.end(&self.limits);
+            self.motion = None;
+        } else {
+            self.state = active.trajectory.sample(elapsed, &self.limits);
+        }
+
+        for index in reached {
+            self.core
+                .session
+                .broadcast(Operation::new(user_id, Action::WaypointReached { index }));
+        }
+        if completes_path {
+            self.core
+                .session
+                .broadcast(Operation::new(user_id, Action::PathComplete));
+        }
+    }
+
+    /// Drops any queued, active or jog motion, leaving the crane where it is
+    fn cancel_motion(&mut self) {
+        self.queue.clear();
+        self.paused = false;
+        self.core.jogs.clear();
+        if let Some(active) = self.motion.take() {
+            self.state = active
+                .trajectory
+                .sample(active.started.elapsed(), &self.limits);
+        }
+    }
+
+    /// Brings the active motion to a controlled halt
+    fn stop(&mut self, user_id: user::ID) {
+        self.queue.clear();
+        self.paused = false;
+        self.core.jogs.clear();
+        let Some(active) = &self.motion else {
+            return;
+        };
+
+        let stopping =
+            active
+                .trajectory
+                .stop(active.started.elapsed(), &self.limits, &self.motion_limits);
+        self.follow(stopping, user_id);
+    }
+}
+
+/// Looks up the crane joints of values addressed by joint name
+fn named_joints<T: Copy>(values: &HashMap<String, T>) -> Result<HashMap<Joint, T>, String> {
+    values
+        .iter()
+        .map(|(name, value)| match Joint::from_name(name) {
+            Some(joint) => Ok((joint, *value)),
+            None => Err(format!("the crane has no joint named `{name}`")),
+        })
+        .collect()
 }
 
 impl Actor for Crane {
     type Context = Context<Self>;
 
-    fn started(&mut self, _ctx: &mut Self::Context) {
+    fn started(&mut self, ctx: &mut Self::Context) {
         tracing::info!("robot crane starting up: name {}", self.id);
+        ctx.run_interval(self.core.tick, |actor, ctx| actor.tick(ctx));
+        ctx.run_interval(PERSIST_INTERVAL, |actor, _ctx| actor.persist());
     }
 
     fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.persist();
         tracing::info!("robot crane stopped: name {}", self.id);
     }
 }
 
+impl Handler<Reconfigure<Crane>> for Crane {
+    type Result = ();
+
+    fn handle(&mut self, msg: Reconfigure<Crane>, _ctx: &mut Self::Context) -> Self::Result {
+        let config = msg.config;
+        if config.dimensions == self.dimensions
+            && config.limits == self.limits
+            && config.motion_limits == self.motion_limits
+        {
+            return;
+        }
+
+        tracing::info!("robot crane {} config changed", self.id);
+        self.source = config.source.clone();
+        self.apply_config(config);
+        let changed = Action::ConfigChanged {
+            payload: Box::new(self.snapshot()),
+        };
+        self.core.reconfigured(user::SYSTEM, changed);
+    }
+}
+
+impl Handler<Retire> for Crane {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Retire, ctx: &mut Self::Context) -> Self::Result {
+        tracing::info!("robot crane {} config removed, stopping", self.id);
+        self.cancel_motion();
+        self.core.forget();
+        self.core.session.kick_all();
+        ctx.stop();
+    }
+}
+
+impl Handler<Persist> for Crane {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Persist, _ctx: &mut Self::Context) -> Self::Result {
+        self.persist();
+    }
+}
+
 impl Handler<Connect> for Crane {
     type Result = ();
 
     fn handle(&mut self, msg: Connect, _ctx: &mut Self::Context) -> Self::Result {
         tracing::info!("user {} connecting to robot crane {}", &msg.user, &self.id);
-        self.recipients.insert(msg.user, msg.addr);
-        let op = Operation::new(msg.user, Action::Join { payload: msg.user });
-        self.broadcast(op);
+        let snapshot = Action::Snapshot {
+            payload: Box::new(self.snapshot()),
+        };
+        self.core.connect(msg, snapshot);
     }
 }
 
@@ -298,36 +611,168 @@ impl Handler<Disconnect> for Crane {
             &msg.user,
             &self.id
         );
-        self.recipients.remove(&msg.user);
-        let op = Operation::new(msg.user, Action::Leave { payload: msg.user });
-        self.broadcast(op);
+        if self.core.disconnect(&msg) {
+            self.revoke_control(msg.user);
+        }
+    }
+}
+
+impl Handler<Heartbeat> for Crane {
+    type Result = ();
+
+    fn handle(&mut self, msg: Heartbeat, _ctx: &mut Self::Context) -> Self::Result {
+        self.core.session.renew_lease(&msg.user);
     }
 }
 
 impl Handler<Operation> for Crane {
     type Result = ();
 
-    fn handle(&mut self, msg: Operation, ctx: &mut Self::Context) -> Self::Result {
-        match msg.action {
+    fn handle(&mut self, msg: Operation, _ctx: &mut Self::Context) -> Self::Result {
+        if !self.core.admit(&msg, &self.id) {
+            return;
+        }
+
+        match &msg.action {
             Action::Command { payload } => {
-                let state = self.process_commands(payload);
-                let op = Operation::new(msg.user_id, Action::Update { payload: state });
-                self.broadcast(op);
+                self.process_commands(payload.clone(), msg.user_id);
+                self.core.session.acknowledge(&msg);
             }
-            Action::Move { payload } => match self.calculate_inverse_kinematics(&payload) {
+            Action::Jog { payload } => match named_joints(payload) {
+                Ok(directions) => {
+                    self.process_jogs(directions, msg.user_id);
+                    self.core.session.acknowledge(&msg);
+                }
+                Err(reason) => self
+                    .core
+                    .session
+                    .reject(&msg, ErrorCode::InvalidAction, reason),
+            },
+            Action::MoveJoints { payload } => match named_joints(payload) {
+                Ok(targets) => {
+                    self.core.session.acknowledge(&msg);
+                    self.move_joints(targets, msg.user_id);
+                }
+                Err(reason) => self
+                    .core
+                    .session
+                    .reject(&msg, ErrorCode::InvalidAction, reason),
+            },
+            Action::Move { payload, clamp } => match self.resolve_target(payload, *clamp) {
                 Ok(target_state) => {
-                    self.interpolate_to_state(
-                        target_state,
-                        msg.user_id,
-                        Duration::from_millis(25),
-                        ctx,
-                    );
+                    self.core.session.acknowledge(&msg);
+                    self.interpolate_to_state(target_state, msg.user_id);
                 }
                 Err(e) => {
                     tracing::error!("failed to move to position: {}", e);
+                    self.core.session.reject(&msg, e.code(), e.to_string());
+                }
+            },
+            Action::MoveLinear { payload } => {
+                match kinematics::linear_path(&self.dimensions, &self.limits, &self.state, payload)
+                {
+                    Ok(path) => {
+                        self.core.session.acknowledge(&msg);
+                        self.follow_path(path, msg.user_id);
+                    }
+                    Err(e) => {
+                        tracing::error!("failed to move along a line to position: {}", e);
+                        self.core.session.reject(&msg, e.code(), e.to_string());
+                    }
+                }
+            }
+            Action::MovePath { payload, .. } if payload.is_empty() => {
+                self.core.session.reject(
+                    &msg,
+                    ErrorCode::InvalidAction,
+                    "the path has no waypoints",
+                );
+            }
+            Action::MovePath { blend_radius, .. } if *blend_radius < 0. => {
+                self.core.session.reject(
+                    &msg,
+                    ErrorCode::InvalidAction,
+                    "the blend radius must not be negative",
+                );
+            }
+            Action::MovePath {
+                payload,
+                blend_radius,
+            } => match path::resolve(
+                &self.dimensions,
+                &self.limits,
+                &self.queue_end(),
+                payload,
+                *blend_radius,
+            ) {
+                Ok(segments) => {
+                    self.core.session.acknowledge(&msg);
+                    self.enqueue(segments, msg.user_id);
+                }
+                Err(e) => {
+                    tracing::error!("failed to queue path: {}", e);
+                    self.core.session.reject(&msg, e.code(), e.to_string());
                 }
             },
-            _ => tracing::warn!("robot action not implemented yet: {:?}", msg.action),
+            Action::Resume if !self.paused => {
+                self.core.session.reject(
+                    &msg,
+                    ErrorCode::InvalidAction,
+                    "there are no paused motions to resume",
+                );
+            }
+            Action::Resume => {
+                tracing::info!("robot crane {} resumed by {}", self.id, msg.user_id);
+                self.paused = false;
+                self.core.session.acknowledge(&msg);
+                self.core
+                    .session
+                    .broadcast(Operation::new(msg.user_id, Action::Resume));
+            }
+            Action::Stop => {
+                self.stop(msg.user_id);
+                self.core.session.acknowledge(&msg);
+                self.core
+                    .session
+                    .broadcast(Operation::new(msg.user_id, Action::Stop));
+            }
+            Action::EmergencyStop => {
+                tracing::warn!(
+                    "robot crane {} emergency stopped by {}",
+                    self.id,
+                    msg.user_id
+                );
+                self.cancel_motion();
+                self.core.emergency_stopped(&msg);
+            }
+            Action::Reset => {
+                tracing::info!("robot crane {} reset by {}", self.id, msg.user_id);
+                self.core.reset(&msg);
+            }
+            Action::ReloadConfig => match self.reload_config() {
+                Ok(()) => {
+                    tracing::info!("robot crane {} config reloaded by {}", self.id, msg.user_id);
+                    self.core.session.acknowledge(&msg);
+                    let changed = Action::ConfigChanged {
+                        payload: Box::new(self.snapshot()),
+                    };
+                    self.core.reconfigured(msg.user_id, changed);
+                }
+                Err(e) => {
+                    tracing::error!("failed to reload robot crane config: {:?}", e);
+                    self.core
+                        .session
+                        .reject(&msg, ErrorCode::InvalidConfig, format!("{e:#}"));
+                }
+            },
+            _ => {
+                tracing::warn!("robot action not implemented yet: {:?}", msg.action);
+                self.core.session.reject(
+                    &msg,
+                    ErrorCode::Unsupported,
+                    "the action is not supported",
+                );
+            }
         }
     }
 }
@@ -336,10 +781,47 @@ impl Handler<RobotCraneInfoRequest> for Crane {
     type Result = RobotCraneInfo;
 
     fn handle(&mut self, _msg: RobotCraneInfoRequest, _ctx: &mut Self::Context) -> Self::Result {
-        RobotCraneInfo {
-            id: self.id.clone(),
-            state: self.state.clone(),
-            dimensions: self.dimensions.clone(),
-        }
+        self.info()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crane() -> Crane {
+        Crane::new(
+            "test".to_string(),
+            CraneDimensions::default(),
+            CraneLimits::default(),
+            MotionLimits::default(),
+        )
+    }
+
+    #[test]
+    fn move_joints_keeps_the_other_joints_where_they_are() {
+        let mut crane = crane();
+        let mut waypoint = crane.state.clone();
+        waypoint.set(Joint::Swing, 60f64.to_radians());
+        waypoint.set(Joint::Elbow, 45f64.to_radians());
+        let mut end = waypoint.clone();
+        end.set(Joint::Swing, -60f64.to_radians());
+        crane.follow_path(vec![waypoint, end.clone()], user::SYSTEM);
+        // start along the path as the next tick would, with more queued behind it
+        let setpoint = crane.queue.pop_front().unwrap();
+        Crane::start(&mut crane, setpoint);
+        let segment = Segment {
+            states: vec![end],
+            markers: Vec::new(),
+        };
+        crane.enqueue(vec![segment], user::SYSTEM);
+
+        crane.move_joints(HashMap::from([(Joint::Lift, 0.4)]), user::SYSTEM);
+
+        let mut expected = crane.state.clone();
+        expected.set(Joint::Lift, 0.4);
+        assert!(crane.motion.is_none());
+        assert_eq!(crane.queue.len(), 1);
+        assert_eq!(crane.queue_end(), expected);
     }
 }
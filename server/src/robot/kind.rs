@@ -0,0 +1,235 @@
+//! # kind
+//!
+//! the kinds of robot the registry hosts. Every kind can be described as
+//! a kinematic chain, so that its joints are addressed by name the same
+//! way whatever its topology, while each kind runs as an actor of its own
+//! with the actions it supports.
+
+use std::path::Path;
+
+use actix::dev::ToEnvelope;
+use actix::{Actor, Addr, Handler, MailboxError, Message};
+
+use super::{
+    chain::{Bounds, ChainJoint, JointKind, KinematicChain, Placement},
+    chain_robot::ChainRobot,
+    core::ID,
+    crane::Crane,
+    kinematics::{joint_origins, Vector3},
+    models::Joint,
+};
+
+pub trait RobotKind {
+    /// The name of the kind, as given by `kind` in configs
+    fn kind(&self) -> &'static str;
+
+    fn id(&self) -> &str;
+
+    /// The config file the robot was loaded from, if any
+    fn source(&self) -> Option<&Path>;
+
+    /// The robot described as a chain of joints
+    fn chain(&self) -> KinematicChain;
+
+    /// A new robot of the same kind and geometry, which is not tied to the
+    /// config file of this one
+    fn instantiate(&self, id: ID) -> Self;
+}
+
+impl RobotKind for Crane {
+    fn kind(&self) -> &'static str {
+        "crane"
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn source(&self) -> Option<&Path> {
+        Crane::source(self)
+    }
+
+    /// The crane as exported to URDF: z-up, with the gripper opening along
+    /// the x axis of the wrist
+    fn chain(&self) -> KinematicChain {
+        let origins = joint_origins(self.dimensions());
+        let joints = Joint::ALL
+            .into_iter()
+            .zip(origins)
+            .map(|(joint, [x, y, z])| {
+                let axis = match joint {
+                    Joint::Gripper => Vector3::new(1., 0., 0.),
+                    _ => Vector3::new(0., 0., 1.),
+                };
+                ChainJoint {
+                    name: joint.to_string(),
+                    kind: match joint.is_revolute() {
+                        true => JointKind::Revolute,
+                        false => JointKind::Prismatic,
+                    },
+                    placement: Placement::Transform {
+                        xyz: Vector3::new(x, y, z),
+                        rpy: Vector3::default(),
+                        axis,
+                    },
+                    range: self.limits().range(joint).map(|range| Bounds {
+                        min: range.min,
+                        max: range.max,
+                    }),
+                    dynamics: self.motion_limits().joint(joint),
+                }
+            })
+            .collect();
+
+        KinematicChain {
+            joints,
+            profile: self.motion_limits().profile,
+        }
+    }
+
+    fn instantiate(&self, id: ID) -> Self {
+        Crane::instantiate(self, id)
+    }
+}
+
+impl RobotKind for ChainRobot {
+    fn kind(&self) -> &'static str {
+        "chain"
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn source(&self) -> Option<&Path> {
+        ChainRobot::source(self)
+    }
+
+    fn chain(&self) -> KinematicChain {
+        ChainRobot::chain(self).clone()
+    }
+
+    fn instantiate(&self, id: ID) -> Self {
+        ChainRobot::instantiate(self, id)
+    }
+}
+
+/// A robot of any kind, as configured or spawned
+#[derive(Debug, Clone)]
+pub enum Robot {
+    Crane(Box<Crane>),
+    Chain(Box<ChainRobot>),
+}
+
+impl RobotKind for Robot {
+    fn kind(&self) -> &'static str {
+        match self {
+            Robot::Crane(crane) => crane.kind(),
+            Robot::Chain(robot) => robot.kind(),
+        }
+    }
+
+    fn id(&self) -> &str {
+        match self {
+            Robot::Crane(crane) => RobotKind::id(crane.as_ref()),
+            Robot::Chain(robot) => RobotKind::id(robot.as_ref()),
+        }
+    }
+
+    fn source(&self) -> Option<&Path> {
+        match self {
+            Robot::Crane(crane) => crane.source(),
+            Robot::Chain(robot) => robot.source(),
+        }
+    }
+
+    fn chain(&self) -> KinematicChain {
+        match self {
+            Robot::Crane(crane) => RobotKind::chain(crane.as_ref()),
+            Robot::Chain(robot) => RobotKind::chain(robot.as_ref()),
+        }
+    }
+
+    fn instantiate(&self, id: ID) -> Self {
+        match self {
+            Robot::Crane(crane) => Robot::Crane(Box::new(crane.instantiate(id))),
+            Robot::Chain(robot) => Robot::Chain(Box::new(robot.instantiate(id))),
+        }
+    }
+}
+
+impl From<Crane> for Robot {
+    fn from(crane: Crane) -> Self {
+        Robot::Crane(Box::new(crane))
+    }
+}
+
+impl From<ChainRobot> for Robot {
+    fn from(robot: ChainRobot) -> Self {
+        Robot::Chain(Box::new(robot))
+    }
+}
+
+/// The running actor of a robot of any kind. Messages every kind handles
+/// can be sent without knowing which kind it is.
+#[derive(Debug, Clone)]
+pub enum RobotAddr {
+    Crane(Addr<Crane>),
+    Chain(Addr<ChainRobot>),
+}
+
+impl RobotAddr {
+    pub fn connected(&self) -> bool {
+        match self {
+            RobotAddr::Crane(addr) => addr.connected(),
+            RobotAddr::Chain(addr) => addr.connected(),
+        }
+    }
+
+    pub fn do_send<M>(&self, msg: M)
+    where
+        M: Message + Send + 'static,
+        M::Result: Send,
+        Crane: Handler<M>,
+        ChainRobot: Handler<M>,
+        <Crane as Actor>::Context: ToEnvelope<Crane, M>,
+        <ChainRobot as Actor>::Context: ToEnvelope<ChainRobot, M>,
+    {
+        match self {
+            RobotAddr::Crane(addr) => addr.do_send(msg),
+            RobotAddr::Chain(addr) => addr.do_send(msg),
+        }
+    }
+
+    /// Sends a message without waiting, failing if the robot's mailbox is
+    /// full or the robot has stopped
+    pub fn try_send<M>(&self, msg: M) -> Result<(), actix::prelude::SendError<M>>
+    where
+        M: Message + Send + 'static,
+        M::Result: Send,
+        Crane: Handler<M>,
+        ChainRobot: Handler<M>,
+        <Crane as Actor>::Context: ToEnvelope<Crane, M>,
+        <ChainRobot as Actor>::Context: ToEnvelope<ChainRobot, M>,
+    {
+        match self {
+            RobotAddr::Crane(addr) => addr.try_send(msg),
+            RobotAddr::Chain(addr) => addr.try_send(msg),
+        }
+    }
+
+    pub async fn send<M>(&self, msg: M) -> Result<M::Result, MailboxError>
+    where
+        M: Message + Send + 'static,
+        M::Result: Send,
+        Crane: Handler<M>,
+        ChainRobot: Handler<M>,
+        <Crane as Actor>::Context: ToEnvelope<Crane, M>,
+        <ChainRobot as Actor>::Context: ToEnvelope<ChainRobot, M>,
+    {
+        match self {
+            RobotAddr::Crane(addr) => addr.send(msg).await,
+            RobotAddr::Chain(addr) => addr.send(msg).await,
+        }
+    }
+}
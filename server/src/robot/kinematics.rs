@@ -0,0 +1,1056 @@
+//! # kinematics
+//!
+//! forward and inverse kinematics for the robot crane. Frames follow the
+//! conventions of the client scene graph: y is up, lengths are in
+//! meters and every revolute joint rotates about the vertical axis.
+
+use std::f64::consts::{PI, TAU};
+use std::ops::{Add, Mul, Sub};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    message::{KinematicError, Location},
+    models::{
+        wrap_angle, CraneDimensions, CraneLimits, CraneState, DhConvention, DhTable, Joint, Range,
+    },
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Vector3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Vector3 {
+    pub const fn new(x: f64, y: f64, z: f64) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn length(&self) -> f64 {
+        (self.x.powi(2) + self.y.powi(2) + self.z.powi(2)).sqrt()
+    }
+}
+
+impl Add for Vector3 {
+    type Output = Vector3;
+
+    fn add(self, rhs: Vector3) -> Self::Output {
+        Vector3::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl Sub for Vector3 {
+    type Output = Vector3;
+
+    fn sub(self, rhs: Vector3) -> Self::Output {
+        Vector3::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl Mul<f64> for Vector3 {
+    type Output = Vector3;
+
+    fn mul(self, rhs: f64) -> Self::Output {
+        Vector3::new(self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+/// Unit quaternion describing the orientation of a frame in world space
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Quaternion {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub w: f64,
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Self {
+            x: 0.,
+            y: 0.,
+            z: 0.,
+            w: 1.,
+        }
+    }
+}
+
+/// Rigid body transform made up of a rotation matrix and a translation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    rotation: [[f64; 3]; 3],
+    translation: Vector3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    pub const fn identity() -> Self {
+        Self {
+            rotation: [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]],
+            translation: Vector3::new(0., 0., 0.),
+        }
+    }
+
+    pub fn translation(x: f64, y: f64, z: f64) -> Self {
+        Self {
+            translation: Vector3::new(x, y, z),
+            ..Self::identity()
+        }
+    }
+
+    /// Rotation about the vertical (y) axis, matching `rotation={[0, angle, 0]}` in three.js
+    pub fn rotation_y(angle_rad: f64) -> Self {
+        let (sin, cos) = angle_rad.sin_cos();
+        Self {
+            rotation: [[cos, 0., sin], [0., 1., 0.], [-sin, 0., cos]],
+            ..Self::identity()
+        }
+    }
+
+    /// Rotation about an arbitrary axis, which does not need to be of unit length
+    pub fn rotation(axis: Vector3, angle_rad: f64) -> Self {
+        let length = axis.length();
+        if length == 0. {
+            return Self::identity();
+        }
+        let (x, y, z) = (axis.x / length, axis.y / length, axis.z / length);
+        let (sin, cos) = angle_rad.sin_cos();
+        let t = 1. - cos;
+        Self {
+            rotation: [
+                [t * x * x + cos, t * x * y - sin * z, t * x * z + sin * y],
+                [t * x * y + sin * z, t * y * y + cos, t * y * z - sin * x],
+                [t * x * z - sin * y, t * y * z + sin * x, t * z * z + cos],
+            ],
+            ..Self::identity()
+        }
+    }
+
+    /// Rotation by roll, pitch and yaw about the fixed x, y and z axes, in
+    /// that order, as URDF origins are given
+    pub fn rpy(roll_rad: f64, pitch_rad: f64, yaw_rad: f64) -> Self {
+        Self::rotation(Vector3::new(0., 0., 1.), yaw_rad)
+            .then(&Self::rotation(Vector3::new(0., 1., 0.), pitch_rad))
+            .then(&Self::rotation(Vector3::new(1., 0., 0.), roll_rad))
+    }
+
+    /// Returns the transform of `child` expressed relative to the parent frame of `self`
+    pub fn then(&self, child: &Transform) -> Transform {
+        let mut rotation = [[0.; 3]; 3];
+        for (i, row) in rotation.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..3)
+                    .map(|k| self.rotation[i][k] * child.rotation[k][j])
+                    .sum();
+            }
+        }
+
+        Transform {
+            rotation,
+            translation: self.apply(child.translation),
+        }
+    }
+
+    /// Maps a point from this frame into the parent frame
+    pub fn apply(&self, point: Vector3) -> Vector3 {
+        let r = &self.rotation;
+        Vector3::new(
+            r[0][0] * point.x + r[0][1] * point.y + r[0][2] * point.z,
+            r[1][0] * point.x + r[1][1] * point.y + r[1][2] * point.z,
+            r[2][0] * point.x + r[2][1] * point.y + r[2][2] * point.z,
+        ) + self.translation
+    }
+
+    pub fn position(&self) -> Vector3 {
+        self.translation
+    }
+
+    pub fn orientation(&self) -> Quaternion {
+        let r = &self.rotation;
+        let trace = r[0][0] + r[1][1] + r[2][2];
+
+        if trace > 0. {
+            let s = (trace + 1.).sqrt() * 2.;
+            Quaternion {
+                w: 0.25 * s,
+                x: (r[2][1] - r[1][2]) / s,
+                y: (r[0][2] - r[2][0]) / s,
+                z: (r[1][0] - r[0][1]) / s,
+            }
+        } else if r[0][0] > r[1][1] && r[0][0] > r[2][2] {
+            let s = (1. + r[0][0] - r[1][1] - r[2][2]).sqrt() * 2.;
+            Quaternion {
+                w: (r[2][1] - r[1][2]) / s,
+                x: 0.25 * s,
+                y: (r[0][1] + r[1][0]) / s,
+                z: (r[0][2] + r[2][0]) / s,
+            }
+        } else if r[1][1] > r[2][2] {
+            let s = (1. + r[1][1] - r[0][0] - r[2][2]).sqrt() * 2.;
+            Quaternion {
+                w: (r[0][2] - r[2][0]) / s,
+                x: (r[0][1] + r[1][0]) / s,
+                y: 0.25 * s,
+                z: (r[1][2] + r[2][1]) / s,
+            }
+        } else {
+            let s = (1. + r[2][2] - r[0][0] - r[1][1]).sqrt() * 2.;
+            Quaternion {
+                w: (r[1][0] - r[0][1]) / s,
+                x: (r[0][2] + r[2][0]) / s,
+                y: (r[1][2] + r[2][1]) / s,
+                z: 0.25 * s,
+            }
+        }
+    }
+
+    /// Heading of the frame's x axis about the vertical axis, in radians
+    pub fn yaw_rad(&self) -> f64 {
+        (0. - self.rotation[2][0]).atan2(self.rotation[0][0])
+    }
+
+    pub fn pose(&self) -> Pose {
+        Pose {
+            position: self.position(),
+            orientation: self.orientation(),
+            yaw_rad: self.yaw_rad(),
+        }
+    }
+}
+
+/// World position (in meters) and orientation of a single link frame
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Pose {
+    pub position: Vector3,
+    pub orientation: Quaternion,
+    pub yaw_rad: f64,
+}
+
+/// World poses of every link frame of a crane for a given joint state
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CranePose {
+    pub base: Pose,
+    pub column: Pose,
+    pub upper_arm: Pose,
+    pub elbow: Pose,
+    pub wrist: Pose,
+    pub gripper_tip: Pose,
+}
+
+/// Offsets of the link frames relative to their parent joint, taken from
+/// the DH table of the crane when it has one and otherwise derived from
+/// the cosmetic dimensions in the same way the client positions its
+/// scene graph groups
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LinkOffsets {
+    /// height of the swing joint above the world origin
+    pub column: Vector3,
+    /// from the lift carriage to the elbow pivot
+    pub elbow: Vector3,
+    /// from the elbow pivot to the wrist pivot
+    pub wrist: Vector3,
+    /// from the wrist pivot to the underside of the gripper
+    pub gripper_tip: Vector3,
+}
+
+impl LinkOffsets {
+    pub fn from(dimensions: &CraneDimensions) -> Self {
+        if let Some(table) = &dimensions.kinematics {
+            return Self::from_table(table);
+        }
+
+        let d = dimensions;
+        let forearm_y = -((d.elbow_joint_height / 2.) + d.lower_arm_thickness);
+
+        LinkOffsets {
+            column: Vector3::new(0., d.base_height, 0.),
+            elbow: Vector3::new(
+                d.upper_arm_length + (d.column_width / 2.) + (d.column_thickness / 2.)
+                    - d.elbow_joint_radius,
+                -(d.elbow_joint_height / 2.),
+                0.,
+            ),
+            wrist: Vector3::new(
+                d.lower_arm_length - d.wrist_joint_radius,
+                forearm_y - (d.wrist_joint_height / 2.),
+                0.,
+            ),
+            gripper_tip: Vector3::new(
+                (d.wrist_joint_radius / 2.) + (d.gripper_length / 2.) - d.gripper_thickness,
+                -((d.wrist_joint_height / 2.) + d.gripper_thickness),
+                0.,
+            ),
+        }
+    }
+
+    /// Reads the offsets from a DH table. Every joint of the crane moves
+    /// about or along the vertical, so validated tables have no twist and
+    /// no joint angle offsets and only `a` (out from the previous axis)
+    /// and `d` (up along it) place a joint. The two conventions differ in
+    /// which row a step belongs to: a standard row steps out after its
+    /// own joint, a modified row before it.
+    fn from_table(table: &DhTable) -> Self {
+        let (swing, lift, elbow, wrist, tip) =
+            (table.swing, table.lift, table.elbow, table.wrist, table.tip);
+        // z-up (a, d) of the table to the y-up frames of the crane
+        let step = |a: f64, d: f64| Vector3::new(a, d, 0.);
+
+        match table.convention {
+            DhConvention::Standard => LinkOffsets {
+                column: step(0., swing.d),
+                elbow: step(swing.a + lift.a, lift.d),
+                wrist: step(elbow.a, elbow.d),
+                gripper_tip: step(wrist.a + tip.a, wrist.d + tip.d),
+            },
+            DhConvention::Modified => LinkOffsets {
+                column: step(swing.a, swing.d),
+                elbow: step(lift.a + elbow.a, lift.d),
+                wrist: step(wrist.a, elbow.d),
+                gripper_tip: step(tip.a, wrist.d + tip.d),
+            },
+        }
+    }
+
+    /// The lengths of the links the client draws between the joints, so
+    /// that they reach the joints wherever the offsets put them
+    pub fn link_lengths(&self, dimensions: &CraneDimensions) -> (f64, f64, f64) {
+        let d = dimensions;
+        let base_height = self.column.y;
+        let upper_arm_length =
+            self.elbow.x - (d.column_width / 2.) - (d.column_thickness / 2.) + d.elbow_joint_radius;
+        let lower_arm_length = self.wrist.x + d.wrist_joint_radius;
+        (base_height, upper_arm_length, lower_arm_length)
+    }
+}
+
+/// Where the crane places each of its joints in the frame of the joint
+/// before it
+pub(crate) fn joint_origins(dimensions: &CraneDimensions) -> [[f64; 3]; 5] {
+    let offsets = LinkOffsets::from(dimensions);
+    [
+        z_up(offsets.column),
+        [0., 0., 0.],
+        z_up(offsets.elbow),
+        z_up(offsets.wrist),
+        z_up(offsets.gripper_tip),
+    ]
+}
+
+/// The crane's frames are Y-up while URDF frames are Z-up
+pub(crate) fn z_up(v: Vector3) -> [f64; 3] {
+    // subtracting from zero rather than negating never gives -0
+    [v.x, 0. - v.z, v.y]
+}
+
+fn offset(v: Vector3) -> Transform {
+    Transform::translation(v.x, v.y, v.z)
+}
+
+/// Computes the world pose of every link frame of the crane
+pub fn forward_kinematics(dimensions: &CraneDimensions, state: &CraneState) -> CranePose {
+    let offsets = LinkOffsets::from(dimensions);
+
+    let base = Transform::identity();
+    let column = base
+        .then(&offset(offsets.column))
+        .then(&Transform::rotation_y(state.swing_rad));
+    let upper_arm = column.then(&Transform::translation(0., state.lift_m, 0.));
+    let elbow = upper_arm
+        .then(&offset(offsets.elbow))
+        .then(&Transform::rotation_y(state.elbow_rad));
+    let wrist = elbow
+        .then(&offset(offsets.wrist))
+        .then(&Transform::rotation_y(state.wrist_rad));
+    let gripper_tip = wrist.then(&offset(offsets.gripper_tip));
+
+    CranePose {
+        base: base.pose(),
+        column: column.pose(),
+        upper_arm: upper_arm.pose(),
+        elbow: elbow.pose(),
+        wrist: wrist.pose(),
+        gripper_tip: gripper_tip.pose(),
+    }
+}
+
+/// Where the gripper tip can reach, as distances from the swing axis and
+/// heights above the floor in meters
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReachEnvelope {
+    pub min_radius: f64,
+    pub max_radius: f64,
+    pub min_height: f64,
+    pub max_height: f64,
+}
+
+/// Computes the reach of the gripper tip by sweeping the elbow and wrist
+/// through their travel one degree at a time
+pub fn reach_envelope(dimensions: &CraneDimensions, limits: &CraneLimits) -> ReachEnvelope {
+    let travel = |joint: Joint| {
+        let range = limits.range(joint).unwrap_or(Range::new(-PI, PI));
+        let step = 1f64.to_radians();
+        let steps = ((range.max - range.min) / step).ceil() as usize;
+        (0..=steps).map(move |n| f64::min(range.min + n as f64 * step, range.max))
+    };
+
+    let mut state = limits.constrain(&CraneState::default());
+    let (mut min_radius, mut max_radius) = (f64::INFINITY, 0.);
+    for elbow in travel(Joint::Elbow) {
+        for wrist in travel(Joint::Wrist) {
+            state.elbow_rad = elbow;
+            state.wrist_rad = wrist;
+            let tip = forward_kinematics(dimensions, &state).gripper_tip.position;
+            let radius = (tip.x * tip.x + tip.z * tip.z).sqrt();
+            min_radius = f64::min(min_radius, radius);
+            max_radius = f64::max(max_radius, radius);
+        }
+    }
+
+    // the height of the tip depends on the lift alone
+    let height = |lift_m: f64| {
+        let state = CraneState {
+            lift_m,
+            ..state.clone()
+        };
+        forward_kinematics(dimensions, &state)
+            .gripper_tip
+            .position
+            .y
+    };
+
+    ReachEnvelope {
+        min_radius,
+        max_radius,
+        min_height: height(limits.lift.min),
+        max_height: height(limits.lift.max),
+    }
+}
+
+/// Which side of the line between the swing axis and the wrist the
+/// elbow sits on. For the horizontal arm of the crane elbow up is the
+/// solution with a positive elbow angle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ElbowConfiguration {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InverseSolution {
+    pub configuration: ElbowConfiguration,
+    pub state: CraneState,
+}
+
+/// Joint angles (in radians) and lift (in meters) before they have
+/// been checked against the crane limits
+#[derive(Debug, Clone, Copy)]
+struct JointSolution {
+    configuration: ElbowConfiguration,
+    swing: f64,
+    lift: f64,
+    elbow: f64,
+    wrist: f64,
+}
+
+/// Solves the lift/swing/elbow/wrist chain for a gripper tip target.
+///
+/// The arm is a planar RRR chain in the horizontal plane mounted on a
+/// prismatic lift, so the lift is resolved from the target height and
+/// the wrist pivot is found by stepping back from the target along the
+/// requested heading. The remaining two link problem has at most two
+/// solutions which are returned elbow up first. Only solutions within
+/// every joint limit are returned, with revolute joints wrapped by full
+/// turns to the equivalent angle nearest the current state.
+pub fn inverse_kinematics(
+    dimensions: &CraneDimensions,
+    limits: &CraneLimits,
+    current: &CraneState,
+    target: &Location,
+) -> Result<Vec<InverseSolution>, KinematicError> {
+    let candidates = match solve(dimensions, target, false) {
+        Some(candidates) => candidates,
+        None => {
+            return Err(KinematicError::Unreachable {
+                closest: closest_state(dimensions, limits, current, target),
+            })
+        }
+    };
+
+    if !fits(limits.lift, candidates[0].lift) {
+        return Err(KinematicError::Unreachable {
+            closest: closest_state(dimensions, limits, current, target),
+        });
+    }
+
+    let mut violated = Joint::Swing;
+    let mut solutions = Vec::new();
+    for candidate in candidates {
+        match within_limits(&candidate, limits, current) {
+            Ok(state) => solutions.push(InverseSolution {
+                configuration: candidate.configuration,
+                state,
+            }),
+            Err(joint) => violated = joint,
+        }
+    }
+
+    if solutions.is_empty() {
+        return Err(KinematicError::JointLimit { joint: violated });
+    }
+
+    Ok(solutions)
+}
+
+/// Picks the solution that requires the least joint motion from the current state
+pub fn nearest_solution(
+    solutions: Vec<InverseSolution>,
+    limits: &CraneLimits,
+    current: &CraneState,
+) -> Option<CraneState> {
+    let distance = |state: &CraneState| {
+        REVOLUTE_JOINTS
+            .iter()
+            .map(|joint| {
+                limits
+                    .travel(*joint, current.get(*joint), state.get(*joint))
+                    .abs()
+            })
+            .sum::<f64>()
+    };
+
+    solutions
+        .into_iter()
+        .map(|solution| solution.state)
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+}
+
+/// Returns the reachable state that brings the gripper tip as close as
+/// possible to the target, clamping the target into the workspace and
+/// every joint into its limits.
+pub fn closest_state(
+    dimensions: &CraneDimensions,
+    limits: &CraneLimits,
+    current: &CraneState,
+    target: &Location,
+) -> CraneState {
+    let candidates = solve(dimensions, target, true).unwrap_or_default();
+
+    candidates
+        .iter()
+        .map(|candidate| clamp_to_limits(candidate, limits, current))
+        .min_by(|a, b| {
+            let error = |state: &CraneState| {
+                let tip = forward_kinematics(dimensions, state).gripper_tip.position;
+                (tip - target_position(target)).length()
+            };
+            error(a).total_cmp(&error(b))
+        })
+        .unwrap_or_else(|| current.clone())
+}
+
+/// Spacing in meters of the points a straight line move is solved at
+const LINEAR_STEP: f64 = 0.01;
+
+/// Largest change of heading in radians between two points of a straight line move
+const LINEAR_TURN_STEP: f64 = 2f64.to_radians();
+
+/// Largest change of a revolute joint in radians between two points of a
+/// straight line move, anything beyond means the line passes through a
+/// singularity where the arm would have to flip around
+const LINEAR_MAX_JOINT_STEP: f64 = 30f64.to_radians();
+
+/// Solves a straight line move of the gripper tip from where it is in
+/// the current state to the target.
+///
+/// The line is sampled every centimeter, with the heading turning
+/// the short way round from the current heading to the target heading,
+/// and each point is solved starting from the solution of the previous
+/// one so the arm keeps its elbow configuration. The returned states
+/// follow the current state, ending with the target state. The move
+/// fails if any point along the line cannot be reached.
+pub fn linear_path(
+    dimensions: &CraneDimensions,
+    limits: &CraneLimits,
+    current: &CraneState,
+    target: &Location,
+) -> Result<Vec<CraneState>, KinematicError> {
+    // reject unreachable targets as such before walking the line to them
+    inverse_kinematics(dimensions, limits, current, target)?;
+
+    let tip = forward_kinematics(dimensions, current).gripper_tip;
+    let start = tip.position;
+    let end = target_position(target);
+    let start_heading = tip.yaw_rad;
+    let turn = wrap_angle(target_heading(target) - start_heading);
+
+    let steps = ((end - start).length() / LINEAR_STEP)
+        .max(turn.abs() / LINEAR_TURN_STEP)
+        .ceil()
+        .max(1.) as usize;
+
+    let mut path: Vec<CraneState> = Vec::with_capacity(steps);
+    for step in 1..=steps {
+        let fraction = step as f64 / steps as f64;
+        let point = start + (end - start) * fraction;
+        let location = Location {
+            x: point.x,
+            y: point.y,
+            z: point.z,
+            heading_rad: Some(start_heading + turn * fraction),
+        };
+
+        let previous = path.last().unwrap_or(current);
+        let interrupted = |reason: KinematicError| KinematicError::PathInterrupted {
+            at: location.clone(),
+            reason: Box::new(reason),
+        };
+        let state =
+            inverse_kinematics(dimensions, limits, previous, &location).map_err(interrupted)?;
+        let state = nearest_solution(state, limits, previous).unwrap_or_else(|| previous.clone());
+
+        let jump = REVOLUTE_JOINTS.into_iter().find(|joint| {
+            limits
+                .travel(*joint, previous.get(*joint), state.get(*joint))
+                .abs()
+                > LINEAR_MAX_JOINT_STEP
+        });
+        if let Some(joint) = jump {
+            return Err(interrupted(KinematicError::Singularity { joint }));
+        }
+
+        path.push(state);
+    }
+
+    Ok(path)
+}
+
+fn target_position(target: &Location) -> Vector3 {
+    Vector3::new(target.x, target.y, target.z)
+}
+
+/// Heading of the gripper in radians, facing away from the swing axis
+/// unless the target specifies one
+fn target_heading(target: &Location) -> f64 {
+    let (u, v) = (target.x, -target.z);
+    match target.heading_rad {
+        Some(heading) => heading,
+        None if u == 0. && v == 0. => 0.,
+        None => v.atan2(u),
+    }
+}
+
+/// Closed form solution of the chain. When `clamp` is set a target
+/// outside of the annulus swept by the arm is pulled onto its nearest
+/// edge instead of being rejected.
+fn solve(
+    dimensions: &CraneDimensions,
+    target: &Location,
+    clamp: bool,
+) -> Option<Vec<JointSolution>> {
+    let offsets = LinkOffsets::from(dimensions);
+    let a1 = offsets.elbow.x;
+    let a2 = offsets.wrist.x;
+    let a3 = offsets.gripper_tip.x;
+    let lift =
+        target.y - (offsets.column.y + offsets.elbow.y + offsets.wrist.y + offsets.gripper_tip.y);
+
+    // work in the horizontal plane with v = -z so that a rotation about
+    // the vertical axis is counter clockwise in (u, v)
+    let (u, v) = (target.x, -target.z);
+    let heading = target_heading(target);
+
+    let (mut wu, mut wv) = (u - a3 * heading.cos(), v - a3 * heading.sin());
+    let mut r = (wu.powi(2) + wv.powi(2)).sqrt();
+    let (r_min, r_max) = ((a1 - a2).abs(), a1 + a2);
+
+    if r < r_min || r > r_max {
+        if !clamp {
+            return None;
+        }
+        let clamped = r.clamp(r_min, r_max);
+        if r == 0. {
+            (wu, wv) = (clamped, 0.);
+        } else {
+            (wu, wv) = (wu * clamped / r, wv * clamped / r);
+        }
+        r = clamped;
+    }
+
+    let cos_elbow = ((r.powi(2) - a1.powi(2) - a2.powi(2)) / (2. * a1 * a2)).clamp(-1., 1.);
+    let elbow = cos_elbow.acos();
+
+    let solutions = [
+        (ElbowConfiguration::Up, elbow),
+        (ElbowConfiguration::Down, -elbow),
+    ]
+    .into_iter()
+    .map(|(configuration, elbow)| {
+        let swing = wv.atan2(wu) - (a2 * elbow.sin()).atan2(a1 + a2 * elbow.cos());
+        let wrist = heading - swing - elbow;
+        JointSolution {
+            configuration,
+            swing,
+            lift,
+            elbow,
+            wrist,
+        }
+    })
+    .collect();
+
+    Some(solutions)
+}
+
+impl JointSolution {
+    fn angle(&self, joint: Joint) -> f64 {
+        match joint {
+            Joint::Swing => self.swing,
+            Joint::Elbow => self.elbow,
+            Joint::Wrist => self.wrist,
+            Joint::Lift | Joint::Gripper => unreachable!("{joint} is not a revolute joint"),
+        }
+    }
+}
+
+const REVOLUTE_JOINTS: [Joint; 3] = [Joint::Swing, Joint::Elbow, Joint::Wrist];
+
+/// How far in radians or meters a solution may fall outside of a
+/// joint limit and still be taken to be on it, which absorbs the error
+/// of solving in floating point for targets on the edge of the workspace
+const LIMIT_TOLERANCE: f64 = 1e-6;
+
+/// Whether a solved joint position is within a range, give or take the
+/// tolerance
+fn fits(range: Range, value: f64) -> bool {
+    (range.min - LIMIT_TOLERANCE..=range.max + LIMIT_TOLERANCE).contains(&value)
+}
+
+/// Wraps a revolute joint angle by full turns to the equivalent angle
+/// within the joint limits that is closest to `reference`
+fn fit_angle(limits: &CraneLimits, joint: Joint, angle: f64, reference: f64) -> Option<f64> {
+    let range = match limits.range(joint) {
+        Some(range) => range,
+        None => return Some(limits.clamp(joint, angle)),
+    };
+
+    (-2..=2)
+        .map(|turns| angle + TAU * turns as f64)
+        .filter(|angle| fits(range, *angle))
+        .map(|angle| angle.clamp(range.min, range.max))
+        .min_by(|a, b| (a - reference).abs().total_cmp(&(b - reference).abs()))
+}
+
+fn within_limits(
+    solution: &JointSolution,
+    limits: &CraneLimits,
+    current: &CraneState,
+) -> Result<CraneState, Joint> {
+    let mut state = CraneState {
+        lift_m: limits.clamp(Joint::Lift, solution.lift),
+        ..current.clone()
+    };
+
+    for joint in REVOLUTE_JOINTS {
+        let angle =
+            fit_angle(limits, joint, solution.angle(joint), current.get(joint)).ok_or(joint)?;
+        state.set(joint, angle);
+    }
+
+    Ok(state)
+}
+
+fn clamp_to_limits(
+    solution: &JointSolution,
+    limits: &CraneLimits,
+    current: &CraneState,
+) -> CraneState {
+    let mut state = CraneState {
+        lift_m: limits.clamp(Joint::Lift, solution.lift),
+        ..current.clone()
+    };
+
+    for joint in REVOLUTE_JOINTS {
+        let angle = solution.angle(joint);
+        let angle = fit_angle(limits, joint, angle, current.get(joint)).unwrap_or_else(|| {
+            // no equivalent angle fits, settle on the nearest limit
+            let range = limits.range(joint).unwrap_or(Range::new(-PI, PI));
+            let center = (range.min + range.max) / 2.;
+            limits.clamp(joint, center + wrap_angle(angle - center))
+        });
+        state.set(joint, angle);
+    }
+
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::FRAC_PI_2;
+
+    use super::*;
+    use crate::robot::models::DhParameters;
+
+    const EPSILON: f64 = 1e-9;
+
+    fn location(x: f64, y: f64, z: f64, heading_rad: Option<f64>) -> Location {
+        Location {
+            x,
+            y,
+            z,
+            heading_rad,
+        }
+    }
+
+    fn tip(dimensions: &CraneDimensions, state: &CraneState) -> Pose {
+        forward_kinematics(dimensions, state).gripper_tip
+    }
+
+    /// A crane whose upper and lower arms are as long as each other, so
+    /// that folding the elbow brings the wrist onto the swing axis
+    fn even_arms() -> CraneDimensions {
+        let row = |a: f64, d: f64| DhParameters {
+            a,
+            d,
+            ..Default::default()
+        };
+        CraneDimensions {
+            kinematics: Some(DhTable {
+                convention: DhConvention::Standard,
+                swing: row(0., 0.1),
+                lift: row(0.5, 0.),
+                elbow: row(0.5, 0.),
+                wrist: row(0.1, 0.),
+                tip: row(0., 0.),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn inverse_kinematics_solutions_reach_the_target() {
+        let dimensions = CraneDimensions::default();
+        let limits = CraneLimits::default();
+        let current = CraneState::default();
+        let targets = [
+            location(0.8, 0.9, 0.3, Some(0.5)),
+            location(-0.4, 1.2, -0.6, Some(-2.)),
+            location(0.2, 0.5, 0.9, None),
+            location(1., 1.5, 0., Some(0.)),
+        ];
+
+        for target in targets {
+            let solutions = inverse_kinematics(&dimensions, &limits, &current, &target).unwrap();
+            assert_eq!(solutions.len(), 2);
+            for solution in solutions {
+                let tip = tip(&dimensions, &solution.state);
+                let error = (tip.position - target_position(&target)).length();
+                assert!(error < EPSILON, "{target:?} is missed by {error} m");
+                let turn = wrap_angle(tip.yaw_rad - target_heading(&target));
+                assert!(turn.abs() < EPSILON, "{target:?} is faced {turn} rad off");
+            }
+        }
+    }
+
+    #[test]
+    fn forward_kinematics_of_a_solution_solves_back_to_it() {
+        let dimensions = CraneDimensions::default();
+        let limits = CraneLimits::default();
+        let state = CraneState {
+            swing_rad: 0.7,
+            lift_m: 1.1,
+            elbow_rad: 1.2,
+            wrist_rad: -0.4,
+            gripper_m: 0.1,
+        };
+        let tip = tip(&dimensions, &state);
+        let target = location(
+            tip.position.x,
+            tip.position.y,
+            tip.position.z,
+            Some(tip.yaw_rad),
+        );
+
+        let solutions = inverse_kinematics(&dimensions, &limits, &state, &target).unwrap();
+        let solved = nearest_solution(solutions, &limits, &state).unwrap();
+        for joint in Joint::ALL {
+            assert!((solved.get(joint) - state.get(joint)).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn targets_out_of_reach_are_unreachable() {
+        let dimensions = CraneDimensions::default();
+        let limits = CraneLimits::default();
+        let current = CraneState::default();
+        let targets = [
+            // further out than the stretched arm
+            location(5., 1., 0., None),
+            // closer to the column than the folded arm
+            location(0.05, 1., 0., Some(0.)),
+            // above the top of the lift
+            location(0.8, 3., 0., None),
+        ];
+
+        for target in targets {
+            match inverse_kinematics(&dimensions, &limits, &current, &target) {
+                Err(KinematicError::Unreachable { closest }) => {
+                    assert_eq!(limits.constrain(&closest), closest)
+                }
+                other => panic!("{target:?} gave {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn targets_beyond_a_joint_limit_name_the_joint() {
+        let dimensions = CraneDimensions::default();
+        let limits = CraneLimits {
+            elbow: Some(Range::new(-0.2, 0.2)),
+            ..Default::default()
+        };
+        let current = CraneState::default();
+        // reachable, but only with the elbow bent well past 0.2 rad
+        let target = location(0.6, 1., 0.3, None);
+
+        assert!(matches!(
+            inverse_kinematics(&dimensions, &limits, &current, &target),
+            Err(KinematicError::JointLimit {
+                joint: Joint::Elbow
+            })
+        ));
+    }
+
+    #[test]
+    fn nearest_solution_keeps_the_elbow_on_its_side() {
+        let dimensions = CraneDimensions::default();
+        let limits = CraneLimits::default();
+        let target = location(0.7, 1., 0.4, None);
+
+        for elbow_rad in [FRAC_PI_2, -FRAC_PI_2] {
+            let current = CraneState {
+                elbow_rad,
+                ..Default::default()
+            };
+            let solutions = inverse_kinematics(&dimensions, &limits, &current, &target).unwrap();
+            let nearest = nearest_solution(solutions, &limits, &current).unwrap();
+            assert_eq!(nearest.elbow_rad.signum(), elbow_rad.signum());
+        }
+    }
+
+    #[test]
+    fn linear_path_follows_a_straight_line_to_the_target() {
+        let dimensions = CraneDimensions::default();
+        let limits = CraneLimits::default();
+        let start = location(0.9, 0.8, 0.2, Some(0.2));
+        let end = location(0.6, 1.1, -0.5, Some(-0.4));
+        let solutions =
+            inverse_kinematics(&dimensions, &limits, &CraneState::default(), &start).unwrap();
+        let current = nearest_solution(solutions, &limits, &CraneState::default()).unwrap();
+
+        let path = linear_path(&dimensions, &limits, &current, &end).unwrap();
+
+        let (from, to) = (target_position(&start), target_position(&end));
+        let direction = (to - from) * (1. / (to - from).length());
+        for state in &path {
+            let offset = tip(&dimensions, state).position - from;
+            let along = offset.x * direction.x + offset.y * direction.y + offset.z * direction.z;
+            let off_line = (offset - direction * along).length();
+            assert!(
+                off_line < EPSILON,
+                "the tip strays {off_line} m off the line"
+            );
+        }
+        let last = tip(&dimensions, path.last().unwrap());
+        assert!((last.position - to).length() < EPSILON);
+        assert!(wrap_angle(last.yaw_rad - target_heading(&end)).abs() < EPSILON);
+    }
+
+    #[test]
+    fn linear_path_through_the_swing_axis_is_a_singularity() {
+        let dimensions = even_arms();
+        let limits = CraneLimits::default();
+        // facing along x, so the wrist crosses just beside the swing axis
+        let start = location(0.3, 1., -0.01, Some(0.));
+        let end = location(-0.1, 1., -0.01, Some(0.));
+        let solutions =
+            inverse_kinematics(&dimensions, &limits, &CraneState::default(), &start).unwrap();
+        let current = nearest_solution(solutions, &limits, &CraneState::default()).unwrap();
+
+        match linear_path(&dimensions, &limits, &current, &end) {
+            Err(KinematicError::PathInterrupted { reason, .. }) => {
+                assert!(matches!(*reason, KinematicError::Singularity { .. }))
+            }
+            other => panic!("the path gave {other:?}"),
+        }
+    }
+
+    #[test]
+    fn linear_path_to_an_unreachable_target_is_rejected_up_front() {
+        let dimensions = CraneDimensions::default();
+        let limits = CraneLimits::default();
+        let target = location(5., 1., 0., None);
+
+        assert!(matches!(
+            linear_path(&dimensions, &limits, &CraneState::default(), &target),
+            Err(KinematicError::Unreachable { .. })
+        ));
+    }
+
+    /// The default crane given by a table in the convention instead of
+    /// by the lengths of its links
+    fn stock_table(convention: DhConvention) -> CraneDimensions {
+        let row = |a: f64, d: f64| DhParameters {
+            a,
+            d,
+            ..Default::default()
+        };
+        let table = match convention {
+            DhConvention::Standard => DhTable {
+                convention,
+                swing: row(0., 0.1),
+                lift: row(0.785, -0.02),
+                elbow: row(0.43, -0.06),
+                wrist: row(0.085, -0.06),
+                tip: row(0., 0.),
+            },
+            DhConvention::Modified => DhTable {
+                convention,
+                swing: row(0., 0.1),
+                lift: row(0., -0.02),
+                elbow: row(0.785, -0.06),
+                wrist: row(0.43, -0.06),
+                tip: row(0.085, 0.),
+            },
+        };
+        CraneDimensions {
+            kinematics: Some(table),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn tables_place_the_joints_where_the_link_lengths_do() {
+        let expected = joint_origins(&CraneDimensions::default());
+        for convention in [DhConvention::Standard, DhConvention::Modified] {
+            let origins = joint_origins(&stock_table(convention));
+            for (origin, expected) in origins.iter().zip(&expected) {
+                for (a, b) in origin.iter().zip(expected) {
+                    assert!(
+                        (a - b).abs() < EPSILON,
+                        "{convention:?}: {origin:?} != {expected:?}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -1,15 +1,23 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use actix::{Message, MessageResponse, Recipient};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use super::{crane, models::{CraneDimensions, CraneState}, user};
+use super::{
+    chain::{Frame, JointPositions, KinematicChain},
+    core::ID,
+    kinematics::CranePose,
+    models::{CraneDimensions, CraneLimits, CraneState, Joint, JointMotion, Motion, MotionLimits},
+    user,
+};
 
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct Connect {
     pub user: user::ID,
+    pub session: user::SessionId,
+    pub role: user::Role,
     pub addr: Recipient<Operation>,
 }
 
@@ -17,8 +25,38 @@ pub struct Connect {
 #[rtype(result = "()")]
 pub struct Disconnect {
     pub user: user::ID,
+    pub session: user::SessionId,
 }
 
+/// Sent by a user whenever its client proves to be alive, keeping any
+/// control lease it holds from expiring
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Heartbeat {
+    pub user: user::ID,
+}
+
+/// Replaces the dimensions and limits of a running robot with those of
+/// its updated config
+pub struct Reconfigure<R> {
+    pub config: R,
+}
+
+impl<R> Message for Reconfigure<R> {
+    type Result = ();
+}
+
+/// Stops a robot whose config was removed, disconnecting its users
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Retire;
+
+/// Asks a robot to write its state to the store, as the server is about
+/// to shut down
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Persist;
+
 #[derive(PartialEq, Eq, Hash, Debug, Clone, Serialize, Deserialize)]
 pub enum Command {
     LiftUp,
@@ -33,27 +71,290 @@ pub enum Command {
     GripperClose,
 }
 
-#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+impl Command {
+    /// The joint a jog command drives and the direction it moves in
+    pub fn jog(&self) -> (Joint, i64) {
+        match self {
+            Command::LiftUp => (Joint::Lift, 1),
+            Command::LiftDown => (Joint::Lift, -1),
+            Command::SwingLeft => (Joint::Swing, -1),
+            Command::SwingRight => (Joint::Swing, 1),
+            Command::ElbowLeft => (Joint::Elbow, 1),
+            Command::ElbowRight => (Joint::Elbow, -1),
+            Command::WristLeft => (Joint::Wrist, 1),
+            Command::WristRight => (Joint::Wrist, -1),
+            Command::GripperOpen => (Joint::Gripper, 1),
+            Command::GripperClose => (Joint::Gripper, -1),
+        }
+    }
+}
+
+/// Target position of the gripper tip in world meters, with an optional
+/// heading of the gripper about the vertical axis in radians.
+/// When no heading is provided the gripper points away from the column.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Location {
-    pub x: i64,
-    pub y: i64,
-    pub z: i64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heading_rad: Option<f64>,
+}
+
+/// A target along a path submitted with `MovePath`
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Waypoint {
+    /// a joint state, reached by interpolating the joints
+    Joint { payload: CraneState },
+    /// a gripper tip location, reached by interpolating the joints
+    Location { payload: Location },
+    /// a gripper tip location, reached along a straight line
+    Linear { payload: Location },
 }
 
 #[derive(Clone, Debug, thiserror::Error)]
 pub enum KinematicError {
-    // #[error("the provided location is not reachable")]
-    // Unreachable
+    #[error("the provided location is not reachable")]
+    Unreachable { closest: CraneState },
+
+    #[error("the provided location requires the {joint} joint to move beyond its limits")]
+    JointLimit { joint: Joint },
+
+    #[error("the path passes through a singularity where the {joint} joint would have to flip")]
+    Singularity { joint: Joint },
+
+    #[error("waypoint {index} of the path cannot be reached: {reason}")]
+    InvalidWaypoint {
+        index: usize,
+        reason: Box<KinematicError>,
+    },
+
+    #[error("the path is interrupted at ({}, {}, {}): {reason}", at.x, at.y, at.z)]
+    PathInterrupted {
+        at: Location,
+        reason: Box<KinematicError>,
+    },
+}
+
+impl KinematicError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            KinematicError::Unreachable { .. } => ErrorCode::Unreachable,
+            KinematicError::JointLimit { .. } => ErrorCode::JointLimit,
+            KinematicError::Singularity { .. } => ErrorCode::Unreachable,
+            KinematicError::PathInterrupted { reason, .. }
+            | KinematicError::InvalidWaypoint { reason, .. } => reason.code(),
+        }
+    }
+}
+
+/// Identifier chosen by a client to correlate its actions with the
+/// acknowledgements and rejections sent back by the server
+pub type RequestId = String;
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorCode {
+    InvalidAction,
+    Unavailable,
+    Unsupported,
+    Unreachable,
+    JointLimit,
+    EmergencyStop,
+    /// the action requires holding control of the robot
+    NotInControl,
+    /// another user holds control of the robot
+    ControlHeld,
+    /// the user's role does not allow the action
+    Forbidden,
+    /// the robot's configuration could not be loaded
+    InvalidConfig,
 }
 
 #[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rejection {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<RequestId>,
+    pub code: ErrorCode,
+    pub reason: String,
+}
+
+/// Everything a client needs to render a chain robot, sent to users as
+/// they join
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainSnapshot {
+    pub robot: ChainInfo,
+    pub users: Vec<user::ID>,
+    pub motion: Option<JointMotion>,
+    pub emergency_stop: bool,
+    pub controller: Option<user::ID>,
+}
+
+/// Everything a client needs to render a crane, sent to users as they join
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub robot: RobotCraneInfo,
+    pub limits: CraneLimits,
+    pub motion_limits: MotionLimits,
+    pub users: Vec<user::ID>,
+    pub motion: Option<Motion>,
+    pub emergency_stop: bool,
+    /// whether motions restored from the state store wait to be resumed
+    pub paused: bool,
+    pub controller: Option<user::ID>,
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "camelCase")]
 pub enum Action {
-    Join { payload: user::ID },
-    Leave { payload: user::ID },
-    Move { payload: Location },
-    Command { payload: HashSet<Command> },
-    Update { payload: CraneState }
+    Join {
+        payload: user::ID,
+    },
+    Leave {
+        payload: user::ID,
+    },
+    Move {
+        payload: Location,
+        #[serde(default)]
+        clamp: bool,
+    },
+    /// move the gripper tip along a straight line to the location
+    MoveLinear {
+        payload: Location,
+    },
+    /// queue a path to be followed once the motions queued before it are
+    /// complete, with the blend radius in meters
+    MovePath {
+        payload: Vec<Waypoint>,
+        #[serde(default, rename = "blendRadius")]
+        blend_radius: f64,
+    },
+    /// a path has passed the waypoint at `index`
+    WaypointReached {
+        index: usize,
+    },
+    /// a path has reached its last waypoint
+    PathComplete,
+    Command {
+        payload: HashSet<Command>,
+    },
+    /// jog joints by name, each in the direction of the sign of its
+    /// value, or stop jogging it with zero
+    Jog {
+        payload: HashMap<String, i64>,
+    },
+    /// move the named joints to the given positions, in radians or
+    /// meters, the other joints stay where they are
+    MoveJoints {
+        payload: HashMap<String, f64>,
+    },
+    Update {
+        payload: CraneState,
+    },
+    /// the joint positions of a chain robot changed
+    JointUpdate {
+        payload: JointPositions,
+    },
+    Ack {
+        payload: RequestId,
+    },
+    Rejected {
+        payload: Rejection,
+    },
+    Snapshot {
+        payload: Box<Snapshot>,
+    },
+    ChainSnapshot {
+        payload: Box<ChainSnapshot>,
+    },
+    Motion {
+        payload: Motion,
+    },
+    /// a chain robot started a move
+    JointMotion {
+        payload: JointMotion,
+    },
+    /// decelerate the current motion to a halt
+    Stop,
+    /// halt immediately and refuse to move until reset
+    EmergencyStop,
+    Reset,
+    /// start the motions restored from the state store, which wait for
+    /// the user in control to resume them
+    Resume,
+    /// take control of the robot, only the user in control may move it
+    RequestControl,
+    ReleaseControl,
+    /// the user in control of the robot changed
+    ControlChanged {
+        payload: Option<user::ID>,
+    },
+    /// disconnect a user from the robot
+    Kick {
+        payload: user::ID,
+    },
+    /// reload the robot's dimensions and limits from its config file
+    ReloadConfig,
+    /// the robot was reconfigured, with everything a client needs to
+    /// render it again
+    ConfigChanged {
+        payload: Box<Snapshot>,
+    },
+    /// a chain robot was reconfigured
+    ChainConfigChanged {
+        payload: Box<ChainSnapshot>,
+    },
+}
+
+impl Action {
+    /// The least privileged role allowed to issue the action. Actions
+    /// only ever sent by the server require the most privileged role.
+    pub fn required_role(&self) -> user::Role {
+        match self {
+            Action::Command { .. }
+            | Action::Jog { .. }
+            | Action::MoveJoints { .. }
+            | Action::Move { .. }
+            | Action::MoveLinear { .. }
+            | Action::MovePath { .. }
+            | Action::Resume
+            | Action::Stop
+            | Action::RequestControl
+            | Action::ReleaseControl => user::Role::Operator,
+            _ => user::Role::Admin,
+        }
+    }
+
+    /// Whether the action moves the robot, which takes holding control
+    /// of it
+    pub fn is_motion(&self) -> bool {
+        matches!(
+            self,
+            Action::Command { .. }
+                | Action::Jog { .. }
+                | Action::MoveJoints { .. }
+                | Action::Move { .. }
+                | Action::MoveLinear { .. }
+                | Action::MovePath { .. }
+                | Action::Resume
+        )
+    }
+}
+
+/// An action as sent by a client over the websocket, optionally tagged
+/// with a request id to receive an `Ack` or `Rejected` in response
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Request {
+    #[serde(default)]
+    pub request_id: Option<RequestId>,
+    #[serde(flatten)]
+    pub action: Action,
 }
 
 #[derive(Message, Serialize, Deserialize, Clone, Debug)]
@@ -62,6 +363,8 @@ pub struct Operation {
     pub user_id: user::ID,
     pub action: Action,
     pub created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<RequestId>,
 }
 
 impl Operation {
@@ -70,18 +373,52 @@ impl Operation {
             user_id,
             action,
             created_at: Utc::now(),
+            request_id: None,
         }
     }
+
+    pub fn with_request_id(mut self, request_id: Option<RequestId>) -> Self {
+        self.request_id = request_id;
+        self
+    }
+
+    pub fn rejected(
+        user_id: user::ID,
+        request_id: Option<RequestId>,
+        code: ErrorCode,
+        reason: impl Into<String>,
+    ) -> Self {
+        let rejection = Rejection {
+            request_id: request_id.clone(),
+            code,
+            reason: reason.into(),
+        };
+        Self::new(user_id, Action::Rejected { payload: rejection }).with_request_id(request_id)
+    }
 }
 
-#[derive(MessageResponse, Serialize)]
+#[derive(MessageResponse, PartialEq, Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RobotCraneInfo {
-    pub id: crane::ID,
+    pub id: ID,
     pub state: CraneState,
     pub dimensions: CraneDimensions,
+    pub pose: CranePose,
 }
 
 #[derive(Message)]
 #[rtype(result = "RobotCraneInfo")]
 pub struct RobotCraneInfoRequest;
+
+#[derive(MessageResponse, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainInfo {
+    pub id: ID,
+    pub chain: KinematicChain,
+    pub state: JointPositions,
+    pub frames: Vec<Frame>,
+}
+
+#[derive(Message)]
+#[rtype(result = "ChainInfo")]
+pub struct ChainInfoRequest;
@@ -1,11 +1,24 @@
-
+pub mod chain;
+pub mod chain_robot;
 pub mod crane;
+pub mod kinematics;
 pub mod models;
+pub mod path;
+pub mod trajectory;
+
+mod core;
+pub use self::core::ID;
+
+mod kind;
+pub use self::kind::{Robot, RobotAddr, RobotKind};
+
+mod session;
 
 mod message;
+pub use self::message::Action;
 
 mod user;
-pub use self::user::User;
+pub use self::user::{Role, User};
 
 mod registry;
-pub use self::registry::Registry;
\ No newline at end of file
+pub use self::registry::{DefinitionError, Registry, SpawnError};
@@ -1,30 +1,115 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use super::crane::ID;
+use super::{
+    chain::{Frame, JointPositions, KinematicChain},
+    core::ID,
+    kinematics::{self, CranePose},
+    trajectory::ProfileKind,
+    user,
+};
+
+/// How long a single jog command drives its joint at its maximum velocity
+/// when the joint does not set its own jog increment. Clients repeat the
+/// command for as long as a control is held, so the joint keeps moving
+/// smoothly until it is let go.
+pub const JOG_HOLD: Duration = Duration::from_millis(150);
 
-#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+/// Position of every joint of a crane, in radians for revolute joints and
+/// meters for prismatic ones as the field names say
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CraneState {
-    pub swing_deg: i64,
-    pub lift_mm: i64,
-    pub elbow_deg: i64,
-    pub wrist_deg: i64,
-    pub gripper_mm: i64,
+    pub swing_rad: f64,
+    pub lift_m: f64,
+    pub elbow_rad: f64,
+    pub wrist_rad: f64,
+    pub gripper_m: f64,
+}
+
+impl CraneState {
+    pub fn get(&self, joint: Joint) -> f64 {
+        match joint {
+            Joint::Swing => self.swing_rad,
+            Joint::Lift => self.lift_m,
+            Joint::Elbow => self.elbow_rad,
+            Joint::Wrist => self.wrist_rad,
+            Joint::Gripper => self.gripper_m,
+        }
+    }
+
+    pub fn set(&mut self, joint: Joint, value: f64) {
+        match joint {
+            Joint::Swing => self.swing_rad = value,
+            Joint::Lift => self.lift_m = value,
+            Joint::Elbow => self.elbow_rad = value,
+            Joint::Wrist => self.wrist_rad = value,
+            Joint::Gripper => self.gripper_m = value,
+        }
+    }
 }
 
 impl Default for CraneState {
     fn default() -> Self {
         Self {
-            swing_deg: 0,
-            lift_mm: 100,
-            elbow_deg: 0,
-            wrist_deg: 0,
-            gripper_mm: 200,
+            swing_rad: 0.,
+            lift_m: 0.1,
+            elbow_rad: 0.,
+            wrist_rad: 0.,
+            gripper_m: 0.2,
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Joint {
+    Swing,
+    Lift,
+    Elbow,
+    Wrist,
+    Gripper,
+}
+
+impl Joint {
+    pub const ALL: [Joint; 5] = [
+        Joint::Swing,
+        Joint::Lift,
+        Joint::Elbow,
+        Joint::Wrist,
+        Joint::Gripper,
+    ];
+
+    pub fn is_revolute(&self) -> bool {
+        matches!(self, Joint::Swing | Joint::Elbow | Joint::Wrist)
+    }
+
+    /// The joint with the given name, as used in configs and jog commands
+    pub fn from_name(name: &str) -> Option<Joint> {
+        Joint::ALL
+            .into_iter()
+            .find(|joint| joint.to_string() == name)
+    }
+}
+
+impl fmt::Display for Joint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Joint::Swing => "swing",
+            Joint::Lift => "lift",
+            Joint::Elbow => "elbow",
+            Joint::Wrist => "wrist",
+            Joint::Gripper => "gripper",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct CraneDimensions {
     // Base dimensions
@@ -63,8 +148,11 @@ pub struct CraneDimensions {
     pub gripper_width: f64,     // Width of the gripper
     pub gripper_thickness: f64, // Thickness of the gripper
 
-    pub gripper_max_open: f64,       // Maximum opening of the gripper
+    pub gripper_max_open: f64, // Maximum opening of the gripper
     // pub wrist_extension_length: f64, // Length of the fixed wrist extension
+    /// Where the joints sit when they are not implied by the sizes above
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kinematics: Option<DhTable>,
 }
 
 impl Default for CraneDimensions {
@@ -108,55 +196,336 @@ impl Default for CraneDimensions {
 
             gripper_max_open: 0.2,
             // wrist_extension_length: 0.2,
+            kinematics: None,
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// The convention a Denavit–Hartenberg table is written in
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum DhConvention {
+    /// each row turns about and slides along the z axis of the joint
+    /// before it, then steps out along its own x axis
+    #[default]
+    #[serde(rename = "dh")]
+    Standard,
+    /// each row steps out along the x axis of the joint before it, then
+    /// turns about and slides along its own z axis (Craig's convention)
+    #[serde(rename = "mdh")]
+    Modified,
+}
+
+/// One row of a Denavit–Hartenberg table, lengths in meters and angles
+/// in degrees as configs give them, in radians once loaded
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct DhParameters {
+    #[serde(default)]
+    pub a: f64,
+    #[serde(default)]
+    pub alpha: f64,
+    #[serde(default)]
+    pub d: f64,
+    #[serde(default)]
+    pub theta: f64,
+}
+
+/// Explicit kinematic origins of a crane, z-up with one row per joint
+/// from the base outwards. The gripper does not move the tip so it has
+/// no row, `tip` places the gripper tip relative to the wrist instead.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct DhTable {
+    #[serde(default)]
+    pub convention: DhConvention,
+    pub swing: DhParameters,
+    pub lift: DhParameters,
+    pub elbow: DhParameters,
+    pub wrist: DhParameters,
+    #[serde(default)]
+    pub tip: DhParameters,
+}
+
+impl DhParameters {
+    /// The row with its angles given in degrees converted to radians
+    pub fn to_radians(self) -> Self {
+        Self {
+            alpha: self.alpha.to_radians(),
+            theta: self.theta.to_radians(),
+            ..self
+        }
+    }
+}
+
+impl DhTable {
+    /// The table with its angles given in degrees converted to radians
+    pub fn to_radians(&self) -> Self {
+        Self {
+            convention: self.convention,
+            swing: self.swing.to_radians(),
+            lift: self.lift.to_radians(),
+            elbow: self.elbow.to_radians(),
+            wrist: self.wrist.to_radians(),
+            tip: self.tip.to_radians(),
+        }
+    }
+
+    /// Every row, named by the joint it belongs to
+    pub fn rows(&self) -> [(&'static str, &DhParameters); 5] {
+        [
+            ("swing", &self.swing),
+            ("lift", &self.lift),
+            ("elbow", &self.elbow),
+            ("wrist", &self.wrist),
+            ("tip", &self.tip),
+        ]
+    }
+}
+
+/// Inclusive range a joint may travel within, in radians for revolute
+/// joints and meters for prismatic ones
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Range {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Range {
+    pub const fn new(min: f64, max: f64) -> Self {
+        Self { min, max }
+    }
+
+    pub fn contains(&self, value: f64) -> bool {
+        (self.min..=self.max).contains(&value)
+    }
+}
+
+/// Travel limits of every joint. Revolute joints without a range are
+/// continuous and may turn indefinitely, their position is kept within
+/// `[-π, π)` radians. Prismatic joints are always bounded.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct CraneLimits {
-    pub swing_min: i64,
-    pub swing_max: i64,
-    pub lift_min: i64,
-    pub lift_max: i64,
-    pub elbow_min: i64,
-    pub elbow_max: i64,
-    pub wrist_min: i64,
-    pub wrist_max: i64,
-    pub gripper_min: i64,
-    pub gripper_max: i64,
+    pub swing: Option<Range>,
+    pub lift: Range,
+    pub elbow: Option<Range>,
+    pub wrist: Option<Range>,
+    pub gripper: Range,
+}
+
+impl CraneLimits {
+    pub fn range(&self, joint: Joint) -> Option<Range> {
+        match joint {
+            Joint::Swing => self.swing,
+            Joint::Lift => Some(self.lift),
+            Joint::Elbow => self.elbow,
+            Joint::Wrist => self.wrist,
+            Joint::Gripper => Some(self.gripper),
+        }
+    }
+
+    pub fn is_continuous(&self, joint: Joint) -> bool {
+        self.range(joint).is_none()
+    }
+
+    /// Brings a joint position within limits, wrapping continuous joints
+    /// and clamping bounded ones
+    pub fn clamp(&self, joint: Joint, value: f64) -> f64 {
+        match self.range(joint) {
+            Some(range) => value.clamp(range.min, range.max),
+            None => wrap_angle(value),
+        }
+    }
+
+    /// Moves a joint by `delta`, stopping at the end of its travel
+    pub fn jog(&self, joint: Joint, value: f64, delta: f64) -> f64 {
+        self.clamp(joint, value + delta)
+    }
+
+    /// Signed distance a joint has to travel between two positions,
+    /// taking the shorter way around for continuous joints
+    pub fn travel(&self, joint: Joint, from: f64, to: f64) -> f64 {
+        match self.range(joint) {
+            Some(_) => to - from,
+            None => wrap_angle(to - from),
+        }
+    }
+
+    /// Brings every joint of a state within limits
+    pub fn constrain(&self, state: &CraneState) -> CraneState {
+        let mut constrained = state.clone();
+        for joint in Joint::ALL {
+            constrained.set(joint, self.clamp(joint, state.get(joint)));
+        }
+        constrained
+    }
+}
+
+/// Wraps an angle in radians into `[-π, π)`, leaving angles already
+/// within it untouched
+pub fn wrap_angle(value: f64) -> f64 {
+    if (-PI..PI).contains(&value) {
+        return value;
+    }
+    (value + PI).rem_euclid(2. * PI) - PI
 }
 
 impl Default for CraneLimits {
     fn default() -> Self {
         Self {
-            swing_min: -180,
-            swing_max: 180,
-            lift_min: 200,
-            lift_max: 1700,
-            elbow_min: -180,
-            elbow_max: 180,
-            wrist_min: -180,
-            wrist_max: 180,
-            gripper_min: 0,
-            gripper_max: 200,
+            swing: Some(Range::new(-PI, PI)),
+            lift: Range::new(0.2, 1.7),
+            elbow: Some(Range::new(-PI, PI)),
+            wrist: Some(Range::new(-PI, PI)),
+            gripper: Range::new(0., 0.2),
+        }
+    }
+}
+
+/// Dynamic limits of a single joint, in radians or meters per second, per
+/// second squared and per second cubed
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JointDynamics {
+    pub max_velocity: f64,
+    pub max_acceleration: f64,
+    pub max_jerk: f64,
+    /// how far a single jog command moves the joint, in radians or meters
+    pub jog_increment: f64,
+}
+
+impl JointDynamics {
+    /// Dynamic limits with the jog increment the joint covers at its
+    /// maximum velocity while a jog command holds
+    pub const fn new(max_velocity: f64, max_acceleration: f64, max_jerk: f64) -> Self {
+        Self {
+            max_velocity,
+            max_acceleration,
+            max_jerk,
+            jog_increment: max_velocity * JOG_HOLD.as_secs_f64(),
+        }
+    }
+
+    pub const fn with_jog_increment(mut self, jog_increment: f64) -> Self {
+        self.jog_increment = jog_increment;
+        self
+    }
+
+    /// Converts limits given in degrees, as revolute joints are
+    /// configured, to radians
+    pub const fn to_radians(self) -> Self {
+        Self {
+            max_velocity: self.max_velocity.to_radians(),
+            max_acceleration: self.max_acceleration.to_radians(),
+            max_jerk: self.max_jerk.to_radians(),
+            jog_increment: self.jog_increment.to_radians(),
         }
     }
 }
 
+/// Velocity profile and per joint dynamic limits used to plan moves
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MotionLimits {
+    pub profile: ProfileKind,
+    pub swing: JointDynamics,
+    pub lift: JointDynamics,
+    pub elbow: JointDynamics,
+    pub wrist: JointDynamics,
+    pub gripper: JointDynamics,
+}
+
+impl MotionLimits {
+    pub fn joint(&self, joint: Joint) -> JointDynamics {
+        match joint {
+            Joint::Swing => self.swing,
+            Joint::Lift => self.lift,
+            Joint::Elbow => self.elbow,
+            Joint::Wrist => self.wrist,
+            Joint::Gripper => self.gripper,
+        }
+    }
+}
+
+impl Default for MotionLimits {
+    fn default() -> Self {
+        Self {
+            profile: ProfileKind::default(),
+            swing: JointDynamics::new(45., 90., 360.).to_radians(),
+            lift: JointDynamics::new(0.25, 0.5, 2.),
+            elbow: JointDynamics::new(60., 120., 480.).to_radians(),
+            wrist: JointDynamics::new(90., 180., 720.).to_radians(),
+            gripper: JointDynamics::new(0.1, 0.2, 0.8),
+        }
+    }
+}
+
+/// A move the crane is currently executing
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Motion {
+    pub user_id: user::ID,
+    pub target: CraneState,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+}
+
+/// A move a chain robot is currently executing, with the target
+/// positions of its joints by name
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JointMotion {
+    pub user_id: user::ID,
+    pub target: JointPositions,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CraneDetails {
     pub id: ID,
     pub state: CraneState,
     pub dimensions: CraneDimensions,
+    pub pose: CranePose,
 }
 
 impl Default for CraneDetails {
     fn default() -> Self {
+        let state = CraneState::default();
+        let dimensions = CraneDimensions::default();
+        let pose = kinematics::forward_kinematics(&dimensions, &state);
         Self {
             id: String::from("robotix.v1"),
-            state: Default::default(),
-            dimensions: Default::default(),
+            state,
+            dimensions,
+            pose,
         }
     }
 }
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChainDetails {
+    pub id: ID,
+    /// always `chain`, cranes are told apart by their lack of a kind
+    pub kind: String,
+    pub chain: KinematicChain,
+    pub state: JointPositions,
+    pub frames: Vec<Frame>,
+}
+
+/// The details of a robot of any kind. Cranes keep the shape their
+/// details have always had.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RobotDetails {
+    Crane(Box<CraneDetails>),
+    Chain(ChainDetails),
+}
+
+/// Where the links of a robot of any kind are, the frame of every joint
+/// for chain robots
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RobotPose {
+    Crane(Box<CranePose>),
+    Chain(Vec<Frame>),
+}
@@ -0,0 +1,208 @@
+//! # path
+//!
+//! resolves the waypoints of a path into the joint states the crane
+//! passes through. Without blending the crane comes to rest at every
+//! waypoint, with blending the whole path is followed as one motion that
+//! rounds off the corners at the waypoints it passes.
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    kinematics::{self, Vector3},
+    message::{KinematicError, Waypoint},
+    models::{CraneDimensions, CraneLimits, CraneState, Joint},
+};
+
+/// Largest change of a revolute joint in radians between two states of
+/// a blended joint space move
+const REVOLUTE_STEP: f64 = 2f64.to_radians();
+
+/// Largest change of a prismatic joint in meters between two states of a
+/// blended joint space move
+const PRISMATIC_STEP: f64 = 0.01;
+
+/// A waypoint of the path, reached once the motion has covered
+/// `position` segments of the states it passes through
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Marker {
+    pub index: usize,
+    pub position: f64,
+}
+
+/// States followed one after another as a single motion, starting from
+/// wherever the crane is when the motion starts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Segment {
+    pub states: Vec<CraneState>,
+    pub markers: Vec<Marker>,
+}
+
+/// Resolves every waypoint of a path starting from `start`. Each
+/// waypoint is solved from the state the previous one ends in, and the
+/// path is rejected as a whole if any of them cannot be reached.
+///
+/// A `blend_radius` of zero results in one segment per waypoint. A
+/// positive radius, in meters, results in a single segment that
+/// cuts every corner short within that distance of its waypoint.
+pub fn resolve(
+    dimensions: &CraneDimensions,
+    limits: &CraneLimits,
+    start: &CraneState,
+    waypoints: &[Waypoint],
+    blend_radius: f64,
+) -> Result<Vec<Segment>, KinematicError> {
+    let blended = blend_radius > 0.;
+
+    let mut legs: Vec<Vec<CraneState>> = Vec::with_capacity(waypoints.len());
+    let mut previous = start.clone();
+    for (index, waypoint) in waypoints.iter().enumerate() {
+        let leg = resolve_waypoint(dimensions, limits, &previous, waypoint, blended).map_err(
+            |reason| KinematicError::InvalidWaypoint {
+                index,
+                reason: Box::new(reason),
+            },
+        )?;
+        if let Some(end) = leg.last() {
+            previous = end.clone();
+        }
+        legs.push(leg);
+    }
+
+    if blended {
+        return Ok(vec![blend(dimensions, legs, blend_radius)]);
+    }
+
+    let segments = legs
+        .into_iter()
+        .enumerate()
+        .map(|(index, states)| Segment {
+            markers: vec![Marker {
+                index,
+                position: states.len() as f64,
+            }],
+            states,
+        })
+        .collect();
+    Ok(segments)
+}
+
+/// The states leading from `previous` to a waypoint. Joint space moves
+/// are subdivided when they are to be blended so that their corners
+/// can be cut short.
+fn resolve_waypoint(
+    dimensions: &CraneDimensions,
+    limits: &CraneLimits,
+    previous: &CraneState,
+    waypoint: &Waypoint,
+    subdivide: bool,
+) -> Result<Vec<CraneState>, KinematicError> {
+    let target = match waypoint {
+        Waypoint::Joint { payload } => limits.constrain(payload),
+        Waypoint::Location { payload } => {
+            let solutions = kinematics::inverse_kinematics(dimensions, limits, previous, payload)?;
+            kinematics::nearest_solution(solutions, limits, previous)
+                .unwrap_or_else(|| previous.clone())
+        }
+        Waypoint::Linear { payload } => {
+            return kinematics::linear_path(dimensions, limits, previous, payload)
+        }
+    };
+
+    if !subdivide {
+        return Ok(vec![target]);
+    }
+
+    let travel =
+        Joint::ALL.map(|joint| limits.travel(joint, previous.get(joint), target.get(joint)));
+    let steps = Joint::ALL
+        .into_iter()
+        .zip(travel)
+        .map(|(joint, travel)| {
+            let step = if joint.is_revolute() {
+                REVOLUTE_STEP
+            } else {
+                PRISMATIC_STEP
+            };
+            (travel.abs() / step).ceil() as usize
+        })
+        .max()
+        .unwrap_or_default()
+        .max(1);
+
+    let mut leg: Vec<CraneState> = (1..steps)
+        .map(|step| {
+            let mut state = previous.clone();
+            for (joint, travel) in Joint::ALL.into_iter().zip(travel) {
+                let value = previous.get(joint) + travel * step as f64 / steps as f64;
+                state.set(joint, limits.clamp(joint, value));
+            }
+            state
+        })
+        .collect();
+    leg.push(target);
+    Ok(leg)
+}
+
+/// Joins the legs of a path into a single segment, dropping the states
+/// within `radius` of every waypoint but the last so that the motion
+/// passes the waypoint without stopping at it
+fn blend(dimensions: &CraneDimensions, legs: Vec<Vec<CraneState>>, radius: f64) -> Segment {
+    let mut corners = Vec::with_capacity(legs.len());
+    let mut states = Vec::new();
+    for leg in legs {
+        states.extend(leg);
+        corners.push(states.len() - 1);
+    }
+
+    let tips: Vec<Vector3> = states
+        .iter()
+        .map(|state| {
+            kinematics::forward_kinematics(dimensions, state)
+                .gripper_tip
+                .position
+        })
+        .collect();
+
+    let mut keep = vec![true; states.len()];
+    for (n, corner) in corners.iter().copied().enumerate() {
+        let Some(next) = corners.get(n + 1).copied() else {
+            break;
+        };
+        let previous = match n {
+            0 => 0,
+            _ => corners[n - 1] + 1,
+        };
+        let near = |i: &usize| (tips[*i] - tips[corner]).length() < radius;
+
+        // only cut the corner when the states on both sides of it are close
+        // enough to join, otherwise the motion would stray further than radius
+        let before = (previous..corner).rev().take_while(near).count();
+        let after = (corner + 1..next).take_while(near).count();
+        if before == 0 || after == 0 {
+            continue;
+        }
+        keep[corner - before..=corner + after].fill(false);
+    }
+
+    let markers = corners
+        .iter()
+        .enumerate()
+        .map(|(index, corner)| {
+            let passed = keep[..*corner].iter().filter(|kept| **kept).count();
+            let position = match keep[*corner] {
+                true => passed as f64 + 1.,
+                // halfway along the chord cutting the corner
+                false => passed as f64 + 0.5,
+            };
+            Marker { index, position }
+        })
+        .collect();
+
+    let states = states
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(state, kept)| kept.then_some(state))
+        .collect();
+
+    Segment { states, markers }
+}
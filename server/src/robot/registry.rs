@@ -1,75 +1,583 @@
-use actix::{Actor, Addr};
-use dashmap::DashMap;
+use std::sync::Arc;
+use std::time::Duration;
 
-use crate::storage::Database;
+use actix::Actor;
+use dashmap::{mapref::entry::Entry, DashMap};
+
+use crate::storage::{self, ConfigEvent, Database, InvalidConfig, RobotConfig, StateStore};
 
 use super::{
-    crane::{self, Crane},
-    message::RobotCraneInfoRequest,
-    models::{CraneDetails, CraneDimensions, CraneLimits},
+    core::ID,
+    kind::{Robot, RobotAddr, RobotKind},
+    message::{
+        Action, ChainInfo, ChainInfoRequest, Operation, Persist, Reconfigure, Retire,
+        RobotCraneInfo, RobotCraneInfoRequest,
+    },
+    models::{ChainDetails, CraneDetails, RobotDetails, RobotPose},
+    user,
 };
 
+#[derive(Debug, thiserror::Error)]
+pub enum SpawnError {
+    #[error("spawning robots is not allowed on this server")]
+    Disabled,
+
+    #[error("the template robot `{0}` was not found")]
+    TemplateNotFound(ID),
+
+    #[error("a robot with id `{0}` already exists")]
+    AlreadyExists(ID),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DefinitionError {
+    #[error("the robot `{0}` was not found")]
+    NotFound(ID),
+
+    #[error("a robot with id `{0}` already exists")]
+    AlreadyExists(ID),
+
+    #[error("the robot `{0}` was spawned and has no config to change")]
+    Spawned(ID),
+
+    #[error("the robot `{0}` is imported from URDF and its config can not be rewritten")]
+    Imported(ID),
+
+    #[error("the robot `{0}` is a {1} robot, which does not support this")]
+    KindUnsupported(ID, &'static str),
+
+    #[error("the config is not valid: {0}")]
+    Invalid(#[from] InvalidConfig),
+
+    #[error(transparent)]
+    Storage(#[from] anyhow::Error),
+}
+
 #[derive(Debug)]
 pub struct Registry {
     db: Database,
-    robots: DashMap<crane::ID, Addr<Crane>>,
+    /// robots spawned from a template while the server is running
+    instances: DashMap<ID, Robot>,
+    robots: DashMap<ID, RobotAddr>,
+    states: Arc<StateStore>,
+    tick: Duration,
+    idle_timeout: Option<Duration>,
+    allow_spawn: bool,
 }
 
 impl Registry {
-    pub fn new(db: Database) -> Self {
+    pub fn new(db: Database, tick: Duration) -> Self {
         Registry {
             db,
+            instances: Default::default(),
             robots: Default::default(),
+            states: Default::default(),
+            tick,
+            idle_timeout: None,
+            allow_spawn: false,
         }
     }
 
-    #[tracing::instrument(name = "get_or_create", skip(self))]
-    pub async fn get_or_create(&self, id: &crane::ID) -> Addr<Crane> {
+    /// Keeps the state of robots in the given store rather than only in
+    /// memory
+    pub fn with_state_store(mut self, states: StateStore) -> Self {
+        self.states = Arc::new(states);
+        self
+    }
+
+    /// Stops robots that have gone without users or motion for the given
+    /// period, they are started again on the next request for them
+    pub fn with_idle_timeout(mut self, idle_timeout: Option<Duration>) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Allows robots to be spawned from templates through `spawn`
+    pub fn with_spawn(mut self, allow_spawn: bool) -> Self {
+        self.allow_spawn = allow_spawn;
+        self
+    }
+
+    /// Returns the running actor of a robot, starting it if it is known
+    /// but not running yet. Unknown ids are never started.
+    #[tracing::instrument(name = "get_or_start", skip(self))]
+    pub async fn get_or_start(&self, id: &ID) -> Option<RobotAddr> {
         tracing::info!("fetching a robot from the registry");
-        if let Some(addr) = self.robots.get(id) {
-            return addr.clone();
+        self.running(id)
+    }
+
+    /// The actor of a robot, started while holding its entry so that
+    /// concurrent requests never start the same robot twice
+    fn running(&self, id: &ID) -> Option<RobotAddr> {
+        match self.robots.entry(id.clone()) {
+            Entry::Occupied(entry) if entry.get().connected() => Some(entry.get().clone()),
+            // the robot stopped after going idle
+            Entry::Occupied(mut entry) => match self.definition(id) {
+                Some(robot) => {
+                    let addr = self.start(robot);
+                    entry.insert(addr.clone());
+                    Some(addr)
+                }
+                None => {
+                    entry.remove();
+                    None
+                }
+            },
+            Entry::Vacant(entry) => {
+                let addr = self.start(self.definition(id)?);
+                Some(entry.insert(addr).clone())
+            }
         }
+    }
 
-        let robot = match self.db.get(id) {
-            Some(crane) => crane.clone(),
-            None => Crane::new(id.clone(), CraneDimensions::default(), CraneLimits::default())
+    /// The robot as it is configured or was spawned, without starting it
+    pub fn definition(&self, id: &ID) -> Option<Robot> {
+        match self.db.get(id) {
+            Some(robot) => Some(robot),
+            None => Some(self.instances.get(id)?.clone()),
+        }
+    }
+
+    fn start(&self, robot: Robot) -> RobotAddr {
+        match robot {
+            Robot::Crane(crane) => {
+                let mut crane = (*crane)
+                    .with_tick(self.tick)
+                    .with_store(self.states.clone());
+                if let Some(idle_timeout) = self.idle_timeout {
+                    crane = crane.with_idle_timeout(idle_timeout);
+                }
+                RobotAddr::Crane(crane.start())
+            }
+            Robot::Chain(robot) => {
+                let mut robot = (*robot)
+                    .with_tick(self.tick)
+                    .with_store(self.states.clone());
+                if let Some(idle_timeout) = self.idle_timeout {
+                    robot = robot.with_idle_timeout(idle_timeout);
+                }
+                RobotAddr::Chain(robot.start())
+            }
+        }
+    }
+
+    /// Starts a new robot with the dimensions and limits of a configured
+    /// one. Spawned robots last until the server stops.
+    #[tracing::instrument(name = "spawn", skip(self))]
+    pub async fn spawn(&self, template: &ID, id: ID) -> Result<RobotAddr, SpawnError> {
+        if !self.allow_spawn {
+            return Err(SpawnError::Disabled);
+        }
+        let Some(template) = self.db.get(template) else {
+            return Err(SpawnError::TemplateNotFound(template.clone()));
+        };
+        if self.db.get(&id).is_some() {
+            return Err(SpawnError::AlreadyExists(id));
+        }
+
+        match self.instances.entry(id.clone()) {
+            Entry::Occupied(_) => return Err(SpawnError::AlreadyExists(id)),
+            Entry::Vacant(entry) => entry.insert(template.instantiate(id.clone())),
         };
+        tracing::info!("spawned robot {id} from template {}", template.id());
+        self.running(&id)
+            .ok_or_else(|| SpawnError::TemplateNotFound(template.id().to_string()))
+    }
+
+    /// The details of a robot, from its actor while it is running and
+    /// otherwise from its definition and saved state, without starting it
+    #[tracing::instrument(name = "get_details", skip(self))]
+    pub async fn get_details(&self, id: &ID) -> Option<RobotDetails> {
+        tracing::info!("fetching robot details");
+        let running = self.robots.get(id).map(|addr| addr.clone());
+        if let Some(addr) = running.filter(|addr| addr.connected()) {
+            let details = match addr {
+                RobotAddr::Crane(addr) => addr.send(RobotCraneInfoRequest).await.map(crane_details),
+                RobotAddr::Chain(addr) => addr.send(ChainInfoRequest).await.map(chain_details),
+            };
+            // otherwise the robot stopped while being asked
+            if let Ok(details) = details {
+                return Some(details);
+            }
+        }
 
-        let id = robot.id.clone();
-        let robot = robot.start();
-        self.robots.insert(id, robot.clone());
-        robot
-    }
-
-    #[tracing::instrument(name = "get_crane_details", skip(self))]
-    pub async fn get_crane_details(&self, id: &crane::ID) -> Option<CraneDetails> {
-        tracing::info!("fetching crane details");
-        let addr = self.get_or_create(id).await;
-        return match addr.send(RobotCraneInfoRequest).await {
-            Ok(info) => Some(CraneDetails {
-                id: info.id,
-                state: info.state,
-                dimensions: info.dimensions,
-            }),
-            Err(_) => None,
+        let details = match self.definition(id)? {
+            Robot::Crane(crane) => crane_details(crane.with_store(self.states.clone()).info()),
+            Robot::Chain(robot) => chain_details(robot.with_store(self.states.clone()).info()),
         };
+        Some(details)
+    }
+
+    #[tracing::instrument(name = "get_pose", skip(self))]
+    pub async fn get_pose(&self, id: &ID) -> Option<RobotPose> {
+        tracing::info!("fetching robot pose");
+        match self.get_details(id).await? {
+            RobotDetails::Crane(details) => Some(RobotPose::Crane(Box::new(details.pose))),
+            RobotDetails::Chain(details) => Some(RobotPose::Chain(details.frames)),
+        }
+    }
+
+    /// Issues an action to a robot on behalf of the server, returning
+    /// whether the robot accepted it or none if there is no such robot
+    #[tracing::instrument(name = "send_action", skip(self))]
+    pub async fn send_action(&self, id: &ID, action: Action) -> Option<bool> {
+        tracing::info!("sending action to robot");
+        let addr = self.get_or_start(id).await?;
+        Some(
+            addr.send(Operation::new(user::SYSTEM, action))
+                .await
+                .is_ok(),
+        )
+    }
+
+    /// Brings the robots in line with a change to the config directory.
+    /// Running robots take on their new config straight away, new robots
+    /// are started on the first request for them and the robots of
+    /// removed files are stopped.
+    #[tracing::instrument(name = "update_config", skip(self, event))]
+    pub async fn update_config(&self, event: ConfigEvent) {
+        match event {
+            ConfigEvent::Changed(robot) => {
+                let Some(source) = robot.source() else {
+                    return;
+                };
+                if let Some(existing) = self.db.get(robot.id()) {
+                    if existing.source() != Some(source) {
+                        let diagnostic = storage::duplicate_id(source, &existing);
+                        tracing::warn!("ignoring config changes: {diagnostic}");
+                        return;
+                    }
+                }
+                // the file may now describe a different robot
+                if let Some(previous) = self.db.find_source(source) {
+                    if previous != robot.id() {
+                        self.retire(&previous).await;
+                    }
+                }
+
+                let id = robot.id().to_string();
+                match self.db.insert((*robot).clone()) {
+                    Some(_) => tracing::info!("robot {id} config changed"),
+                    None => tracing::info!("robot {id} added"),
+                }
+                let running = self.robots.get(&id).map(|addr| addr.clone());
+                let Some(addr) = running.filter(|addr| addr.connected()) else {
+                    return;
+                };
+                let sent = match (addr, *robot) {
+                    (RobotAddr::Crane(addr), Robot::Crane(config)) => {
+                        addr.send(Reconfigure { config: *config }).await
+                    }
+                    (RobotAddr::Chain(addr), Robot::Chain(config)) => {
+                        addr.send(Reconfigure { config: *config }).await
+                    }
+                    // a robot of another kind is started afresh on the
+                    // next request for it
+                    (addr, _) => {
+                        tracing::info!("robot {id} changed kind, stopping");
+                        self.robots.remove(&id);
+                        addr.send(Retire).await
+                    }
+                };
+                if let Err(e) = sent {
+                    tracing::warn!("failed to reconfigure robot {id}: {e}");
+                }
+            }
+            ConfigEvent::Removed(path) => {
+                if let Some(id) = self.db.find_source(&path) {
+                    self.retire(&id).await;
+                }
+            }
+        }
+    }
+
+    /// Adds a robot, writing its config to `<id>.toml` in the config
+    /// directory
+    #[tracing::instrument(name = "create_robot", skip(self, config), fields(id = config.id()))]
+    pub async fn create_robot(&self, config: RobotConfig) -> Result<(), DefinitionError> {
+        let id = config.id().to_string();
+        if self.db.get(&id).is_some() || self.instances.contains_key(&id) {
+            return Err(DefinitionError::AlreadyExists(id));
+        }
+
+        let path = self.db.dir().join(format!("{id}.toml"));
+        let robot = storage::check_robot_config(&path, config.clone())?;
+        // the file may hold a robot with another id, or have been created
+        // by a concurrent request for the same robot
+        if let Err(e) = storage::create_robot_config(&path, &config) {
+            let exists = e
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::AlreadyExists);
+            return Err(match exists {
+                true => DefinitionError::AlreadyExists(id),
+                false => e.into(),
+            });
+        }
+        self.update_config(ConfigEvent::Changed(Box::new(robot.into())))
+            .await;
+        Ok(())
     }
 
-    #[tracing::instrument(name = "get_all_crane_details", skip(self))]
-    pub async fn get_all_crane_details(&self) -> Vec<CraneDetails> {
+    /// Replaces the config of a crane, rewriting its config file. Robots
+    /// imported from URDF are changed by editing their file instead.
+    #[tracing::instrument(name = "update_robot", skip(self, config))]
+    pub async fn update_robot(&self, id: &ID, config: RobotConfig) -> Result<(), DefinitionError> {
+        let source = self.source_of(id)?;
+        // the config describes a crane, which must not replace another kind
+        if let Some(robot @ Robot::Chain(_)) = self.db.get(id) {
+            return Err(DefinitionError::KindUnsupported(id.clone(), robot.kind()));
+        }
+        if source.extension().and_then(|s| s.to_str()) != Some("toml") {
+            return Err(DefinitionError::Imported(id.clone()));
+        }
+        let robot = storage::check_robot_config(&source, config.clone())?;
+        storage::write_robot_config(&source, &config)?;
+        self.update_config(ConfigEvent::Changed(Box::new(robot.into())))
+            .await;
+        Ok(())
+    }
+
+    /// Removes a robot along with its config file
+    #[tracing::instrument(name = "delete_robot", skip(self))]
+    pub async fn delete_robot(&self, id: &ID) -> Result<(), DefinitionError> {
+        let source = self.source_of(id)?;
+        storage::remove_robot_config(&source)?;
+        self.update_config(ConfigEvent::Removed(source)).await;
+        Ok(())
+    }
+
+    /// The config file of a configured robot
+    fn source_of(&self, id: &ID) -> Result<std::path::PathBuf, DefinitionError> {
+        match self.db.get(id) {
+            Some(robot) => robot
+                .source()
+                .map(|source| source.to_path_buf())
+                .ok_or_else(|| DefinitionError::NotFound(id.clone())),
+            None if self.instances.contains_key(id) => Err(DefinitionError::Spawned(id.clone())),
+            None => Err(DefinitionError::NotFound(id.clone())),
+        }
+    }
+
+    /// Forgets a configured robot along with its saved state, stopping it
+    /// if it is running
+    async fn retire(&self, id: &ID) {
+        self.db.remove(id);
+        tracing::info!("robot {id} removed");
+        if let Some((_, robot)) = self.robots.remove(id) {
+            if let Err(e) = robot.send(Retire).await {
+                tracing::debug!("robot {id} already stopped: {e}");
+            }
+        }
+        // the retired robot no longer writes to the store
+        self.states.remove(id);
+    }
+
+    /// Has every running robot write its state to the store
+    #[tracing::instrument(name = "persist_all", skip(self))]
+    pub async fn persist_all(&self) {
+        tracing::info!("persisting the state of all robots");
+        // robots stopped along with the server have persisted themselves
+        let robots: Vec<RobotAddr> = self
+            .robots
+            .iter()
+            .filter(|robot| robot.connected())
+            .map(|robot| robot.clone())
+            .collect();
+        for robot in robots {
+            if let Err(e) = robot.send(Persist).await {
+                tracing::warn!("failed to persist the state of a robot: {e}");
+            }
+        }
+    }
+
+    /// The details of every robot, none of which are started to get them
+    #[tracing::instrument(name = "get_all_details", skip(self))]
+    pub async fn get_all_details(&self) -> Vec<RobotDetails> {
+        let mut ids: Vec<ID> = self
+            .db
+            .get_all()
+            .into_iter()
+            .map(|robot| robot.id().to_string())
+            .collect();
+        ids.extend(self.instances.iter().map(|instance| instance.key().clone()));
+
         let mut details = Vec::new();
-        
-        for crane in self.db.get_all() {
-            let addr = self.get_or_create(&crane.id).await;
-            if let Ok(info) = addr.send(RobotCraneInfoRequest).await {
-                details.push(CraneDetails {
-                    id: info.id,
-                    state: info.state,
-                    dimensions: info.dimensions,
-                });
+        for id in ids {
+            if let Some(robot) = self.get_details(&id).await {
+                details.push(robot);
             }
         }
-        
         details
     }
 }
+
+fn crane_details(info: RobotCraneInfo) -> RobotDetails {
+    RobotDetails::Crane(Box::new(CraneDetails {
+        id: info.id,
+        state: info.state,
+        dimensions: info.dimensions,
+        pose: info.pose,
+    }))
+}
+
+fn chain_details(info: ChainInfo) -> RobotDetails {
+    RobotDetails::Chain(ChainDetails {
+        id: info.id,
+        kind: "chain".to_string(),
+        chain: info.chain,
+        state: info.state,
+        frames: info.frames,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::robot::models::CraneState;
+    use crate::storage::SavedCrane;
+
+    /// A directory of its own for a test to keep configs or state in
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("robotix-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    const CHAIN: &str = r#"
+id = "chain"
+kind = "chain"
+
+[[joints]]
+name = "shoulder"
+type = "revolute"
+dh = { alpha = 90.0, d = 0.4 }
+min = -170
+max = 170
+"#;
+
+    fn config(id: &str) -> RobotConfig {
+        let content = include_str!("../../config/robot-1.toml")
+            .replace(r#"id = "standard-bot""#, &format!(r#"id = "{id}""#));
+        toml::from_str(&content).unwrap()
+    }
+
+    async fn state(registry: &Registry, id: &ID) -> CraneState {
+        match registry.get_details(id).await {
+            Some(RobotDetails::Crane(details)) => details.state,
+            details => panic!("expected the details of a crane, got {details:?}"),
+        }
+    }
+
+    #[actix::test]
+    async fn a_recreated_robot_starts_afresh() {
+        let (configs, states) = (temp_dir("configs"), temp_dir("states"));
+        let db = Database::setup(configs.to_str().unwrap()).unwrap();
+        let store = StateStore::open(states.to_str().unwrap()).unwrap();
+        let registry = Registry::new(db, Duration::from_millis(5)).with_state_store(store);
+        let id = "recreated".to_string();
+
+        registry.create_robot(config(&id)).await.unwrap();
+        let initial = state(&registry, &id).await;
+        let swing = HashMap::from([("swing".to_string(), 1.)]);
+        let moved = registry
+            .send_action(&id, Action::MoveJoints { payload: swing })
+            .await;
+        assert_eq!(moved, Some(true));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_ne!(state(&registry, &id).await, initial);
+        // as the robot does every second while it moves
+        registry.persist_all().await;
+        assert!(states.join(format!("{id}.json")).exists());
+
+        registry.delete_robot(&id).await.unwrap();
+        assert!(!states.join(format!("{id}.json")).exists());
+
+        registry.create_robot(config(&id)).await.unwrap();
+        let recreated = state(&registry, &id).await;
+        registry.delete_robot(&id).await.unwrap();
+        let _ = fs::remove_dir_all(&configs);
+        let _ = fs::remove_dir_all(&states);
+
+        assert_eq!(recreated, initial);
+    }
+
+    #[actix::test]
+    async fn a_chain_robot_is_not_replaced_by_a_crane() {
+        let configs = temp_dir("chain-update");
+        let file = configs.join("chain.toml");
+        fs::write(&file, CHAIN).unwrap();
+        let db = Database::setup(configs.to_str().unwrap()).unwrap();
+        let registry = Registry::new(db, Duration::from_millis(5));
+        let id = "chain".to_string();
+
+        let updated = registry.update_robot(&id, config(&id)).await;
+        let content = fs::read_to_string(&file).unwrap();
+        let _ = fs::remove_dir_all(&configs);
+
+        assert!(matches!(
+            updated,
+            Err(DefinitionError::KindUnsupported(_, "chain"))
+        ));
+        assert_eq!(content, CHAIN);
+        assert_eq!(
+            registry.definition(&id).map(|robot| robot.kind()),
+            Some("chain")
+        );
+    }
+
+    #[actix::test]
+    async fn a_new_robot_never_replaces_a_config_file() {
+        let configs = temp_dir("create");
+        // a file named after the robot that describes another one
+        let file = configs.join("taken.toml");
+        fs::write(&file, CHAIN).unwrap();
+        let db = Database::setup(configs.to_str().unwrap()).unwrap();
+        let registry = Registry::new(db, Duration::from_millis(5));
+
+        let created = registry.create_robot(config("taken")).await;
+        let content = fs::read_to_string(&file).unwrap();
+        let files = fs::read_dir(&configs).unwrap().count();
+        let _ = fs::remove_dir_all(&configs);
+
+        assert!(matches!(created, Err(DefinitionError::AlreadyExists(_))));
+        assert_eq!(content, CHAIN);
+        assert_eq!(files, 1);
+    }
+
+    #[actix::test]
+    async fn listing_robots_does_not_start_them() {
+        let configs = temp_dir("listing");
+        fs::write(
+            configs.join("listed.toml"),
+            toml::to_string(&config("listed")).unwrap(),
+        )
+        .unwrap();
+        let db = Database::setup(configs.to_str().unwrap()).unwrap();
+        let store = StateStore::default();
+        let saved = SavedCrane {
+            state: CraneState {
+                swing_rad: 1.,
+                lift_m: 0.5,
+                ..Default::default()
+            },
+            queue: Vec::new(),
+            emergency_stop: false,
+        };
+        store.save(&"listed".to_string(), &saved.clone().into());
+        let registry = Registry::new(db, Duration::from_millis(5)).with_state_store(store);
+
+        let details = registry.get_all_details().await;
+        let _ = fs::remove_dir_all(&configs);
+
+        assert!(registry.robots.is_empty());
+        match details.as_slice() {
+            [RobotDetails::Crane(details)] => assert_eq!(details.state, saved.state),
+            details => panic!("expected the details of one crane, got {details:?}"),
+        }
+    }
+}
@@ -0,0 +1,441 @@
+//! # session
+//!
+//! the users connected to a robot and the lease of the one in control of
+//! it. Every kind of robot hosts its users the same way, only what they
+//! can make the robot do differs.
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+use actix::Recipient;
+
+use super::{
+    message::{Action, Connect, Disconnect, ErrorCode, Operation},
+    user,
+};
+
+/// A user connected to the robot through one of its sessions
+#[derive(Debug, Clone)]
+struct Participant {
+    addr: Recipient<Operation>,
+    role: user::Role,
+    session: user::SessionId,
+}
+
+/// The right of a single user to move the robot, renewed by the user's
+/// heartbeats and actions
+#[derive(Debug, Clone)]
+struct Lease {
+    holder: user::ID,
+    expires: Instant,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(super) struct Session {
+    users: HashMap<user::ID, Participant>,
+    lease: Option<Lease>,
+}
+
+impl Session {
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+
+    pub fn users(&self) -> Vec<user::ID> {
+        self.users.keys().copied().collect()
+    }
+
+    pub fn broadcast(&self, msg: Operation) {
+        for (_, user) in self.users.iter() {
+            user.addr.do_send(msg.clone())
+        }
+    }
+
+    /// Sends an operation only to the given user rather than every recipient
+    pub fn reply(&self, user_id: &user::ID, msg: Operation) {
+        if let Some(user) = self.users.get(user_id) {
+            user.addr.do_send(msg)
+        }
+    }
+
+    /// Acknowledges an operation to its sender when the client asked for it
+    pub fn acknowledge(&self, msg: &Operation) {
+        if let Some(request_id) = &msg.request_id {
+            let ack = Action::Ack {
+                payload: request_id.clone(),
+            };
+            let op = Operation::new(msg.user_id, ack).with_request_id(msg.request_id.clone());
+            self.reply(&msg.user_id, op);
+        }
+    }
+
+    pub fn reject(&self, msg: &Operation, code: ErrorCode, reason: impl Into<String>) {
+        let op = Operation::rejected(msg.user_id, msg.request_id.clone(), code, reason);
+        self.reply(&msg.user_id, op);
+    }
+
+    /// The role of a connected user. The server itself may do anything
+    /// while users that are no longer connected may do nothing.
+    pub fn role(&self, user_id: &user::ID) -> Option<user::Role> {
+        match *user_id == user::SYSTEM {
+            true => Some(user::Role::Admin),
+            false => self.users.get(user_id).map(|user| user.role),
+        }
+    }
+
+    /// The user currently in control of the robot
+    pub fn controller(&self) -> Option<user::ID> {
+        self.lease.as_ref().map(|lease| lease.holder)
+    }
+
+    /// Whether a user may move the robot. Operations issued by the server
+    /// itself are always allowed.
+    pub fn in_control(&self, user_id: &user::ID) -> bool {
+        *user_id == user::SYSTEM || self.controller().as_ref() == Some(user_id)
+    }
+
+    /// Extends the lease if it is held by the given user
+    pub fn renew_lease(&mut self, user_id: &user::ID) {
+        if let Some(lease) = self.lease.as_mut().filter(|lease| lease.holder == *user_id) {
+            lease.expires = Instant::now() + user::LEASE_TIMEOUT;
+        }
+    }
+
+    /// The user whose lease ran out without being renewed
+    pub fn expired_lease(&self) -> Option<user::ID> {
+        self.lease
+            .as_ref()
+            .filter(|lease| lease.expires <= Instant::now())
+            .map(|lease| lease.holder)
+    }
+
+    /// Hands control to a user, or releases it when `holder` is none, and
+    /// lets everyone know who is in control now
+    pub fn set_controller(&mut self, holder: Option<user::ID>, user_id: user::ID) {
+        let previous = self.controller();
+        self.lease = holder.map(|holder| Lease {
+            holder,
+            expires: Instant::now() + user::LEASE_TIMEOUT,
+        });
+
+        if previous != holder {
+            self.broadcast(Operation::new(
+                user_id,
+                Action::ControlChanged { payload: holder },
+            ));
+        }
+    }
+
+    /// Adds a user. A user is connected through a single session at a
+    /// time, a new session takes over from the previous one along with
+    /// its control.
+    pub fn join(&mut self, msg: Connect) {
+        let participant = Participant {
+            addr: msg.addr,
+            role: msg.role,
+            session: msg.session,
+        };
+        if let Some(previous) = self.users.insert(msg.user, participant) {
+            tracing::info!("user {} replaced an earlier session", &msg.user);
+            previous
+                .addr
+                .do_send(Operation::new(msg.user, Action::Kick { payload: msg.user }));
+        }
+    }
+
+    /// Removes a user and lets everyone else know, returning whether the
+    /// session was still the user's current one
+    pub fn leave(&mut self, msg: &Disconnect) -> bool {
+        // the session may already have been replaced by a newer one
+        if self.users.get(&msg.user).map(|user| user.session) != Some(msg.session) {
+            return false;
+        }
+
+        self.users.remove(&msg.user);
+        let op = Operation::new(msg.user, Action::Leave { payload: msg.user });
+        self.broadcast(op);
+        true
+    }
+
+    /// Disconnects every user
+    pub fn kick_all(&mut self) {
+        for (user, participant) in self.users.drain() {
+            participant
+                .addr
+                .do_send(Operation::new(user::SYSTEM, Action::Kick { payload: user }));
+        }
+    }
+
+    /// Checks that the sender of an operation may carry it out, rejecting
+    /// it otherwise. Moving the robot requires holding control of it and
+    /// the robot not to be emergency stopped.
+    pub fn authorize(&mut self, msg: &Operation, emergency_stop: bool) -> bool {
+        let role = self.role(&msg.user_id);
+        if role.is_none_or(|role| role < msg.action.required_role()) {
+            self.reject(
+                msg,
+                ErrorCode::Forbidden,
+                "the user's role does not allow this action",
+            );
+            return false;
+        }
+
+        let moving = msg.action.is_motion();
+        if moving && !self.in_control(&msg.user_id) {
+            let reason = match self.controller() {
+                Some(holder) => format!("the robot is controlled by {holder}"),
+                None => "control of the robot must be requested before moving it".to_string(),
+            };
+            self.reject(msg, ErrorCode::NotInControl, reason);
+            return false;
+        }
+        self.renew_lease(&msg.user_id);
+
+        if moving && emergency_stop {
+            self.reject(
+                msg,
+                ErrorCode::EmergencyStop,
+                "the robot is emergency stopped and must be reset before moving",
+            );
+            return false;
+        }
+        true
+    }
+
+    /// Carries out the actions about control of the robot and its users,
+    /// returning false for every other action
+    pub fn handle(&mut self, msg: &Operation, robot: &str) -> bool {
+        match &msg.action {
+            Action::RequestControl => match self.controller() {
+                Some(holder) if holder != msg.user_id => {
+                    let reason = format!("the robot is controlled by {holder}");
+                    self.reject(msg, ErrorCode::ControlHeld, reason);
+                }
+                _ => {
+                    self.acknowledge(msg);
+                    self.set_controller(Some(msg.user_id), msg.user_id);
+                }
+            },
+            Action::ReleaseControl => {
+                if self.controller() == Some(msg.user_id) {
+                    self.acknowledge(msg);
+                    self.set_controller(None, msg.user_id);
+                } else {
+                    self.reject(
+                        msg,
+                        ErrorCode::NotInControl,
+                        "control of the robot is not held by this user",
+                    );
+                }
+            }
+            Action::Kick { payload } => match self.users.get(payload) {
+                Some(user) => {
+                    tracing::info!("robot {} kicking {} for {}", robot, payload, msg.user_id);
+                    user.addr
+                        .do_send(Operation::new(msg.user_id, msg.action.clone()));
+                    self.acknowledge(msg);
+                }
+                None => {
+                    let reason = format!("user {payload} is not connected to the robot");
+                    self.reject(msg, ErrorCode::InvalidAction, reason);
+                }
+            },
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use actix::{Actor, Context, Handler};
+
+    use super::*;
+
+    /// Keeps every operation sent to a user
+    struct Inbox(Arc<Mutex<Vec<Operation>>>);
+
+    impl Actor for Inbox {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<Operation> for Inbox {
+        type Result = ();
+
+        fn handle(&mut self, msg: Operation, _ctx: &mut Self::Context) -> Self::Result {
+            self.0.lock().unwrap().push(msg);
+        }
+    }
+
+    /// A user connected through a session of their own
+    struct Client {
+        user: user::ID,
+        session: user::SessionId,
+        received: Arc<Mutex<Vec<Operation>>>,
+    }
+
+    impl Client {
+        fn join(session: &mut Session, user: user::ID, role: user::Role) -> Self {
+            let received = Arc::new(Mutex::new(Vec::new()));
+            let addr = Inbox(received.clone()).start().recipient();
+            let client = Client {
+                user,
+                session: uuid::Uuid::new_v4(),
+                received,
+            };
+            session.join(Connect {
+                user,
+                session: client.session,
+                role,
+                addr,
+            });
+            client
+        }
+
+        fn send(&self, action: Action) -> Operation {
+            Operation::new(self.user, action)
+        }
+
+        /// The actions delivered to the user so far
+        async fn received(&self) -> Vec<Action> {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let received = self.received.lock().unwrap();
+            received.iter().map(|op| op.action.clone()).collect()
+        }
+    }
+
+    fn move_joints() -> Action {
+        Action::MoveJoints {
+            payload: HashMap::from([("swing".to_string(), 1.)]),
+        }
+    }
+
+    #[actix::test]
+    async fn only_the_holder_of_the_lease_moves_the_robot() {
+        let mut session = Session::default();
+        let alice = Client::join(&mut session, uuid::Uuid::new_v4(), user::Role::Operator);
+        let bob = Client::join(&mut session, uuid::Uuid::new_v4(), user::Role::Operator);
+
+        assert!(!session.authorize(&alice.send(move_joints()), false));
+        assert!(session.handle(&alice.send(Action::RequestControl), "robot"));
+        assert_eq!(session.controller(), Some(alice.user));
+
+        assert!(session.authorize(&alice.send(move_joints()), false));
+        assert!(!session.authorize(&bob.send(move_joints()), false));
+        // control is not taken from its holder
+        session.handle(&bob.send(Action::RequestControl), "robot");
+        assert_eq!(session.controller(), Some(alice.user));
+        // nor released by anyone else
+        session.handle(&bob.send(Action::ReleaseControl), "robot");
+        assert_eq!(session.controller(), Some(alice.user));
+
+        let codes: Vec<ErrorCode> = bob
+            .received()
+            .await
+            .into_iter()
+            .filter_map(|action| match action {
+                Action::Rejected { payload } => Some(payload.code),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                ErrorCode::NotInControl,
+                ErrorCode::ControlHeld,
+                ErrorCode::NotInControl
+            ]
+        );
+    }
+
+    #[actix::test]
+    async fn the_lease_expires_unless_renewed() {
+        let mut session = Session::default();
+        let alice = Client::join(&mut session, uuid::Uuid::new_v4(), user::Role::Operator);
+        let bob = Client::join(&mut session, uuid::Uuid::new_v4(), user::Role::Operator);
+        session.set_controller(Some(alice.user), alice.user);
+        assert_eq!(session.expired_lease(), None);
+
+        let lapse = |session: &mut Session| {
+            session.lease.as_mut().unwrap().expires = Instant::now() - Duration::from_millis(1);
+        };
+        lapse(&mut session);
+        // only the holder renews the lease
+        session.renew_lease(&bob.user);
+        assert_eq!(session.expired_lease(), Some(alice.user));
+        session.renew_lease(&alice.user);
+        assert_eq!(session.expired_lease(), None);
+
+        // as do the actions of the holder
+        lapse(&mut session);
+        assert!(session.authorize(&alice.send(move_joints()), false));
+        assert_eq!(session.expired_lease(), None);
+    }
+
+    #[actix::test]
+    async fn rejoining_kicks_the_earlier_session() {
+        let mut session = Session::default();
+        let user = uuid::Uuid::new_v4();
+        let first = Client::join(&mut session, user, user::Role::Operator);
+        let second = Client::join(&mut session, user, user::Role::Operator);
+
+        assert_eq!(first.received().await, vec![Action::Kick { payload: user }]);
+        assert_eq!(second.received().await, Vec::new());
+        assert_eq!(session.users(), vec![user]);
+
+        // the earlier session closing does not disconnect the new one
+        let leave = |client: &Client| Disconnect {
+            user: client.user,
+            session: client.session,
+        };
+        assert!(!session.leave(&leave(&first)));
+        assert_eq!(session.users(), vec![user]);
+        assert!(session.leave(&leave(&second)));
+        assert!(session.is_empty());
+    }
+
+    #[actix::test]
+    async fn roles_allow_what_the_roles_before_them_do() {
+        let mut session = Session::default();
+        let viewer = Client::join(&mut session, uuid::Uuid::new_v4(), user::Role::Viewer);
+        let operator = Client::join(&mut session, uuid::Uuid::new_v4(), user::Role::Operator);
+        let admin = Client::join(&mut session, uuid::Uuid::new_v4(), user::Role::Admin);
+        let mut allowed =
+            |client: &Client, action: Action| session.authorize(&client.send(action), false);
+
+        assert!(!allowed(&viewer, Action::RequestControl));
+        assert!(!allowed(&viewer, Action::Stop));
+        assert!(allowed(&operator, Action::RequestControl));
+        assert!(allowed(&operator, Action::Stop));
+        assert!(!allowed(&operator, Action::EmergencyStop));
+        assert!(!allowed(&operator, Action::Reset));
+        assert!(allowed(&admin, Action::RequestControl));
+        assert!(allowed(&admin, Action::EmergencyStop));
+        assert!(allowed(&admin, Action::Reset));
+
+        // users that are no longer connected may do nothing
+        session.leave(&Disconnect {
+            user: admin.user,
+            session: admin.session,
+        });
+        assert!(!session.authorize(&admin.send(Action::Stop), false));
+        // while the server itself may do anything
+        let system = Operation::new(user::SYSTEM, Action::EmergencyStop);
+        assert!(session.authorize(&system, false));
+
+        let forbidden = |actions: Vec<Action>| {
+            actions
+                .into_iter()
+                .filter(|action| {
+                    matches!(action, Action::Rejected { payload } if payload.code == ErrorCode::Forbidden)
+                })
+                .count()
+        };
+        assert_eq!(forbidden(viewer.received().await), 2);
+        assert_eq!(forbidden(operator.received().await), 2);
+    }
+}
@@ -0,0 +1,539 @@
+//! # trajectory
+//!
+//! time parameterisation of joint space motions. Every joint follows the
+//! same normalised velocity profile scaled by its own displacement, so
+//! all joints start and finish together while staying within their
+//! individual velocity, acceleration and jerk limits.
+
+use std::f64::consts::TAU;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::models::{CraneLimits, CraneState, Joint, JointDynamics, MotionLimits};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileKind {
+    /// constant acceleration ramps, the jerk limit is ignored
+    Trapezoidal,
+    /// jerk limited ramps, also known as a double S profile
+    #[default]
+    SCurve,
+}
+
+/// A symmetric rest to rest velocity profile over a distance of one.
+///
+/// The acceleration phase lasts `ta` and is made of a jerk phase of `tj`,
+/// a constant acceleration phase and a second jerk phase of `tj`. It is
+/// followed by a cruise at `peak_velocity` for `tv` and a deceleration
+/// phase mirroring the acceleration. A trapezoidal profile is the special
+/// case where `tj` is zero.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Profile {
+    tj: f64,
+    ta: f64,
+    tv: f64,
+    peak_velocity: f64,
+    jerk: f64,
+}
+
+impl Profile {
+    /// Plans the fastest profile covering a unit distance without
+    /// exceeding the given velocity, acceleration and jerk.
+    pub fn plan(kind: ProfileKind, velocity: f64, acceleration: f64, jerk: f64) -> Self {
+        // duration of the acceleration phase needed to reach `v`, along
+        // with the duration of each of its jerk phases
+        let ramp = |v: f64| match kind {
+            ProfileKind::Trapezoidal => (0., v / acceleration),
+            ProfileKind::SCurve if v * jerk >= acceleration.powi(2) => {
+                let tj = acceleration / jerk;
+                (tj, tj + v / acceleration)
+            }
+            ProfileKind::SCurve => {
+                let tj = (v / jerk).sqrt();
+                (tj, 2. * tj)
+            }
+        };
+
+        // accelerating to and back down from `v` covers `v * ta`
+        let (tj, ta) = ramp(velocity);
+        if velocity * ta <= 1. {
+            return Self {
+                tj,
+                ta,
+                tv: (1. - velocity * ta) / velocity,
+                peak_velocity: velocity,
+                jerk,
+            };
+        }
+
+        // too short to reach full speed, search for the peak velocity
+        // at which the ramps alone cover the distance
+        let (mut low, mut high) = (0., velocity);
+        for _ in 0..64 {
+            let mid = (low + high) / 2.;
+            let (_, ta) = ramp(mid);
+            if mid * ta > 1. {
+                high = mid;
+            } else {
+                low = mid;
+            }
+        }
+
+        let (tj, ta) = ramp(low);
+        Self {
+            tj,
+            ta,
+            tv: 0.,
+            peak_velocity: low,
+            jerk,
+        }
+    }
+
+    pub fn duration(&self) -> f64 {
+        2. * self.ta + self.tv
+    }
+
+    /// Fraction of the distance covered after `t` seconds
+    pub fn position(&self, t: f64) -> f64 {
+        let total = self.duration();
+        if total <= 0. || t >= total {
+            return 1.;
+        }
+        if t <= 0. {
+            return 0.;
+        }
+
+        if t < self.ta {
+            self.ramp_position(t)
+        } else if t < self.ta + self.tv {
+            self.peak_velocity * (self.ta / 2. + (t - self.ta))
+        } else {
+            1. - self.ramp_position(total - t)
+        }
+    }
+
+    /// Rate at which the distance is covered after `t` seconds
+    pub fn velocity(&self, t: f64) -> f64 {
+        let total = self.duration();
+        if t <= 0. || t >= total {
+            return 0.;
+        }
+
+        if t < self.ta {
+            self.ramp_velocity(t)
+        } else if t < self.ta + self.tv {
+            self.peak_velocity
+        } else {
+            self.ramp_velocity(total - t)
+        }
+    }
+
+    fn ramp_velocity(&self, t: f64) -> f64 {
+        let (tj, ta, v) = (self.tj, self.ta, self.peak_velocity);
+        let acceleration = if tj > 0. { self.jerk * tj } else { v / ta };
+
+        if t < tj {
+            self.jerk * t.powi(2) / 2.
+        } else if t < ta - tj {
+            acceleration * (t - tj / 2.)
+        } else {
+            v - self.jerk * (ta - t).powi(2) / 2.
+        }
+    }
+
+    /// Distance covered `t` seconds into the acceleration phase
+    fn ramp_position(&self, t: f64) -> f64 {
+        let (tj, ta, v) = (self.tj, self.ta, self.peak_velocity);
+        let acceleration = if tj > 0. { self.jerk * tj } else { v / ta };
+
+        if t < tj {
+            self.jerk * t.powi(3) / 6.
+        } else if t < ta - tj {
+            acceleration / 6. * (3. * t.powi(2) - 3. * tj * t + tj.powi(2))
+        } else {
+            v * t - v * ta / 2. + self.jerk * (ta - t).powi(3) / 6.
+        }
+    }
+}
+
+/// How the unit distance of a trajectory is covered over time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Timing {
+    /// starts and ends at rest
+    Profile(Profile),
+    /// starts in motion and slows down at a constant rate until it stops
+    Decelerate { duration: f64 },
+}
+
+impl Timing {
+    /// Brings joints moving at `velocities` to rest together, as fast as
+    /// the acceleration of each allows, returning the timing along with
+    /// how far each joint travels until it stops
+    pub(super) fn decelerate(
+        velocities: &[f64],
+        accelerations: impl IntoIterator<Item = f64>,
+    ) -> (Self, Vec<f64>) {
+        let mut duration: f64 = 0.;
+        for (velocity, acceleration) in velocities.iter().zip(accelerations) {
+            duration = duration.max(velocity.abs() / acceleration);
+        }
+
+        // slowing down linearly from v to rest over the duration covers v * duration / 2
+        let travel = velocities
+            .iter()
+            .map(|velocity| velocity * duration / 2.)
+            .collect();
+        (Timing::Decelerate { duration }, travel)
+    }
+
+    pub(super) fn duration(&self) -> f64 {
+        match self {
+            Timing::Profile(profile) => profile.duration(),
+            Timing::Decelerate { duration } => *duration,
+        }
+    }
+
+    pub(super) fn position(&self, t: f64) -> f64 {
+        match self {
+            Timing::Profile(profile) => profile.position(t),
+            Timing::Decelerate { duration } if t >= *duration => 1.,
+            Timing::Decelerate { duration } => {
+                let t = t.max(0.) / duration;
+                2. * t - t.powi(2)
+            }
+        }
+    }
+
+    pub(super) fn velocity(&self, t: f64) -> f64 {
+        match self {
+            Timing::Profile(profile) => profile.velocity(t),
+            Timing::Decelerate { duration } if t >= *duration => 0.,
+            Timing::Decelerate { duration } => 2. / duration * (1. - t.max(0.) / duration),
+        }
+    }
+}
+
+type Joints = [f64; Joint::ALL.len()];
+
+/// A synchronised motion through a sequence of joint positions. The path
+/// is parameterised uniformly over its waypoints, so a joint space move
+/// has two waypoints while a cartesian move samples many points along
+/// its line. Continuous joints are stored unwrapped so that they always
+/// travel the intended way around.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trajectory {
+    waypoints: Vec<Joints>,
+    timing: Timing,
+}
+
+impl Trajectory {
+    /// Plans a joint space move from `start` to `target`
+    pub fn plan(
+        start: &CraneState,
+        target: &CraneState,
+        limits: &CraneLimits,
+        motion: &MotionLimits,
+    ) -> Self {
+        Self::through(&[start.clone(), target.clone()], limits, motion)
+    }
+
+    /// Plans a move passing through every state of `path` in order, the
+    /// path must contain at least one state
+    pub fn through(path: &[CraneState], limits: &CraneLimits, motion: &MotionLimits) -> Self {
+        let mut waypoints: Vec<Joints> = Vec::with_capacity(path.len());
+        for (n, state) in path.iter().enumerate() {
+            let mut joints = [0.; Joint::ALL.len()];
+            for (i, joint) in Joint::ALL.into_iter().enumerate() {
+                joints[i] = match n {
+                    0 => state.get(joint),
+                    _ => {
+                        let previous = &path[n - 1];
+                        let travel = limits.travel(joint, previous.get(joint), state.get(joint));
+                        // the state itself, turned by however many full turns
+                        // the joint has travelled, so that it is reached exactly
+                        let turns =
+                            ((waypoints[n - 1][i] + travel - state.get(joint)) / TAU).round();
+                        state.get(joint) + TAU * turns
+                    }
+                };
+            }
+            waypoints.push(joints);
+        }
+
+        // the fastest rate of change of each joint relative to the unit
+        // distance of the shared profile
+        let segments = waypoints.len().saturating_sub(1) as f64;
+        let mut gradient: Joints = [0.; Joint::ALL.len()];
+        for pair in waypoints.windows(2) {
+            for (i, value) in gradient.iter_mut().enumerate() {
+                *value = (*value).max((pair[1][i] - pair[0][i]).abs() * segments);
+            }
+        }
+
+        // scale each joint's limits down to the unit distance and keep
+        // the most restrictive of them
+        let (mut velocity, mut acceleration, mut jerk) = (f64::MAX, f64::MAX, f64::MAX);
+        for (i, joint) in Joint::ALL.into_iter().enumerate() {
+            if gradient[i] == 0. {
+                continue;
+            }
+            let JointDynamics {
+                max_velocity,
+                max_acceleration,
+                max_jerk,
+                ..
+            } = motion.joint(joint);
+            velocity = velocity.min(max_velocity / gradient[i]);
+            acceleration = acceleration.min(max_acceleration / gradient[i]);
+            jerk = jerk.min(max_jerk / gradient[i]);
+        }
+
+        let timing = if velocity == f64::MAX {
+            // nothing to move
+            Timing::Decelerate { duration: 0. }
+        } else {
+            Timing::Profile(Profile::plan(motion.profile, velocity, acceleration, jerk))
+        };
+
+        Self { waypoints, timing }
+    }
+
+    /// Plans how to bring this trajectory to a halt `elapsed` into it,
+    /// slowing every joint down as fast as its acceleration limit allows
+    /// while keeping them synchronised.
+    pub fn stop(&self, elapsed: Duration, limits: &CraneLimits, motion: &MotionLimits) -> Self {
+        let t = elapsed.as_secs_f64();
+        let (segment, _) = self.locate(self.timing.position(t));
+        let rate = self.timing.velocity(t) * (self.waypoints.len() - 1).max(1) as f64;
+
+        let mut velocities = [0.; Joint::ALL.len()];
+        if let Some(pair) = self.waypoints.get(segment..segment + 2) {
+            for (i, velocity) in velocities.iter_mut().enumerate() {
+                *velocity = (pair[1][i] - pair[0][i]) * rate;
+            }
+        }
+
+        let accelerations = Joint::ALL.map(|joint| motion.joint(joint).max_acceleration);
+        let (timing, travel) = Timing::decelerate(&velocities, accelerations);
+
+        let start = self.sample(elapsed, limits);
+        let mut from = [0.; Joint::ALL.len()];
+        let mut to = [0.; Joint::ALL.len()];
+        for (i, joint) in Joint::ALL.into_iter().enumerate() {
+            from[i] = start.get(joint);
+            to[i] = from[i] + travel[i];
+        }
+
+        Self {
+            waypoints: vec![from, to],
+            timing,
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.timing.duration())
+    }
+
+    /// The state the crane is in once the motion completes
+    pub fn end(&self, limits: &CraneLimits) -> CraneState {
+        self.sample(self.duration(), limits)
+    }
+
+    /// How many segments between waypoints have been covered `elapsed`
+    /// into the motion
+    pub fn progress(&self, elapsed: Duration) -> f64 {
+        let segments = self.waypoints.len().saturating_sub(1) as f64;
+        self.timing.position(elapsed.as_secs_f64()) * segments
+    }
+
+    /// The waypoints still ahead of the crane `elapsed` into the motion
+    pub fn remaining(&self, elapsed: Duration, limits: &CraneLimits) -> Vec<CraneState> {
+        let passed = self.progress(elapsed).floor() as usize;
+        self.waypoints
+            .iter()
+            .skip(passed + 1)
+            .map(|joints| {
+                let mut state = CraneState::default();
+                for (i, joint) in Joint::ALL.into_iter().enumerate() {
+                    state.set(joint, limits.clamp(joint, joints[i]));
+                }
+                state
+            })
+            .collect()
+    }
+
+    /// The segment a fraction of the path falls in, and how far along it
+    fn locate(&self, progress: f64) -> (usize, f64) {
+        let segments = self.waypoints.len().saturating_sub(1);
+        if segments == 0 {
+            return (0, 0.);
+        }
+        let position = progress.clamp(0., 1.) * segments as f64;
+        let segment = (position.floor() as usize).min(segments - 1);
+        (segment, position - segment as f64)
+    }
+
+    /// The state of the crane `elapsed` into the motion
+    pub fn sample(&self, elapsed: Duration, limits: &CraneLimits) -> CraneState {
+        let (segment, fraction) = self.locate(self.timing.position(elapsed.as_secs_f64()));
+        let from = self.waypoints[segment];
+        let to = self.waypoints.get(segment + 1).unwrap_or(&from);
+
+        let mut state = CraneState::default();
+        for (i, joint) in Joint::ALL.into_iter().enumerate() {
+            // weighing both ends lands exactly on them, where stepping
+            // from one to the other can fall short by a rounding error
+            let value = from[i] * (1. - fraction) + to[i] * fraction;
+            state.set(joint, limits.clamp(joint, value));
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sampling period used to differentiate profiles numerically
+    const DT: f64 = 1e-4;
+
+    /// Relative slack allowed on a limit for the error of sampling
+    const SLACK: f64 = 1e-6;
+
+    /// Unit distance limits of a long move that cruises, and of short
+    /// moves that never reach full speed or full acceleration
+    const LIMITS: [(f64, f64, f64); 4] = [
+        (0.5, 1., 4.),
+        (2., 1., 4.),
+        (4., 20., 10.),
+        (10., 50., 1000.),
+    ];
+
+    fn samples(duration: f64) -> impl Iterator<Item = f64> {
+        let steps = (duration / DT).ceil() as usize;
+        (0..=steps).map(move |n| n as f64 * DT)
+    }
+
+    fn within(value: f64, limit: f64) -> bool {
+        value.abs() <= limit * (1. + SLACK)
+    }
+
+    #[test]
+    fn profiles_stay_within_their_velocity_and_acceleration() {
+        for kind in [ProfileKind::Trapezoidal, ProfileKind::SCurve] {
+            for (velocity, acceleration, jerk) in LIMITS {
+                let profile = Profile::plan(kind, velocity, acceleration, jerk);
+                for t in samples(profile.duration()) {
+                    let v = profile.velocity(t);
+                    assert!(within(v, velocity), "{kind:?} reaches {v} at {t} s");
+                    let a = (profile.velocity(t + DT) - v) / DT;
+                    assert!(
+                        within(a, acceleration),
+                        "{kind:?} accelerates at {a} at {t} s"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn s_curves_stay_within_their_jerk() {
+        for (velocity, acceleration, jerk) in LIMITS {
+            let profile = Profile::plan(ProfileKind::SCurve, velocity, acceleration, jerk);
+            let acceleration = |t: f64| (profile.velocity(t + DT) - profile.velocity(t)) / DT;
+            for t in samples(profile.duration()) {
+                let j = (acceleration(t + DT) - acceleration(t)) / DT;
+                // differentiating twice leaves more noise than once
+                assert!(j.abs() <= jerk * 1.001, "the jerk is {j} at {t} s");
+            }
+        }
+    }
+
+    #[test]
+    fn profiles_cover_the_distance_exactly() {
+        for kind in [ProfileKind::Trapezoidal, ProfileKind::SCurve] {
+            for (velocity, acceleration, jerk) in LIMITS {
+                let profile = Profile::plan(kind, velocity, acceleration, jerk);
+                let duration = profile.duration();
+                assert_eq!(profile.position(0.), 0.);
+                assert_eq!(profile.position(duration), 1.);
+                assert_eq!(profile.velocity(duration), 0.);
+                // no jump onto the end as the profile runs out
+                assert!((profile.position(duration - DT) - 1.).abs() < 1e-6);
+
+                let mut previous = 0.;
+                for t in samples(duration) {
+                    let position = profile.position(t);
+                    assert!(position >= previous, "{kind:?} goes back at {t} s");
+                    previous = position;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn trajectories_keep_every_joint_within_its_dynamics() {
+        let limits = CraneLimits::default();
+        let start = CraneState::default();
+        let target = CraneState {
+            swing_rad: 2.5,
+            lift_m: 1.4,
+            elbow_rad: -1.,
+            wrist_rad: 0.3,
+            gripper_m: 0.05,
+        };
+
+        for profile in [ProfileKind::Trapezoidal, ProfileKind::SCurve] {
+            let motion = MotionLimits {
+                profile,
+                ..Default::default()
+            };
+            let trajectory = Trajectory::plan(&start, &target, &limits, &motion);
+            let duration = trajectory.duration().as_secs_f64();
+            let at = |t: f64| trajectory.sample(Duration::from_secs_f64(t), &limits);
+
+            for t in samples(duration) {
+                let (now, next) = (at(t), at(t + DT));
+                for joint in Joint::ALL {
+                    let velocity = (next.get(joint) - now.get(joint)) / DT;
+                    let max_velocity = motion.joint(joint).max_velocity;
+                    assert!(
+                        within(velocity, max_velocity),
+                        "{joint} moves at {velocity} at {t} s"
+                    );
+                }
+            }
+            assert_eq!(trajectory.end(&limits), target);
+        }
+    }
+
+    #[test]
+    fn continuous_joints_reach_the_target_the_short_way_round() {
+        let limits = CraneLimits {
+            swing: None,
+            ..Default::default()
+        };
+        let motion = MotionLimits::default();
+        let start = CraneState {
+            swing_rad: 3.,
+            lift_m: 0.5,
+            ..Default::default()
+        };
+        let target = CraneState {
+            swing_rad: -3.,
+            lift_m: 0.5,
+            ..Default::default()
+        };
+
+        let trajectory = Trajectory::plan(&start, &target, &limits, &motion);
+
+        let halfway = trajectory.sample(trajectory.duration() / 2, &limits);
+        assert!(
+            halfway.swing_rad.abs() > 3.,
+            "the swing turns the long way round"
+        );
+        assert_eq!(trajectory.end(&limits), target);
+    }
+}
@@ -1,31 +1,73 @@
 use std::time::{Duration, Instant};
 
-use actix::{Actor, ActorContext, Addr, AsyncContext, Handler, StreamHandler};
+use actix::{Actor, ActorContext, AsyncContext, Handler, StreamHandler};
 use actix_web_actors::ws::{self, Message, ProtocolError};
-use chrono::Utc;
+use serde::{Deserialize, Serialize};
 
 use super::{
-    crane::Crane,
-    message::{Action, Connect, Disconnect, Operation},
+    kind::RobotAddr,
+    message::{Action, Connect, Disconnect, ErrorCode, Heartbeat, Operation, Request, RequestId},
 };
 
 // Every minute - check if this client is alive.
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
 
+/// How long a user keeps control of a crane without a heartbeat, long
+/// enough to survive a single missed pong but well within the time it
+/// takes for the connection to be dropped
+pub const LEASE_TIMEOUT: Duration = Duration::from_secs(25);
+
 pub type ID = uuid::Uuid;
 
+/// Identifies a single connection of a user, which may reconnect under
+/// the same user id
+pub type SessionId = uuid::Uuid;
+
+/// Identifies operations issued by the server itself, such as those
+/// made through the REST api rather than by a connected user
+pub const SYSTEM: ID = uuid::Uuid::nil();
+
+/// What a user is allowed to do with a crane, every role is allowed
+/// everything the roles before it are
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// observes the crane without being able to affect it
+    Viewer,
+    /// jogs and moves the crane
+    Operator,
+    /// emergency stops and resets the crane, kicks users and reloads configs
+    Admin,
+}
+
 #[derive(Debug)]
 pub struct User {
     pub id: ID,
-    pub addr: Addr<Crane>,
+    pub session: SessionId,
+    pub role: Role,
+    pub addr: RobotAddr,
     heartbeat: Instant,
 }
 
 impl User {
-    pub fn new(id: ID, addr: Addr<Crane>) -> Self {
+    pub fn new(id: ID, role: Role, addr: RobotAddr) -> Self {
         User {
             id,
+            session: uuid::Uuid::new_v4(),
+            role,
             addr,
             heartbeat: Instant::now(),
         }
@@ -40,6 +82,21 @@ impl User {
             ctx.ping(b"");
         });
     }
+
+    /// Replies directly to this user's client, bypassing the crane
+    fn reply(&self, op: Operation, ctx: &mut <Self as Actor>::Context) {
+        match serde_json::to_string(&op) {
+            Ok(json) => ctx.text(json),
+            Err(e) => tracing::error!("failed to serialize reply: {:?}", e),
+        }
+    }
+}
+
+/// Used to recover the request id of a message that could not be parsed
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RequestIdOnly {
+    request_id: Option<RequestId>,
 }
 
 impl Actor for User {
@@ -50,6 +107,8 @@ impl Actor for User {
         let addr = ctx.address();
         let msg = Connect {
             user: self.id,
+            session: self.session,
+            role: self.role,
             addr: addr.recipient(),
         };
         self.addr.do_send(msg);
@@ -58,7 +117,10 @@ impl Actor for User {
 
     fn stopped(&mut self, _ctx: &mut Self::Context) {
         tracing::info!("stopping user actor {}", self.id);
-        let msg = Disconnect { user: self.id };
+        let msg = Disconnect {
+            user: self.id,
+            session: self.session,
+        };
         self.addr.do_send(msg);
     }
 }
@@ -68,20 +130,36 @@ impl StreamHandler<Result<ws::Message, ProtocolError>> for User {
         // tracing::info!("handling in bound action {}", self.id);
         match msg {
             Ok(Message::Text(text)) => {
-                let action: Action = match serde_json::from_slice(text.as_bytes()) {
-                    Ok(c) => c,
+                let request: Request = match serde_json::from_slice(text.as_bytes()) {
+                    Ok(r) => r,
                     Err(e) => {
                         tracing::error!("command not supported: {:?}", e);
+                        let request_id = serde_json::from_slice::<RequestIdOnly>(text.as_bytes())
+                            .ok()
+                            .and_then(|r| r.request_id);
+                        let op = Operation::rejected(
+                            self.id,
+                            request_id,
+                            ErrorCode::InvalidAction,
+                            e.to_string(),
+                        );
+                        self.reply(op, ctx);
                         return;
                     }
                 };
 
-                if let Err(e) = self.addr.try_send(Operation {
-                    user_id: self.id,
-                    action,
-                    created_at: Utc::now(),
-                }) {
-                    tracing::error!("failed to send action to crane: {:?}", e);
+                let op =
+                    Operation::new(self.id, request.action).with_request_id(request.request_id);
+                let request_id = op.request_id.clone();
+                if let Err(e) = self.addr.try_send(op) {
+                    tracing::error!("failed to send action to robot: {:?}", e);
+                    let op = Operation::rejected(
+                        self.id,
+                        request_id,
+                        ErrorCode::Unavailable,
+                        "the robot is not able to accept actions right now",
+                    );
+                    self.reply(op, ctx);
                 }
             }
 
@@ -89,6 +167,7 @@ impl StreamHandler<Result<ws::Message, ProtocolError>> for User {
             // reset the heartbeat
             Ok(Message::Pong(_)) => {
                 self.heartbeat = Instant::now();
+                self.addr.do_send(Heartbeat { user: self.id });
             }
 
             // if the message is not next or a pong, we stop the context
@@ -102,7 +181,12 @@ impl Handler<Operation> for User {
     type Result = ();
 
     fn handle(&mut self, msg: Operation, ctx: &mut Self::Context) -> Self::Result {
-        let json = serde_json::to_string(&msg).unwrap();
-        ctx.text(json)
+        let kicked = matches!(msg.action, Action::Kick { payload } if payload == self.id);
+        self.reply(msg, ctx);
+        if kicked {
+            tracing::info!("user {} was kicked", self.id);
+            ctx.close(Some(ws::CloseCode::Policy.into()));
+            ctx.stop();
+        }
     }
 }
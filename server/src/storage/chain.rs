@@ -0,0 +1,204 @@
+//! # chain
+//!
+//! the TOML config format of chain robots, chosen with `kind = "chain"`.
+//! The joints are listed from the base outwards, each placed relative to
+//! the joint before it either by Denavit–Hartenberg parameters, classic
+//! (`dh`) or modified (`mdh`), or by an origin and the axis it moves
+//! about:
+//!
+//! ```toml
+//! id = "arm-1"
+//! kind = "chain"
+//!
+//! [[joints]]
+//! name = "shoulder"
+//! type = "revolute"
+//! dh = { a = 0.0, alpha = 90.0, d = 0.4, theta = 0.0 }
+//! min = -170
+//! max = 170
+//!
+//! [[joints]]
+//! name = "carriage"
+//! type = "prismatic"
+//! origin = { xyz = [0.0, 0.0, 0.2], rpy = [0.0, 0.0, 0.0] }
+//! axis = [1.0, 0.0, 0.0]
+//! min = 0.0
+//! max = 0.8
+//! max_velocity = 0.4
+//! jog_increment = 0.005
+//! ```
+//!
+//! Lengths, prismatic limits among them, are in meters and angles in
+//! degrees. Joints without dynamic limits take those of the crane's
+//! elbow or lift, and a single jog command moves a joint by its
+//! `jog_increment`, what it covers in 150 ms at its maximum velocity
+//! unless given. A joint with none of `dh`, `mdh` and `origin` sits at
+//! the frame of the joint before it.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::robot::chain::{Bounds, ChainJoint, JointKind, KinematicChain, Placement};
+use crate::robot::chain_robot::ChainRobot;
+use crate::robot::kinematics::Vector3;
+use crate::robot::models::JointDynamics;
+use crate::robot::trajectory::ProfileKind;
+
+use super::{validate, InvalidConfig};
+
+const REVOLUTE_DYNAMICS: JointDynamics = JointDynamics::new(60., 120., 480.);
+const PRISMATIC_DYNAMICS: JointDynamics = JointDynamics::new(0.25, 0.5, 2.);
+
+/// A chain robot as it is described in its TOML config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainConfig {
+    pub(super) id: String,
+    #[serde(default)]
+    pub(super) motion: ChainMotionConfig,
+    #[serde(default)]
+    pub(super) joints: Vec<ChainJointConfig>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(super) struct ChainMotionConfig {
+    #[serde(default)]
+    pub profile: ProfileKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct ChainJointConfig {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: JointKind,
+    pub dh: Option<DhConfig>,
+    pub mdh: Option<DhConfig>,
+    pub origin: Option<OriginConfig>,
+    /// the axis the joint moves about after its origin, z by default
+    pub axis: Option<[f64; 3]>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    #[serde(default)]
+    pub continuous: bool,
+    pub max_velocity: Option<f64>,
+    pub max_acceleration: Option<f64>,
+    pub max_jerk: Option<f64>,
+    pub jog_increment: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub(super) struct DhConfig {
+    #[serde(default)]
+    pub a: f64,
+    #[serde(default)]
+    pub alpha: f64,
+    #[serde(default)]
+    pub d: f64,
+    #[serde(default)]
+    pub theta: f64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub(super) struct OriginConfig {
+    #[serde(default)]
+    pub xyz: [f64; 3],
+    #[serde(default)]
+    pub rpy: [f64; 3],
+}
+
+impl ChainJointConfig {
+    /// Validated configs place every joint in exactly one way
+    fn placement(&self) -> Placement {
+        match (self.dh, self.mdh, self.origin) {
+            (Some(dh), _, _) => Placement::Dh {
+                a: dh.a,
+                alpha: dh.alpha.to_radians(),
+                d: dh.d,
+                theta: dh.theta.to_radians(),
+            },
+            (None, Some(mdh), _) => Placement::ModifiedDh {
+                a: mdh.a,
+                alpha: mdh.alpha.to_radians(),
+                d: mdh.d,
+                theta: mdh.theta.to_radians(),
+            },
+            (None, None, origin) => {
+                let origin = origin.unwrap_or(OriginConfig {
+                    xyz: [0.; 3],
+                    rpy: [0.; 3],
+                });
+                let [x, y, z] = self.axis.unwrap_or([0., 0., 1.]);
+                Placement::Transform {
+                    xyz: vector(origin.xyz),
+                    rpy: vector(origin.rpy.map(f64::to_radians)),
+                    axis: Vector3::new(x, y, z),
+                }
+            }
+        }
+    }
+
+    fn dynamics(&self) -> JointDynamics {
+        let defaults = match self.kind {
+            JointKind::Revolute => REVOLUTE_DYNAMICS,
+            JointKind::Prismatic => PRISMATIC_DYNAMICS,
+        };
+        let dynamics = JointDynamics::new(
+            self.max_velocity.unwrap_or(defaults.max_velocity),
+            self.max_acceleration.unwrap_or(defaults.max_acceleration),
+            self.max_jerk.unwrap_or(defaults.max_jerk),
+        );
+        let dynamics = match self.jog_increment {
+            Some(jog_increment) => dynamics.with_jog_increment(jog_increment),
+            None => dynamics,
+        };
+        match self.kind {
+            JointKind::Revolute => dynamics.to_radians(),
+            JointKind::Prismatic => dynamics,
+        }
+    }
+
+    /// The travel range of the joint in radians or meters, none for a
+    /// continuous joint
+    fn range(&self) -> Option<Bounds> {
+        let revolute = self.kind == JointKind::Revolute;
+        (!self.continuous).then(|| Bounds {
+            min: super::position(revolute, self.min.unwrap_or_default()),
+            max: super::position(revolute, self.max.unwrap_or_default()),
+        })
+    }
+}
+
+fn vector([x, y, z]: [f64; 3]) -> Vector3 {
+    Vector3::new(x, y, z)
+}
+
+/// Validates a chain config read from `path`, returning the robot it
+/// describes
+pub fn check_chain_config(path: &Path, config: ChainConfig) -> Result<ChainRobot, InvalidConfig> {
+    let diagnostics = validate::validate_chain(path, &config);
+    if !diagnostics.is_empty() {
+        return Err(InvalidConfig { diagnostics });
+    }
+    Ok(build_chain(config).with_source(path.to_path_buf()))
+}
+
+/// Builds a robot from a chain config that passed validation
+fn build_chain(config: ChainConfig) -> ChainRobot {
+    let joints = config
+        .joints
+        .iter()
+        .map(|joint| ChainJoint {
+            name: joint.name.clone(),
+            kind: joint.kind,
+            placement: joint.placement(),
+            range: joint.range(),
+            dynamics: joint.dynamics(),
+        })
+        .collect();
+
+    let chain = KinematicChain {
+        joints,
+        profile: config.motion.profile,
+    };
+    ChainRobot::new(config.id, chain)
+}
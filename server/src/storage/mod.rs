@@ -1,16 +1,34 @@
 use std::fs;
 use std::path::Path;
-use std::collections::HashMap;
 use std::path::PathBuf;
 
 use anyhow::{Context, Result};
-use serde::Deserialize;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
 
 use crate::robot::crane::Crane;
-use crate::robot::models::{CraneDimensions, CraneLimits};
+use crate::robot::kinematics::LinkOffsets;
+use crate::robot::models::{
+    CraneDimensions, CraneLimits, DhTable, Joint, JointDynamics, MotionLimits, Range,
+};
+use crate::robot::trajectory::ProfileKind;
+use crate::robot::{Robot, RobotKind};
 
-#[derive(Debug, Deserialize)]
-struct RobotConfig {
+mod chain;
+mod state;
+mod urdf;
+mod validate;
+mod watch;
+use self::chain::ChainConfig;
+pub use self::state::{SavedChain, SavedCrane, SavedRobot, StateStore};
+pub use self::urdf::export as export_urdf;
+pub use self::validate::{plain_id, Diagnostic, InvalidConfig, Rule, Severity};
+pub use self::watch::{ConfigEvent, ConfigWatcher};
+
+/// A robot as it is described in its TOML config file, also accepted as
+/// JSON by the api
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RobotConfig {
     id: String,
     base: BaseConfig,
     column: ColumnConfig,
@@ -22,36 +40,46 @@ struct RobotConfig {
     wrist_joint: JointConfig,
     gripper: GripperConfig,
     limits: LimitsConfig,
+    #[serde(default)]
+    motion: MotionConfig,
+    /// explicit joint origins, taking the place of those implied by the
+    /// sizes of the links
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kinematics: Option<DhTable>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct BaseConfig {
-    height: f64,
+    /// left out when a kinematics table places the swing joint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    height: Option<f64>,
     radius_bottom: f64,
     radius_top: f64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct ColumnConfig {
     height: f64,
     width: f64,
     thickness: f64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct ArmConfig {
-    length: f64,
+    /// left out when a kinematics table places the joint the arm reaches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    length: Option<f64>,
     width: f64,
     thickness: f64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct JointConfig {
     radius: f64,
     height: f64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct GripperConfig {
     length: f64,
     width: f64,
@@ -59,58 +87,310 @@ struct GripperConfig {
     max_open: f64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct LimitsConfig {
-    swing_min: i64,
-    swing_max: i64,
-    lift_min: i64,
-    lift_max: i64,
-    elbow_min: i64,
-    elbow_max: i64,
-    wrist_min: i64,
-    wrist_max: i64,
-    gripper_min: i64,
-    gripper_max: i64,
-}
-
-pub fn load_robot_configs(config_dir: &Path) -> Result<Vec<Crane>> {
-    let mut cranes = Vec::new();
-    
-    // Read all .toml files in the config directory
-    for entry in fs::read_dir(config_dir)? {
-        let entry = entry?;
-        let path = entry.path();
-        
-        if path.extension().and_then(|s| s.to_str()) == Some("toml") {
-            let config = load_robot_config(&path)?;
-            let crane = create_crane_from_config(config)?;
-            cranes.push(crane);
+    #[serde(default)]
+    continuous: Vec<Joint>,
+    swing_min: Option<f64>,
+    swing_max: Option<f64>,
+    lift_min: Option<f64>,
+    lift_max: Option<f64>,
+    elbow_min: Option<f64>,
+    elbow_max: Option<f64>,
+    wrist_min: Option<f64>,
+    wrist_max: Option<f64>,
+    gripper_min: Option<f64>,
+    gripper_max: Option<f64>,
+}
+
+impl LimitsConfig {
+    fn bounds(&self, joint: Joint) -> (Option<f64>, Option<f64>) {
+        match joint {
+            Joint::Swing => (self.swing_min, self.swing_max),
+            Joint::Lift => (self.lift_min, self.lift_max),
+            Joint::Elbow => (self.elbow_min, self.elbow_max),
+            Joint::Wrist => (self.wrist_min, self.wrist_max),
+            Joint::Gripper => (self.gripper_min, self.gripper_max),
+        }
+    }
+
+    /// The travel range of a joint in radians or meters, none for joints
+    /// listed as continuous. Validated configs declare both bounds of
+    /// every other joint.
+    fn range(&self, joint: Joint) -> Option<Range> {
+        if self.continuous.contains(&joint) && joint.is_revolute() {
+            return None;
+        }
+        Some(self.bounded(joint))
+    }
+
+    fn bounded(&self, joint: Joint) -> Range {
+        let (min, max) = self.bounds(joint);
+        let position =
+            |value: Option<f64>| position(joint.is_revolute(), value.unwrap_or_default());
+        Range::new(position(min), position(max))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct MotionConfig {
+    #[serde(default)]
+    profile: ProfileKind,
+    swing: Option<JointDynamicsConfig>,
+    lift: Option<JointDynamicsConfig>,
+    elbow: Option<JointDynamicsConfig>,
+    wrist: Option<JointDynamicsConfig>,
+    gripper: Option<JointDynamicsConfig>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct JointDynamicsConfig {
+    max_velocity: f64,
+    max_acceleration: f64,
+    max_jerk: f64,
+    /// how far a single jog command moves the joint, what it covers in
+    /// 150 ms at its maximum velocity by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    jog_increment: Option<f64>,
+}
+
+impl MotionConfig {
+    fn joint(&self, joint: Joint) -> Option<JointDynamicsConfig> {
+        match joint {
+            Joint::Swing => self.swing,
+            Joint::Lift => self.lift,
+            Joint::Elbow => self.elbow,
+            Joint::Wrist => self.wrist,
+            Joint::Gripper => self.gripper,
+        }
+    }
+
+    /// Resolves the dynamic limits of a joint, falling back to the
+    /// defaults for joints that are not configured
+    fn dynamics(&self, joint: Joint) -> JointDynamics {
+        let Some(config) = self.joint(joint) else {
+            return MotionLimits::default().joint(joint);
+        };
+        let dynamics = JointDynamics::new(
+            config.max_velocity,
+            config.max_acceleration,
+            config.max_jerk,
+        );
+        let dynamics = match config.jog_increment {
+            Some(jog_increment) => dynamics.with_jog_increment(jog_increment),
+            None => dynamics,
+        };
+        match joint.is_revolute() {
+            true => dynamics.to_radians(),
+            false => dynamics,
         }
     }
-    
-    Ok(cranes)
 }
 
-fn load_robot_config(path: &Path) -> Result<RobotConfig> {
+impl RobotConfig {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+/// Converts a joint position from the units of config files, degrees for
+/// revolute joints and meters for prismatic ones, to radians and meters
+fn position(revolute: bool, value: f64) -> f64 {
+    match revolute {
+        true => value.to_radians(),
+        false => value,
+    }
+}
+
+/// Whether a file describes a robot, either in the TOML config format or
+/// as URDF
+pub fn is_config_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|s| s.to_str()),
+        Some("toml" | "urdf")
+    )
+}
+
+/// Loads every robot in the config directory. Invalid configs, and
+/// configs reusing the id of a robot loaded before them, are skipped and
+/// reported while the rest are loaded. Warnings about the configs that
+/// were loaded are reported along with them.
+pub fn load_robot_configs(config_dir: &Path) -> Result<(Vec<Robot>, Vec<Diagnostic>)> {
+    let mut paths = Vec::new();
+
+    // Read all .toml and .urdf files in the config directory
+    let entries = fs::read_dir(config_dir)
+        .with_context(|| format!("Failed to read config directory: {}", config_dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if is_config_file(&path) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut robots: Vec<Robot> = Vec::new();
+    let mut diagnostics = Vec::new();
+    for path in paths {
+        match load_file(&path) {
+            Ok((robot, warnings)) => match robots.iter().find(|loaded| loaded.id() == robot.id()) {
+                Some(loaded) => diagnostics.push(duplicate_id(&path, loaded)),
+                None => {
+                    diagnostics.extend(warnings);
+                    robots.push(robot);
+                }
+            },
+            Err(e) => diagnostics.extend(e.diagnostics),
+        }
+    }
+
+    Ok((robots, diagnostics))
+}
+
+/// Reports a config file reusing the id of an already loaded robot
+pub fn duplicate_id(path: &Path, loaded: &Robot) -> Diagnostic {
+    let other = loaded
+        .source()
+        .map(|source| source.display().to_string())
+        .unwrap_or_default();
+    Diagnostic::new(
+        path,
+        "id",
+        Rule::DuplicateId,
+        format!("robot `{}` is already configured by {other}", loaded.id()),
+    )
+}
+
+/// Loads a single robot from its config file, remembering the file so
+/// that the robot can reload it later
+pub fn load_robot(path: &Path) -> Result<Robot, InvalidConfig> {
+    let (robot, warnings) = load_file(path)?;
+    for warning in warnings {
+        tracing::warn!("{warning}");
+    }
+    Ok(robot)
+}
+
+/// Loads a robot from a TOML or URDF file, along with the warnings about it
+fn load_file(path: &Path) -> Result<(Robot, Vec<Diagnostic>), InvalidConfig> {
     let content = fs::read_to_string(path)
-        .with_context(|| format!("Failed to read config file: {}", path.display()))?;
-    
-    toml::from_str(&content)
-        .with_context(|| format!("Failed to parse config file: {}", path.display()))
+        .map_err(|e| Diagnostic::new(path, "", Rule::Unreadable, e.to_string()))?;
+    if path.extension().and_then(|s| s.to_str()) != Some("urdf") {
+        return Ok((load_toml(path, &content)?, Vec::new()));
+    }
+
+    let (config, warnings) = urdf::import(path, &content)?;
+    match check_robot_config(path, config) {
+        Ok(crane) => Ok((crane.into(), warnings)),
+        Err(mut invalid) => {
+            invalid.diagnostics.extend(warnings);
+            Err(invalid)
+        }
+    }
+}
+
+/// Validates a config that is to be written to `path`, returning the
+/// robot it describes
+pub fn check_robot_config(path: &Path, config: RobotConfig) -> Result<Crane, InvalidConfig> {
+    let diagnostics = validate::validate(path, &config);
+    if !diagnostics.is_empty() {
+        return Err(InvalidConfig { diagnostics });
+    }
+    Ok(build_crane(config).with_source(path.to_path_buf()))
+}
+
+/// Writes a config file in one go, so that the config directory watcher
+/// never sees half of it
+pub fn write_robot_config(path: &Path, config: &RobotConfig) -> Result<()> {
+    let content = toml::to_string_pretty(config).context("Failed to serialize robot config")?;
+    let partial = path.with_extension("toml.partial");
+    fs::write(&partial, content)
+        .with_context(|| format!("Failed to write config file: {}", partial.display()))?;
+    fs::rename(&partial, path)
+        .with_context(|| format!("Failed to replace config file: {}", path.display()))
+}
+
+/// Writes the config file of a new robot in one go, failing with an
+/// `AlreadyExists` error rather than replacing a file that is already there
+pub fn create_robot_config(path: &Path, config: &RobotConfig) -> Result<()> {
+    let content = toml::to_string_pretty(config).context("Failed to serialize robot config")?;
+    // concurrent requests for the same file each write their own
+    let partial = path.with_extension(format!("toml.{}.partial", uuid::Uuid::new_v4()));
+    fs::write(&partial, content)
+        .with_context(|| format!("Failed to write config file: {}", partial.display()))?;
+    let linked = fs::hard_link(&partial, path);
+    let _ = fs::remove_file(&partial);
+    linked.with_context(|| format!("Failed to create config file: {}", path.display()))
+}
+
+pub fn remove_robot_config(path: &Path) -> Result<()> {
+    fs::remove_file(path)
+        .with_context(|| format!("Failed to remove config file: {}", path.display()))
+}
+
+/// Only the kind of robot a TOML config describes, cranes unless told
+/// otherwise
+#[derive(Deserialize)]
+struct KindConfig {
+    kind: Option<String>,
+}
+
+/// Loads a robot of whichever kind the TOML config describes
+fn load_toml(path: &Path, content: &str) -> Result<Robot, InvalidConfig> {
+    let kind: KindConfig = parse_toml(path, content)?;
+    match kind.kind.as_deref() {
+        None | Some("crane") => {
+            let config: RobotConfig = parse_toml(path, content)?;
+            Ok(check_robot_config(path, config)?.into())
+        }
+        Some("chain") => {
+            let config: ChainConfig = parse_toml(path, content)?;
+            Ok(chain::check_chain_config(path, config)?.into())
+        }
+        Some(other) => Err(Diagnostic::new(
+            path,
+            "kind",
+            Rule::Syntax,
+            format!("unknown robot kind `{other}`, expected `crane` or `chain`"),
+        )
+        .into()),
+    }
 }
 
-fn create_crane_from_config(config: RobotConfig) -> Result<Crane> {
-    let dimensions = CraneDimensions {
-        base_height: config.base.height,
+fn parse_toml<T: serde::de::DeserializeOwned>(path: &Path, content: &str) -> Result<T, Diagnostic> {
+    serde_path_to_error::deserialize(toml::Deserializer::new(content)).map_err(|e| {
+        let key = match e.path().to_string() {
+            root if root == "." => String::new(),
+            key => key,
+        };
+        let inner = e.into_inner();
+        let message = match inner.span() {
+            Some(span) => {
+                let line = content[..span.start].lines().count().max(1);
+                format!("{} (line {line})", inner.message())
+            }
+            None => inner.message().to_string(),
+        };
+        Diagnostic::new(path, key, Rule::Syntax, message)
+    })
+}
+
+/// The dimensions of a crane as the api reports them. A DH table takes
+/// the place of the lengths of the base and the arms, which are then
+/// those that reach the joints where the table puts them, while the
+/// other sizes are only drawn.
+fn dimensions(config: &RobotConfig) -> CraneDimensions {
+    let mut dimensions = CraneDimensions {
+        base_height: config.base.height.unwrap_or_default(),
         base_radius_bottom: config.base.radius_bottom,
         base_radius_top: config.base.radius_top,
         column_height: config.column.height,
         column_width: config.column.width,
         column_thickness: config.column.thickness,
-        upper_arm_length: config.upper_arm.length,
+        upper_arm_length: config.upper_arm.length.unwrap_or_default(),
         upper_arm_width: config.upper_arm.width,
         upper_arm_thickness: config.upper_arm.thickness,
-        lower_arm_length: config.lower_arm.length,
+        lower_arm_length: config.lower_arm.length.unwrap_or_default(),
         lower_arm_width: config.lower_arm.width,
         lower_arm_thickness: config.lower_arm.thickness,
         swing_joint_radius: config.swing_joint.radius,
@@ -125,49 +405,100 @@ fn create_crane_from_config(config: RobotConfig) -> Result<Crane> {
         gripper_width: config.gripper.width,
         gripper_thickness: config.gripper.thickness,
         gripper_max_open: config.gripper.max_open,
+        kinematics: config.kinematics.as_ref().map(DhTable::to_radians),
     };
 
+    if dimensions.kinematics.is_some() {
+        let (base_height, upper_arm_length, lower_arm_length) =
+            LinkOffsets::from(&dimensions).link_lengths(&dimensions);
+        dimensions.base_height = base_height;
+        dimensions.upper_arm_length = upper_arm_length;
+        dimensions.lower_arm_length = lower_arm_length;
+    }
+    dimensions
+}
+
+/// Builds a robot from a config that passed validation
+fn build_crane(config: RobotConfig) -> Crane {
+    let dimensions = dimensions(&config);
+
     let limits = CraneLimits {
-        swing_min: config.limits.swing_min,
-        swing_max: config.limits.swing_max,
-        lift_min: config.limits.lift_min,
-        lift_max: config.limits.lift_max,
-        elbow_min: config.limits.elbow_min,
-        elbow_max: config.limits.elbow_max,
-        wrist_min: config.limits.wrist_min,
-        wrist_max: config.limits.wrist_max,
-        gripper_min: config.limits.gripper_min,
-        gripper_max: config.limits.gripper_max,
+        swing: config.limits.range(Joint::Swing),
+        lift: config.limits.bounded(Joint::Lift),
+        elbow: config.limits.range(Joint::Elbow),
+        wrist: config.limits.range(Joint::Wrist),
+        gripper: config.limits.bounded(Joint::Gripper),
     };
 
-    let crane = Crane::new(config.id, dimensions, limits);
+    let motion_limits = MotionLimits {
+        profile: config.motion.profile,
+        swing: config.motion.dynamics(Joint::Swing),
+        lift: config.motion.dynamics(Joint::Lift),
+        elbow: config.motion.dynamics(Joint::Elbow),
+        wrist: config.motion.dynamics(Joint::Wrist),
+        gripper: config.motion.dynamics(Joint::Gripper),
+    };
 
-    Ok(crane)
+    Crane::new(config.id, dimensions, limits, motion_limits)
 }
 
+/// The robots described by the config directory, kept up to date as
+/// their files change
 #[derive(Debug)]
 pub struct Database {
-    cranes: HashMap<String, Crane>,
+    dir: PathBuf,
+    robots: DashMap<String, Robot>,
 }
 
 impl Database {
     pub fn setup(config_dir: &str) -> Result<Self> {
         let path = PathBuf::from(config_dir);
-        let cranes = load_robot_configs(&path)?;
-        
-        let mut crane_map = HashMap::new();
-        for crane in cranes {
-            crane_map.insert(crane.id.clone(), crane);
+        let (robots, diagnostics) = load_robot_configs(&path)?;
+        for diagnostic in diagnostics {
+            match diagnostic.is_error() {
+                true => tracing::warn!("skipping robot config: {diagnostic}"),
+                false => tracing::warn!("{diagnostic}"),
+            }
         }
-        
-        Ok(Self { cranes: crane_map })
+
+        let robot_map = DashMap::new();
+        for robot in robots {
+            robot_map.insert(robot.id().to_string(), robot);
+        }
+
+        Ok(Self {
+            dir: path,
+            robots: robot_map,
+        })
     }
-    
-    pub fn get(&self, id: &str) -> Option<&Crane> {
-        self.cranes.get(id)
+
+    /// The directory the robots are configured in
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn get(&self, id: &str) -> Option<Robot> {
+        self.robots.get(id).map(|robot| robot.clone())
+    }
+
+    pub fn get_all(&self) -> Vec<Robot> {
+        self.robots.iter().map(|robot| robot.clone()).collect()
     }
 
-    pub fn get_all(&self) -> Vec<&Crane> {
-        self.cranes.values().collect()
+    /// Adds a robot or replaces the config of one, returning its old config
+    pub fn insert(&self, robot: Robot) -> Option<Robot> {
+        self.robots.insert(robot.id().to_string(), robot)
     }
-}
\ No newline at end of file
+
+    pub fn remove(&self, id: &str) -> Option<Robot> {
+        self.robots.remove(id).map(|(_, robot)| robot)
+    }
+
+    /// The robot loaded from a config file
+    pub fn find_source(&self, path: &Path) -> Option<String> {
+        self.robots
+            .iter()
+            .find(|robot| robot.source() == Some(path))
+            .map(|robot| robot.id().to_string())
+    }
+}
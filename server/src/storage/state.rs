@@ -0,0 +1,231 @@
+//! # state
+//!
+//! keeps the joint state of robots, and the motion queue of cranes, while
+//! their actors are not running, so that a robot restarted after going
+//! idle or after the server restarts picks up where it left off. With a
+//! directory every robot is written to its own `<id>.json` file in it,
+//! without one the state only outlives the actor and not the server.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::robot::{chain::JointPositions, crane::Setpoint, models::CraneState, ID};
+
+/// What is kept of a crane between runs of its actor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedCrane {
+    pub state: CraneState,
+    /// motions yet to be completed, starting with the one in progress
+    #[serde(default)]
+    pub queue: Vec<Setpoint>,
+    #[serde(default)]
+    pub emergency_stop: bool,
+}
+
+/// What is kept of a chain robot between runs of its actor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedChain {
+    pub positions: JointPositions,
+    #[serde(default)]
+    pub emergency_stop: bool,
+}
+
+/// What is kept of a robot of any kind, told apart by its fields
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SavedRobot {
+    Crane(SavedCrane),
+    Chain(SavedChain),
+}
+
+impl From<SavedCrane> for SavedRobot {
+    fn from(crane: SavedCrane) -> Self {
+        SavedRobot::Crane(crane)
+    }
+}
+
+impl From<SavedChain> for SavedRobot {
+    fn from(robot: SavedChain) -> Self {
+        SavedRobot::Chain(robot)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct StateStore {
+    dir: Option<PathBuf>,
+    robots: DashMap<ID, SavedRobot>,
+}
+
+impl StateStore {
+    /// Opens a store writing to `dir`, creating it if needed
+    pub fn open(dir: &str) -> Result<Self> {
+        let dir = PathBuf::from(dir);
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create state directory: {}", dir.display()))?;
+        tracing::info!("persisting robot state to {}", dir.display());
+
+        Ok(Self {
+            dir: Some(dir),
+            robots: DashMap::new(),
+        })
+    }
+
+    pub fn save(&self, id: &ID, robot: &SavedRobot) {
+        if let Some(path) = self.file(id) {
+            if let Err(e) = write(&path, robot) {
+                tracing::warn!("failed to persist the state of robot {id}: {e:#}");
+            }
+        }
+        self.robots.insert(id.clone(), robot.clone());
+    }
+
+    pub fn load(&self, id: &ID) -> Option<SavedRobot> {
+        if let Some(robot) = self.robots.get(id) {
+            return Some(robot.clone());
+        }
+
+        let path = self.file(id)?;
+        if !path.exists() {
+            return None;
+        }
+        match read(&path) {
+            Ok(robot) => Some(robot),
+            Err(e) => {
+                tracing::warn!("ignoring the saved state of robot {id}: {e:#}");
+                None
+            }
+        }
+    }
+
+    /// Forgets a robot that was removed, so that a robot later added with
+    /// the same id starts afresh
+    pub fn remove(&self, id: &ID) {
+        self.robots.remove(id);
+        let Some(path) = self.file(id) else {
+            return;
+        };
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => tracing::warn!("failed to remove the state file of robot {id}: {e}"),
+        }
+    }
+
+    /// The file a robot is written to, robots whose id is not a plain file
+    /// name are only kept in memory
+    fn file(&self, id: &ID) -> Option<PathBuf> {
+        match super::validate::plain_id(id) {
+            true => Some(self.dir.as_ref()?.join(format!("{id}.json"))),
+            false => None,
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<SavedRobot> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read state file: {}", path.display()))?;
+    serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse state file: {}", path.display()))
+}
+
+/// Writes the file in one go so that a crash never leaves half of it
+fn write(path: &Path, robot: &SavedRobot) -> Result<()> {
+    let content = serde_json::to_string_pretty(robot)?;
+    let partial = path.with_extension("json.partial");
+    fs::write(&partial, content)
+        .with_context(|| format!("Failed to write state file: {}", partial.display()))?;
+    fs::rename(&partial, path)
+        .with_context(|| format!("Failed to replace state file: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory of its own for a test to keep state in
+    fn state_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("robotix-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn crane() -> SavedRobot {
+        SavedCrane {
+            state: CraneState {
+                swing_rad: 1.,
+                lift_m: 0.5,
+                ..Default::default()
+            },
+            queue: Vec::new(),
+            emergency_stop: true,
+        }
+        .into()
+    }
+
+    fn chain() -> SavedRobot {
+        SavedChain {
+            positions: JointPositions::from([("shoulder".to_string(), 0.5)]),
+            emergency_stop: false,
+        }
+        .into()
+    }
+
+    #[test]
+    fn saved_robots_are_loaded_back_by_a_new_store() {
+        let dir = state_dir("round-trip");
+        let store = StateStore::open(dir.to_str().unwrap()).unwrap();
+        store.save(&"crane".to_string(), &crane());
+        store.save(&"chain".to_string(), &chain());
+
+        let reopened = StateStore::open(dir.to_str().unwrap()).unwrap();
+        let (crane_loaded, chain_loaded) = (
+            reopened.load(&"crane".to_string()),
+            reopened.load(&"chain".to_string()),
+        );
+        let missing = reopened.load(&"missing".to_string());
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(crane_loaded, Some(crane()));
+        // a chain robot is not taken for a crane
+        assert!(matches!(chain_loaded, Some(SavedRobot::Chain(_))));
+        assert_eq!(chain_loaded, Some(chain()));
+        assert_eq!(missing, None);
+    }
+
+    #[test]
+    fn removed_robots_are_forgotten() {
+        let dir = state_dir("remove");
+        let store = StateStore::open(dir.to_str().unwrap()).unwrap();
+        let id = "crane".to_string();
+        store.save(&id, &crane());
+        assert!(dir.join("crane.json").exists());
+
+        store.remove(&id);
+        let exists = dir.join("crane.json").exists();
+        let reopened = StateStore::open(dir.to_str().unwrap()).unwrap();
+        let loaded = (store.load(&id), reopened.load(&id));
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(!exists);
+        assert_eq!(loaded, (None, None));
+    }
+
+    #[test]
+    fn ids_that_are_not_file_names_are_kept_in_memory() {
+        let dir = state_dir("memory");
+        let store = StateStore::open(dir.to_str().unwrap()).unwrap();
+        let id = "../crane".to_string();
+        store.save(&id, &crane());
+
+        let files = fs::read_dir(&dir).unwrap().count();
+        let loaded = store.load(&id);
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(files, 0);
+        assert_eq!(loaded, Some(crane()));
+    }
+}
@@ -0,0 +1,978 @@
+//! # urdf
+//!
+//! imports robots from URDF files and exports configured cranes as
+//! URDF. The crane maps onto a serial chain of five movable joints from
+//! the root link outwards: a revolute swing, a prismatic lift, a
+//! revolute elbow and wrist and a prismatic gripper.
+//! Revolute joints may also be continuous. Links joined by fixed joints
+//! are treated as one.
+//!
+//! URDF frames are Z-up, so every joint but the gripper has to turn or
+//! slide about the z axis. The sizes of the crane are taken from the
+//! visuals of the links along the chain:
+//!
+//! - the root link: a cylinder for the base
+//! - the link after the swing: a cylinder for the swing joint and a box
+//!   for the column
+//! - the links after the lift, elbow and wrist: a cylinder for the joint
+//!   and a box for the upper arm, lower arm and gripper respectively
+//!
+//! The crane lays its links out from their sizes, so visual origins are
+//! not read and joint origins are only checked against the layout. Every
+//! element that could not be mapped is reported as a warning.
+//!
+//! Exporting writes the same chain with visual and collision geometry
+//! laid out as the client draws the crane, so that an exported robot is
+//! imported as it was. URDF has no cone, the base is exported as a
+//! cylinder with the bottom radius.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::path::Path;
+
+use roxmltree::{Document, Node};
+
+use crate::robot::{
+    crane::Crane,
+    kinematics::joint_origins,
+    models::{Joint, MotionLimits},
+};
+
+use super::{
+    ArmConfig, BaseConfig, ColumnConfig, Diagnostic, GripperConfig, InvalidConfig, JointConfig,
+    JointDynamicsConfig, LimitsConfig, MotionConfig, RobotConfig, Rule,
+};
+
+/// Distance in meters within which a joint origin is taken to match the
+/// layout of the crane
+const ORIGIN_TOLERANCE: f64 = 1e-3;
+
+/// Sizes taken from the visuals of one link of the chain
+#[derive(Debug, Default, Clone, Copy)]
+struct Shapes {
+    /// `[x, y, z]` size of the first box
+    block: Option<[f64; 3]>,
+    /// radius and length of the first cylinder
+    cylinder: Option<(f64, f64)>,
+}
+
+/// The travel and speed of a joint, in degrees or meters as configs give them
+#[derive(Debug, Default, Clone, Copy)]
+struct Travel {
+    continuous: bool,
+    min: f64,
+    max: f64,
+    velocity: Option<f64>,
+}
+
+struct Import<'a> {
+    file: &'a Path,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Import<'_> {
+    fn error(&mut self, key: impl Into<String>, rule: Rule, message: impl Into<String>) {
+        self.diagnostics
+            .push(Diagnostic::new(self.file, key, rule, message));
+    }
+
+    fn unmapped(&mut self, key: impl Into<String>, message: impl Into<String>) {
+        self.diagnostics
+            .push(Diagnostic::warning(self.file, key, Rule::Unmapped, message));
+    }
+}
+
+/// Maps a URDF file onto a robot config, returning it along with the
+/// elements that could not be mapped
+pub(super) fn import(
+    file: &Path,
+    content: &str,
+) -> Result<(RobotConfig, Vec<Diagnostic>), InvalidConfig> {
+    let document = Document::parse(content).map_err(|e| {
+        Diagnostic::new(
+            file,
+            "",
+            Rule::Syntax,
+            format!("{e} (line {})", e.pos().row),
+        )
+    })?;
+    let mut import = Import {
+        file,
+        diagnostics: Vec::new(),
+    };
+
+    let robot = document.root_element();
+    if robot.tag_name().name() != "robot" {
+        import.error("", Rule::Topology, "the root element must be <robot>");
+        return Err(InvalidConfig {
+            diagnostics: import.diagnostics,
+        });
+    }
+    let id = robot.attribute("name").unwrap_or_default().to_string();
+
+    let config = map_robot(&mut import, robot, id);
+    match import.diagnostics.iter().any(Diagnostic::is_error) {
+        true => Err(InvalidConfig {
+            diagnostics: import.diagnostics,
+        }),
+        false => Ok((config, import.diagnostics)),
+    }
+}
+
+fn map_robot(import: &mut Import, robot: Node, id: String) -> RobotConfig {
+    let mut links = HashMap::new();
+    let mut joints = Vec::new();
+    for element in robot.children().filter(Node::is_element) {
+        let name = element.attribute("name").unwrap_or_default();
+        match element.tag_name().name() {
+            "link" => {
+                links.insert(name, element);
+            }
+            "joint" => joints.push(element),
+            tag => import.unmapped(key(tag, name), format!("<{tag}> elements are not used")),
+        }
+    }
+
+    let children: HashSet<&str> = joints.iter().filter_map(|joint| child_of(*joint)).collect();
+    let mut roots: Vec<&str> = links
+        .keys()
+        .copied()
+        .filter(|link| !children.contains(link))
+        .collect();
+    roots.sort();
+    let root = match roots.as_slice() {
+        [root] => *root,
+        _ => {
+            import.error(
+                "",
+                Rule::Topology,
+                format!("expected a single root link, found {}", roots.len()),
+            );
+            ""
+        }
+    };
+
+    let (slots, chain) = walk(import, &joints, root);
+    for (name, link) in &links {
+        if !slots.iter().flatten().any(|visited| visited == name) {
+            import.unmapped(key("link", name), "the link is not part of the crane");
+        }
+        map_link_children(import, *link);
+    }
+
+    let mut travel = [Travel::default(); 5];
+    for (n, joint) in Joint::ALL.into_iter().enumerate() {
+        match chain.get(n) {
+            Some(node) => travel[n] = map_joint(import, joint, *node),
+            None => {
+                import.error(
+                    "",
+                    Rule::Topology,
+                    format!(
+                        "the chain from `{root}` has {} movable joints, the {joint} joint is missing",
+                        chain.len()
+                    ),
+                );
+                break;
+            }
+        }
+    }
+    for extra in chain.iter().skip(Joint::ALL.len()) {
+        let name = extra.attribute("name").unwrap_or_default();
+        import.unmapped(
+            key("joint", name),
+            "the crane has no joints past the gripper",
+        );
+    }
+
+    let shapes: Vec<Shapes> = slots
+        .iter()
+        .enumerate()
+        .map(|(n, slot)| shapes_of(import, slot, &links, n))
+        .collect();
+    let config = build_config(import, id, &slots, &shapes, &travel);
+
+    if chain.len() >= Joint::ALL.len() {
+        check_origins(import, &config, &chain);
+    }
+    config
+}
+
+/// Follows the chain of joints from the root link, returning the links
+/// after every movable joint and the movable joints in order
+fn walk<'a, 'i>(
+    import: &mut Import,
+    joints: &[Node<'a, 'i>],
+    root: &'a str,
+) -> (Vec<Vec<&'a str>>, Vec<Node<'a, 'i>>) {
+    let mut slots = vec![vec![root]];
+    let mut chain = Vec::new();
+    let mut link = root;
+    let mut visited = HashSet::new();
+
+    while visited.insert(link) {
+        let mut children: Vec<Node> = joints
+            .iter()
+            .copied()
+            .filter(|joint| {
+                joint
+                    .children()
+                    .any(|c| c.has_tag_name("parent") && c.attribute("link") == Some(link))
+            })
+            .collect();
+        // follow movable joints ahead of fixed ones
+        children.sort_by_key(|joint| joint.attribute("type") == Some("fixed"));
+
+        let Some((next, rest)) = children.split_first() else {
+            break;
+        };
+        for branch in rest {
+            let name = branch.attribute("name").unwrap_or_default();
+            import.unmapped(
+                key("joint", name),
+                format!(
+                    "the crane is a single chain, only `{}` is followed from `{link}`",
+                    next.attribute("name").unwrap_or_default()
+                ),
+            );
+        }
+
+        let Some(child) = child_of(*next) else {
+            break;
+        };
+        if next.attribute("type") == Some("fixed") {
+            if let Some(slot) = slots.last_mut() {
+                slot.push(child);
+            }
+        } else {
+            chain.push(*next);
+            slots.push(vec![child]);
+        }
+        link = child;
+    }
+    (slots, chain)
+}
+
+fn map_joint(import: &mut Import, joint: Joint, node: Node) -> Travel {
+    let name = node.attribute("name").unwrap_or_default();
+    let kind = node.attribute("type").unwrap_or_default();
+    let expected = match joint.is_revolute() {
+        true => ["revolute", "continuous"].contains(&kind),
+        false => kind == "prismatic",
+    };
+    if !expected {
+        let wanted = match joint.is_revolute() {
+            true => "revolute or continuous",
+            false => "prismatic",
+        };
+        import.error(
+            key("joint", name),
+            Rule::Topology,
+            format!("the {joint} joint must be {wanted}, not {kind}"),
+        );
+        return Travel::default();
+    }
+
+    for child in node.children().filter(Node::is_element) {
+        match child.tag_name().name() {
+            "parent" | "child" | "origin" | "axis" | "limit" => {}
+            tag => import.unmapped(
+                format!("{}/{tag}", key("joint", name)),
+                format!("<{tag}> is not used by the crane"),
+            ),
+        }
+    }
+
+    // every joint but the gripper moves about the vertical axis, a joint
+    // about -z moves the other way
+    let axis = node
+        .children()
+        .find(|c| c.has_tag_name("axis"))
+        .and_then(|axis| floats(axis.attribute("xyz")?))
+        .unwrap_or(vec![1., 0., 0.]);
+    let sign = match axis.as_slice() {
+        [x, y, z] if joint != Joint::Gripper && x.abs() < 1e-9 && y.abs() < 1e-9 => z.signum(),
+        _ if joint == Joint::Gripper => 1.,
+        _ => {
+            import.error(
+                format!("{}/axis", key("joint", name)),
+                Rule::Topology,
+                format!("the {joint} joint must move about the z axis"),
+            );
+            1.
+        }
+    };
+
+    // degrees for revolute joints, prismatic ones stay in meters
+    let scale = match joint.is_revolute() {
+        true => 1f64.to_degrees(),
+        false => 1.,
+    };
+    let limit = node.children().find(|c| c.has_tag_name("limit"));
+    // URDF requires an effort on every limit, zero is taken to mean none
+    if let Some(limit) = limit {
+        let effort = limit
+            .attribute("effort")
+            .and_then(|e| e.parse::<f64>().ok());
+        if effort.is_some_and(|effort| effort != 0.) {
+            import.unmapped(
+                format!("{}/limit@effort", key("joint", name)),
+                "the crane has no effort limits",
+            );
+        }
+    }
+    // converting from radians leaves noise in the last digits
+    let convert = |value: f64| (value * scale * 1e6).round() / 1e6;
+    let velocity = limit
+        .and_then(|limit| limit.attribute("velocity")?.parse::<f64>().ok())
+        .map(convert);
+
+    if kind == "continuous" {
+        return Travel {
+            continuous: true,
+            velocity,
+            ..Default::default()
+        };
+    }
+
+    let bound = |attribute: &str| {
+        limit
+            .and_then(|limit| limit.attribute(attribute)?.parse::<f64>().ok())
+            .map(|value| convert(value * sign))
+    };
+    let (Some(lower), Some(upper)) = (bound("lower"), bound("upper")) else {
+        import.error(
+            format!("{}/limit", key("joint", name)),
+            Rule::Required,
+            format!("the {joint} joint needs a <limit> with a lower and an upper bound"),
+        );
+        return Travel::default();
+    };
+
+    Travel {
+        continuous: false,
+        min: lower.min(upper),
+        max: lower.max(upper),
+        velocity,
+    }
+}
+
+fn map_link_children(import: &mut Import, link: Node) {
+    let name = link.attribute("name").unwrap_or_default();
+    let mut reported = HashSet::new();
+    for child in link.children().filter(Node::is_element) {
+        let tag = child.tag_name().name();
+        if tag != "visual" && reported.insert(tag) {
+            import.unmapped(
+                format!("{}/{tag}", key("link", name)),
+                format!("<{tag}> is not used by the crane"),
+            );
+        }
+    }
+}
+
+/// Collects the first box and cylinder among the visuals of the links
+/// of a slot, reporting the rest
+fn shapes_of(import: &mut Import, slot: &[&str], links: &HashMap<&str, Node>, n: usize) -> Shapes {
+    let mut shapes = Shapes::default();
+    for name in slot {
+        let Some(link) = links.get(name) else {
+            continue;
+        };
+        let visuals = link.children().filter(|c| c.has_tag_name("visual"));
+        for (v, visual) in visuals.enumerate() {
+            let at = format!("{}/visual[{v}]", key("link", name));
+            let Some(geometry) = visual
+                .children()
+                .find(|c| c.has_tag_name("geometry"))
+                .and_then(|g| g.children().find(Node::is_element))
+            else {
+                import.unmapped(at, "the visual has no geometry");
+                continue;
+            };
+
+            let tag = geometry.tag_name().name();
+            match tag {
+                "box" if shapes.block.is_none() && n > 0 => {
+                    match geometry.attribute("size").and_then(floats).as_deref() {
+                        Some(&[x, y, z]) => shapes.block = Some([x, y, z]),
+                        _ => import.error(at, Rule::Syntax, "a box needs a size of three numbers"),
+                    }
+                }
+                "cylinder" if shapes.cylinder.is_none() && n < 5 => {
+                    let radius = geometry.attribute("radius").and_then(|r| r.parse().ok());
+                    let length = geometry.attribute("length").and_then(|l| l.parse().ok());
+                    match (radius, length) {
+                        (Some(radius), Some(length)) => shapes.cylinder = Some((radius, length)),
+                        _ => {
+                            import.error(at, Rule::Syntax, "a cylinder needs a radius and a length")
+                        }
+                    }
+                }
+                _ => import.unmapped(at, format!("the {tag} geometry is not used by the crane")),
+            }
+        }
+    }
+    shapes
+}
+
+fn build_config(
+    import: &mut Import,
+    id: String,
+    slots: &[Vec<&str>],
+    shapes: &[Shapes],
+    travel: &[Travel; 5],
+) -> RobotConfig {
+    let mut cylinder = |n: usize, part: &str| {
+        let shape = shapes.get(n).and_then(|shapes| shapes.cylinder);
+        if shape.is_none() {
+            let link = slots
+                .get(n)
+                .and_then(|slot| slot.first())
+                .copied()
+                .unwrap_or_default();
+            import.error(
+                key("link", link),
+                Rule::Required,
+                format!("the link needs a cylinder visual for the {part}"),
+            );
+        }
+        shape.unwrap_or_default()
+    };
+    let base = cylinder(0, "base");
+    let swing_joint = cylinder(1, "swing joint");
+    let lift_joint = cylinder(2, "lift joint");
+    let elbow_joint = cylinder(3, "elbow joint");
+    let wrist_joint = cylinder(4, "wrist joint");
+
+    let mut block = |n: usize, part: &str| {
+        let shape = shapes.get(n).and_then(|shapes| shapes.block);
+        if shape.is_none() {
+            let link = slots
+                .get(n)
+                .and_then(|slot| slot.first())
+                .copied()
+                .unwrap_or_default();
+            import.error(
+                key("link", link),
+                Rule::Required,
+                format!("the link needs a box visual for the {part}"),
+            );
+        }
+        shape.unwrap_or_default()
+    };
+    let column = block(1, "column");
+    let upper_arm = block(2, "upper arm");
+    let lower_arm = block(3, "lower arm");
+    let gripper = block(4, "gripper");
+
+    let [swing, lift, elbow, wrist, gripper_travel] = *travel;
+    let range = |travel: Travel| match travel.continuous {
+        true => (None, None),
+        false => (Some(travel.min), Some(travel.max)),
+    };
+    let dynamics = |joint: Joint, travel: Travel| {
+        let defaults = MotionLimits::default().joint(joint);
+        // the defaults are in radians, configs give revolute joints in degrees
+        let scale = match joint.is_revolute() {
+            true => 1f64.to_degrees(),
+            false => 1.,
+        };
+        travel.velocity.map(|max_velocity| JointDynamicsConfig {
+            max_velocity,
+            max_acceleration: defaults.max_acceleration * scale,
+            max_jerk: defaults.max_jerk * scale,
+            jog_increment: None,
+        })
+    };
+
+    RobotConfig {
+        id,
+        base: BaseConfig {
+            height: Some(base.1),
+            radius_bottom: base.0,
+            radius_top: base.0,
+        },
+        column: ColumnConfig {
+            height: column[2],
+            width: column[0],
+            thickness: column[1],
+        },
+        upper_arm: arm(upper_arm),
+        lower_arm: arm(lower_arm),
+        swing_joint: joint(swing_joint),
+        lift_joint: joint(lift_joint),
+        elbow_joint: joint(elbow_joint),
+        wrist_joint: joint(wrist_joint),
+        gripper: GripperConfig {
+            length: gripper[0],
+            width: gripper[1],
+            thickness: gripper[2],
+            max_open: gripper_travel.max,
+        },
+        limits: LimitsConfig {
+            continuous: Joint::ALL
+                .into_iter()
+                .zip(travel)
+                .filter_map(|(joint, travel)| travel.continuous.then_some(joint))
+                .collect(),
+            swing_min: range(swing).0,
+            swing_max: range(swing).1,
+            lift_min: range(lift).0,
+            lift_max: range(lift).1,
+            elbow_min: range(elbow).0,
+            elbow_max: range(elbow).1,
+            wrist_min: range(wrist).0,
+            wrist_max: range(wrist).1,
+            gripper_min: range(gripper_travel).0,
+            gripper_max: range(gripper_travel).1,
+        },
+        motion: MotionConfig {
+            profile: Default::default(),
+            swing: dynamics(Joint::Swing, swing),
+            lift: dynamics(Joint::Lift, lift),
+            elbow: dynamics(Joint::Elbow, elbow),
+            wrist: dynamics(Joint::Wrist, wrist),
+            gripper: dynamics(Joint::Gripper, gripper_travel),
+        },
+        kinematics: None,
+    }
+}
+
+fn arm(block: [f64; 3]) -> ArmConfig {
+    ArmConfig {
+        length: Some(block[0]),
+        width: block[1],
+        thickness: block[2],
+    }
+}
+
+fn joint((radius, height): (f64, f64)) -> JointConfig {
+    JointConfig { radius, height }
+}
+
+/// Reports joint origins that differ from where the crane places the
+/// joint given the sizes of its links
+fn check_origins(import: &mut Import, config: &RobotConfig, chain: &[Node]) {
+    let expected = joint_origins(&super::dimensions(config));
+    for (node, expected) in chain.iter().zip(expected) {
+        let name = node.attribute("name").unwrap_or_default();
+        let origin = node.children().find(|c| c.has_tag_name("origin"));
+        let xyz = origin
+            .and_then(|origin| floats(origin.attribute("xyz")?))
+            .unwrap_or(vec![0., 0., 0.]);
+        let rpy = origin
+            .and_then(|origin| floats(origin.attribute("rpy")?))
+            .unwrap_or(vec![0., 0., 0.]);
+
+        let matches = xyz.len() == 3
+            && xyz
+                .iter()
+                .zip(expected)
+                .all(|(a, b)| (a - b).abs() < ORIGIN_TOLERANCE);
+        if !matches {
+            import.unmapped(
+                format!("{}/origin@xyz", key("joint", name)),
+                format!(
+                    "the origin {xyz:?} differs from {expected:?} given by the link sizes, which is used instead"
+                ),
+            );
+        }
+        if rpy.iter().any(|angle| angle.abs() > 1e-9) {
+            import.unmapped(
+                format!("{}/origin@rpy", key("joint", name)),
+                "the crane does not rotate its joint frames",
+            );
+        }
+    }
+}
+
+/// A primitive making up the geometry of a link
+enum Shape {
+    /// `[x, y, z]` size
+    Block([f64; 3]),
+    /// radius and length
+    Cylinder(f64, f64),
+}
+
+/// Writes a robot as a URDF document, with a link for every part of the
+/// crane between its joints
+pub fn export(crane: &Crane) -> String {
+    let d = crane.dimensions();
+    let tip = d.wrist_joint_radius / 2. + d.gripper_length / 2. - d.gripper_thickness;
+    let forearm_z = -(d.elbow_joint_height / 2. + d.lower_arm_thickness);
+    let links = [
+        (
+            "base",
+            vec![(
+                Shape::Cylinder(d.base_radius_bottom, d.base_height),
+                [0., 0., d.base_height / 2.],
+            )],
+        ),
+        (
+            "column",
+            vec![
+                (
+                    Shape::Cylinder(d.swing_joint_radius, d.swing_joint_height),
+                    [0., 0., 0.],
+                ),
+                (
+                    Shape::Block([d.column_width, d.column_thickness, d.column_height]),
+                    [0., 0., d.column_height / 2.],
+                ),
+            ],
+        ),
+        (
+            "upper_arm",
+            vec![
+                (
+                    Shape::Cylinder(d.lift_joint_radius, d.lift_joint_height),
+                    [0., 0., d.upper_arm_thickness / 2.],
+                ),
+                (
+                    Shape::Block([d.upper_arm_length, d.upper_arm_width, d.upper_arm_thickness]),
+                    [
+                        d.column_width / 2. + d.column_thickness / 2. + d.upper_arm_length / 2.,
+                        0.,
+                        d.upper_arm_thickness / 2.,
+                    ],
+                ),
+            ],
+        ),
+        (
+            "lower_arm",
+            vec![
+                (
+                    Shape::Cylinder(d.elbow_joint_radius, d.elbow_joint_height),
+                    [0., 0., 0.],
+                ),
+                (
+                    Shape::Block([d.lower_arm_length, d.lower_arm_width, d.lower_arm_thickness]),
+                    [
+                        d.lower_arm_length / 2.,
+                        0.,
+                        forearm_z + d.lower_arm_thickness / 2.,
+                    ],
+                ),
+            ],
+        ),
+        (
+            "gripper",
+            vec![
+                (
+                    Shape::Cylinder(d.wrist_joint_radius, d.wrist_joint_height),
+                    [0., 0., 0.],
+                ),
+                (
+                    Shape::Block([d.gripper_length, d.gripper_width, d.gripper_thickness]),
+                    [
+                        tip,
+                        0.,
+                        -(d.wrist_joint_height / 2. + d.gripper_thickness / 2.),
+                    ],
+                ),
+            ],
+        ),
+        // the moving jaw, opening from the back of the gripper
+        (
+            "jaw",
+            vec![(
+                Shape::Block([0.02, 0.08, 0.08]),
+                [
+                    -d.gripper_length / 2.,
+                    0.,
+                    -(d.gripper_thickness / 2. + 0.015),
+                ],
+            )],
+        ),
+    ];
+
+    let mut urdf = String::new();
+    let _ = writeln!(urdf, r#"<?xml version="1.0"?>"#);
+    let _ = writeln!(urdf, r#"<robot name="{}">"#, escape(&crane.id));
+    for (name, shapes) in &links {
+        write_link(&mut urdf, name, shapes);
+    }
+    let origins = joint_origins(d);
+    for (n, joint) in Joint::ALL.into_iter().enumerate() {
+        write_joint(
+            &mut urdf,
+            crane,
+            joint,
+            links[n].0,
+            links[n + 1].0,
+            origins[n],
+        );
+    }
+    urdf.push_str("</robot>\n");
+    urdf
+}
+
+fn write_link(urdf: &mut String, name: &str, shapes: &[(Shape, [f64; 3])]) {
+    let _ = writeln!(urdf, r#"  <link name="{name}">"#);
+    for element in ["visual", "collision"] {
+        for (shape, [x, y, z]) in shapes {
+            let geometry = match shape {
+                Shape::Block([sx, sy, sz]) => format!(r#"<box size="{sx} {sy} {sz}"/>"#),
+                Shape::Cylinder(radius, length) => {
+                    format!(r#"<cylinder radius="{radius}" length="{length}"/>"#)
+                }
+            };
+            let _ = writeln!(urdf, "    <{element}>");
+            let _ = writeln!(urdf, r#"      <origin xyz="{x} {y} {z}"/>"#);
+            let _ = writeln!(urdf, "      <geometry>{geometry}</geometry>");
+            let _ = writeln!(urdf, "    </{element}>");
+        }
+    }
+    let _ = writeln!(urdf, "  </link>");
+}
+
+fn write_joint(
+    urdf: &mut String,
+    crane: &Crane,
+    joint: Joint,
+    parent: &str,
+    child: &str,
+    [x, y, z]: [f64; 3],
+) {
+    let range = crane.limits().range(joint);
+    let kind = match (joint.is_revolute(), range) {
+        (true, Some(_)) => "revolute",
+        (true, None) => "continuous",
+        (false, _) => "prismatic",
+    };
+    // every joint but the gripper moves about the vertical axis
+    let axis = match joint {
+        Joint::Gripper => "1 0 0",
+        _ => "0 0 1",
+    };
+    let velocity = crane.motion_limits().joint(joint).max_velocity;
+
+    let _ = writeln!(urdf, r#"  <joint name="{joint}" type="{kind}">"#);
+    let _ = writeln!(urdf, r#"    <parent link="{parent}"/>"#);
+    let _ = writeln!(urdf, r#"    <child link="{child}"/>"#);
+    let _ = writeln!(urdf, r#"    <origin xyz="{x} {y} {z}"/>"#);
+    let _ = writeln!(urdf, r#"    <axis xyz="{axis}"/>"#);
+    match range {
+        Some(range) => {
+            let (lower, upper) = (range.min, range.max);
+            let _ = writeln!(
+                urdf,
+                r#"    <limit lower="{lower}" upper="{upper}" effort="0" velocity="{velocity}"/>"#
+            );
+        }
+        None => {
+            let _ = writeln!(urdf, r#"    <limit effort="0" velocity="{velocity}"/>"#);
+        }
+    }
+    let _ = writeln!(urdf, "  </joint>");
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn child_of<'a>(joint: Node<'a, '_>) -> Option<&'a str> {
+    joint
+        .children()
+        .find(|c| c.has_tag_name("child"))?
+        .attribute("link")
+}
+
+fn floats(value: &str) -> Option<Vec<f64>> {
+    value
+        .split_whitespace()
+        .map(|number| number.parse().ok())
+        .collect()
+}
+
+fn key(tag: &str, name: &str) -> String {
+    match name {
+        "" => tag.to_string(),
+        name => format!("{tag}[{name}]"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+    use super::*;
+    use crate::robot::models::{CraneDimensions, Range};
+    use crate::storage::check_robot_config;
+
+    const URDF: &str = r#"<?xml version="1.0"?>
+<robot name="urdf-bot">
+  <link name="base">
+    <visual><geometry><cylinder radius="0.35" length="0.1"/></geometry></visual>
+  </link>
+  <link name="column">
+    <visual><geometry><cylinder radius="0.13" length="0.1"/></geometry></visual>
+    <visual><geometry><box size="0.18 0.05 2.0"/></geometry></visual>
+  </link>
+  <link name="carriage">
+    <visual><geometry><cylinder radius="0.09" length="0.08"/></geometry></visual>
+    <visual><geometry><box size="0.75 0.18 0.04"/></geometry></visual>
+  </link>
+  <link name="forearm">
+    <visual><geometry><cylinder radius="0.08" length="0.04"/></geometry></visual>
+    <visual><geometry><box size="0.5 0.12 0.03"/></geometry></visual>
+  </link>
+  <link name="hand">
+    <visual><geometry><cylinder radius="0.07" length="0.02"/></geometry></visual>
+    <visual><geometry><box size="0.2 0.08 0.05"/></geometry></visual>
+  </link>
+  <link name="finger"/>
+  <link name="camera"/>
+  <joint name="swing" type="continuous">
+    <parent link="base"/><child link="column"/>
+    <origin xyz="0 0 0.1"/><axis xyz="0 0 1"/>
+    <limit velocity="0.785398" effort="100"/>
+  </joint>
+  <joint name="lift" type="prismatic">
+    <parent link="column"/><child link="carriage"/>
+    <axis xyz="0 0 1"/><limit lower="0.2" upper="1.7" velocity="0.25"/>
+  </joint>
+  <joint name="elbow" type="revolute">
+    <parent link="carriage"/><child link="forearm"/>
+    <origin xyz="0.785 0 -0.02"/><axis xyz="0 0 1"/>
+    <limit lower="-3.14159265" upper="3.14159265" velocity="1.0472"/>
+  </joint>
+  <joint name="wrist" type="revolute">
+    <parent link="forearm"/><child link="hand"/>
+    <origin xyz="0.43 0 -0.06"/><axis xyz="0 0 -1"/>
+    <limit lower="-1.5708" upper="3.14159265" velocity="1.5708"/>
+  </joint>
+  <joint name="grip" type="prismatic">
+    <parent link="hand"/><child link="finger"/>
+    <origin xyz="0.085 0 -0.06"/><axis xyz="0 1 0"/>
+    <limit lower="0" upper="0.2" velocity="0.1"/>
+  </joint>
+  <joint name="camera_mount" type="fixed">
+    <parent link="column"/><child link="camera"/>
+    <origin xyz="0 0 1"/>
+  </joint>
+  <transmission name="t1"/>
+</robot>
+"#;
+
+    /// Close enough for values that went through degrees rounded to six
+    /// decimals
+    const EPSILON: f64 = 1e-5;
+
+    fn found(diagnostics: &[Diagnostic]) -> Vec<(&str, Rule)> {
+        diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.key.as_str(), diagnostic.rule))
+            .collect()
+    }
+
+    fn close(range: Option<Range>, min: f64, max: f64) -> bool {
+        range.is_some_and(|range| {
+            (range.min - min).abs() < EPSILON && (range.max - max).abs() < EPSILON
+        })
+    }
+
+    #[test]
+    fn import_maps_the_chain_onto_the_crane() {
+        let file = Path::new("urdf-bot.urdf");
+        let (config, diagnostics) = import(file, URDF).unwrap();
+        assert_eq!(
+            found(&diagnostics),
+            vec![
+                ("transmission[t1]", Rule::Unmapped),
+                ("joint[camera_mount]", Rule::Unmapped),
+                ("link[camera]", Rule::Unmapped),
+                ("joint[swing]/limit@effort", Rule::Unmapped),
+            ]
+        );
+
+        let crane = check_robot_config(file, config).unwrap();
+        assert_eq!(crane.id, "urdf-bot");
+
+        let d = crane.dimensions();
+        assert_eq!(d.base_height, 0.1);
+        assert_eq!(d.base_radius_bottom, 0.35);
+        assert_eq!(d.column_height, 2.0);
+        assert_eq!(d.upper_arm_length, 0.75);
+        assert_eq!(d.lower_arm_length, 0.5);
+        assert_eq!(d.wrist_joint_radius, 0.07);
+        assert_eq!(d.gripper_length, 0.2);
+
+        let limits = crane.limits();
+        assert_eq!(limits.swing, None);
+        assert_eq!(limits.lift, Range::new(0.2, 1.7));
+        assert!(close(limits.elbow, -PI, PI));
+        // the wrist turns about -z, so its limits are mirrored
+        assert!(close(limits.wrist, -PI, FRAC_PI_2));
+        assert_eq!(limits.gripper, Range::new(0., 0.2));
+
+        let motion = crane.motion_limits();
+        let defaults = MotionLimits::default();
+        assert!((motion.swing.max_velocity - FRAC_PI_4).abs() < EPSILON);
+        assert!((motion.swing.max_acceleration - defaults.swing.max_acceleration).abs() < EPSILON);
+        assert_eq!(motion.lift.max_velocity, 0.25);
+        assert_eq!(motion.lift.max_acceleration, defaults.lift.max_acceleration);
+    }
+
+    #[test]
+    fn import_rejects_a_revolute_lift() {
+        let content = URDF.replace(
+            r#"name="lift" type="prismatic""#,
+            r#"name="lift" type="revolute""#,
+        );
+        let error = import(Path::new("urdf-bot.urdf"), &content).unwrap_err();
+        let errors: Vec<_> = error
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.is_error())
+            .collect();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].key, "joint[lift]");
+        assert_eq!(errors[0].rule, Rule::Topology);
+    }
+
+    #[test]
+    fn an_exported_crane_is_imported_as_it_was() {
+        let file = Path::new("standard-bot.toml");
+        let config = toml::from_str(include_str!("../../config/robot-1.toml")).unwrap();
+        let crane = check_robot_config(file, config).unwrap();
+
+        let file = Path::new("standard-bot.urdf");
+        let (config, diagnostics) = import(file, &export(&crane)).unwrap();
+        // the collision geometry repeats the visuals and is not read back
+        assert!(diagnostics
+            .iter()
+            .all(|diagnostic| diagnostic.rule == Rule::Unmapped));
+        let imported = check_robot_config(file, config).unwrap();
+
+        assert_eq!(imported.id, crane.id);
+        // URDF has no cone, the base comes back as a cylinder
+        let dimensions = CraneDimensions {
+            base_radius_top: crane.dimensions().base_radius_bottom,
+            ..crane.dimensions().clone()
+        };
+        assert_eq!(imported.dimensions(), &dimensions);
+
+        let (limits, imported_limits) = (crane.limits(), imported.limits());
+        for joint in Joint::ALL {
+            match limits.range(joint) {
+                Some(range) => assert!(close(imported_limits.range(joint), range.min, range.max)),
+                None => assert_eq!(imported_limits.range(joint), None),
+            }
+        }
+
+        let (motion, imported_motion) = (crane.motion_limits(), imported.motion_limits());
+        assert_eq!(imported_motion.profile, motion.profile);
+        for joint in Joint::ALL {
+            let (a, b) = (motion.joint(joint), imported_motion.joint(joint));
+            assert!((a.max_velocity - b.max_velocity).abs() < EPSILON);
+            assert!((a.max_acceleration - b.max_acceleration).abs() < EPSILON);
+            assert!((a.max_jerk - b.max_jerk).abs() < EPSILON);
+        }
+    }
+}
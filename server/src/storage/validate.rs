@@ -0,0 +1,968 @@
+//! # validate
+//!
+//! checks robot configs before they are turned into robots. Every problem
+//! found is reported as a diagnostic naming the file, the TOML key it was
+//! found at and the rule it breaks, so that a file can be fixed in one go
+//! rather than one error at a time.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::robot::chain::JointKind;
+use crate::robot::kinematics::LinkOffsets;
+use crate::robot::models::{DhConvention, Joint};
+
+use super::{ChainConfig, RobotConfig};
+
+/// The rule a config breaks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    /// the file could not be read
+    Unreadable,
+    /// the file is not valid TOML or does not match the config format
+    Syntax,
+    /// a value that must be given is empty
+    Required,
+    /// an id that can not be used in urls and file names
+    InvalidId,
+    /// a length, or a motion limit, that is not a number greater than zero
+    Positive,
+    /// a joint bound or kinematic parameter that is not a finite number
+    Finite,
+    /// a joint whose min is above its max
+    MinAboveMax,
+    /// a joint that needs a min and a max, or to be listed as continuous
+    BoundsRequired,
+    /// a joint that can not be continuous, or declares bounds while it is
+    Continuous,
+    /// a lift range that does not fit on the column
+    WithinColumn,
+    /// a gripper range wider than the gripper opens
+    WithinGripper,
+    /// an id already used by another config file
+    DuplicateId,
+    /// an element of a URDF file that does not match the crane
+    Topology,
+    /// an element of a URDF file that has no counterpart in the crane
+    Unmapped,
+    /// a chain joint named like another joint of the chain
+    DuplicateName,
+    /// a chain joint placed in more than one way, or about an axis it can
+    /// not move about, or a crane DH table the crane can not follow or
+    /// given along with the lengths it takes the place of
+    Placement,
+}
+
+/// Whether a problem keeps the robot from loading
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    /// the robot loads, but the config does not mean quite what it says
+    Warning,
+}
+
+impl Rule {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Rule::Unreadable => "unreadable",
+            Rule::Syntax => "syntax",
+            Rule::Required => "required",
+            Rule::InvalidId => "invalid_id",
+            Rule::Positive => "positive",
+            Rule::Finite => "finite",
+            Rule::MinAboveMax => "min_above_max",
+            Rule::BoundsRequired => "bounds_required",
+            Rule::Continuous => "continuous",
+            Rule::WithinColumn => "within_column",
+            Rule::WithinGripper => "within_gripper",
+            Rule::DuplicateId => "duplicate_id",
+            Rule::Topology => "topology",
+            Rule::Unmapped => "unmapped",
+            Rule::DuplicateName => "duplicate_name",
+            Rule::Placement => "placement",
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A problem with a config file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    /// dotted path of the TOML key at fault, empty for the whole file
+    pub key: String,
+    pub rule: Rule,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(
+        file: &Path,
+        key: impl Into<String>,
+        rule: Rule,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            file: file.to_path_buf(),
+            key: key.into(),
+            rule,
+            severity: Severity::Error,
+            message: message.into(),
+        }
+    }
+
+    pub fn warning(
+        file: &Path,
+        key: impl Into<String>,
+        rule: Rule,
+        message: impl Into<String>,
+    ) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::new(file, key, rule, message)
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.severity == Severity::Warning {
+            write!(f, "warning: ")?;
+        }
+        write!(f, "{}: ", self.file.display())?;
+        if !self.key.is_empty() {
+            write!(f, "{}: ", self.key)?;
+        }
+        write!(f, "{} [{}]", self.message, self.rule)
+    }
+}
+
+/// A config file that can not be loaded, along with everything wrong
+/// with it
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub struct InvalidConfig {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl fmt::Display for InvalidConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages: Vec<String> = self.diagnostics.iter().map(|d| d.to_string()).collect();
+        f.write_str(&messages.join("; "))
+    }
+}
+
+impl From<Diagnostic> for InvalidConfig {
+    fn from(diagnostic: Diagnostic) -> Self {
+        Self {
+            diagnostics: vec![diagnostic],
+        }
+    }
+}
+
+/// Collects the problems found in a single config file
+struct Report<'a> {
+    file: &'a Path,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Report<'_> {
+    fn add(&mut self, key: impl Into<String>, rule: Rule, message: impl Into<String>) {
+        self.diagnostics
+            .push(Diagnostic::new(self.file, key, rule, message));
+    }
+
+    fn positive(&mut self, key: &str, value: f64) {
+        if !value.is_finite() || value <= 0. {
+            self.add(
+                key,
+                Rule::Positive,
+                format!("must be greater than zero, not {value}"),
+            );
+        }
+    }
+
+    /// Reports a value that is not a finite number, returning whether it is
+    fn finite(&mut self, key: impl Into<String>, value: f64) -> bool {
+        if !value.is_finite() {
+            self.add(
+                key,
+                Rule::Finite,
+                format!("must be a finite number, not {value}"),
+            );
+        }
+        value.is_finite()
+    }
+}
+
+/// Checks a parsed config against every rule, returning all of the
+/// problems found in it
+pub(super) fn validate(file: &Path, config: &RobotConfig) -> Vec<Diagnostic> {
+    let mut report = Report {
+        file,
+        diagnostics: Vec::new(),
+    };
+
+    id(&mut report, &config.id);
+    dimensions(&mut report, config);
+    limits(&mut report, config);
+    motion(&mut report, config);
+    kinematics(&mut report, config);
+
+    report.diagnostics
+}
+
+/// Checks a parsed chain config against every rule, returning all of
+/// the problems found in it
+pub(super) fn validate_chain(file: &Path, config: &ChainConfig) -> Vec<Diagnostic> {
+    let mut report = Report {
+        file,
+        diagnostics: Vec::new(),
+    };
+
+    id(&mut report, &config.id);
+    if config.joints.is_empty() {
+        report.add(
+            "joints",
+            Rule::Required,
+            "the chain needs at least one joint",
+        );
+    }
+
+    for (n, joint) in config.joints.iter().enumerate() {
+        let key = |field: &str| format!("joints[{n}].{field}");
+        if joint.name.trim().is_empty() {
+            report.add(key("name"), Rule::Required, "the joint needs a name");
+        } else if config.joints[..n]
+            .iter()
+            .any(|other| other.name == joint.name)
+        {
+            report.add(
+                key("name"),
+                Rule::DuplicateName,
+                format!("another joint is already named `{}`", joint.name),
+            );
+        }
+
+        let placements = [
+            ("dh", joint.dh.is_some()),
+            ("mdh", joint.mdh.is_some()),
+            ("origin", joint.origin.is_some()),
+        ];
+        let placed: Vec<&str> = placements
+            .iter()
+            .filter_map(|(field, given)| given.then_some(*field))
+            .collect();
+        if placed.len() > 1 {
+            report.add(
+                key(placed[0]),
+                Rule::Placement,
+                format!(
+                    "the joint is placed by one of dh, mdh or origin, not by {}",
+                    placed.join(" and ")
+                ),
+            );
+        }
+        for (field, dh) in [("dh", joint.dh), ("mdh", joint.mdh)] {
+            let Some(dh) = dh else {
+                continue;
+            };
+            let values = [
+                ("a", dh.a),
+                ("alpha", dh.alpha),
+                ("d", dh.d),
+                ("theta", dh.theta),
+            ];
+            for (parameter, value) in values {
+                report.finite(key(&format!("{field}.{parameter}")), value);
+            }
+        }
+        if let Some(origin) = &joint.origin {
+            for (field, values) in [("xyz", origin.xyz), ("rpy", origin.rpy)] {
+                if let Some(value) = values.into_iter().find(|value| !value.is_finite()) {
+                    report.finite(key(&format!("origin.{field}")), value);
+                }
+            }
+        }
+        if let Some(axis) = joint.axis {
+            if let Some(value) = axis.into_iter().find(|value| !value.is_finite()) {
+                report.finite(key("axis"), value);
+            } else if joint.dh.is_some() || joint.mdh.is_some() {
+                report.add(
+                    key("axis"),
+                    Rule::Placement,
+                    "joints placed by dh or mdh move about their z axis and can not declare an axis",
+                );
+            } else if axis.iter().all(|value| *value == 0.) {
+                report.add(key("axis"), Rule::Placement, "the axis can not be zero");
+            }
+        }
+
+        if joint.continuous {
+            if joint.kind == JointKind::Prismatic {
+                report.add(
+                    key("continuous"),
+                    Rule::Continuous,
+                    format!(
+                        "the {} joint is prismatic and can not be continuous",
+                        joint.name
+                    ),
+                );
+            } else if joint.min.is_some() || joint.max.is_some() {
+                report.add(
+                    key("min"),
+                    Rule::Continuous,
+                    format!(
+                        "the {} joint is continuous and can not declare min or max",
+                        joint.name
+                    ),
+                );
+            }
+        } else {
+            match (joint.min, joint.max) {
+                // evaluated in order, so that both bounds are reported
+                (Some(min), Some(max))
+                    if !(report.finite(key("min"), min) & report.finite(key("max"), max)) => {}
+                (Some(min), Some(max)) if min > max => report.add(
+                    key("min"),
+                    Rule::MinAboveMax,
+                    format!("min ({min}) is above max ({max})"),
+                ),
+                (Some(_), Some(_)) => {}
+                (min, _) => report.add(
+                    key(if min.is_some() { "max" } else { "min" }),
+                    Rule::BoundsRequired,
+                    format!(
+                        "the {} joint requires both min and max, or to be continuous",
+                        joint.name
+                    ),
+                ),
+            }
+        }
+
+        let dynamics = [
+            ("max_velocity", joint.max_velocity),
+            ("max_acceleration", joint.max_acceleration),
+            ("max_jerk", joint.max_jerk),
+            ("jog_increment", joint.jog_increment),
+        ];
+        for (field, value) in dynamics {
+            if let Some(value) = value {
+                report.positive(&key(field), value);
+            }
+        }
+    }
+
+    report.diagnostics
+}
+
+fn id(report: &mut Report, id: &str) {
+    if id.trim().is_empty() {
+        report.add("id", Rule::Required, "the robot needs an id");
+    } else if !plain_id(id) {
+        report.add(
+            "id",
+            Rule::InvalidId,
+            format!(
+                "`{id}` may only contain letters, digits, '-', '_' and '.', and not start with '.'"
+            ),
+        );
+    }
+}
+
+/// Whether an id can be used as is in urls and file names
+pub fn plain_id(id: &str) -> bool {
+    !id.starts_with('.')
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+fn dimensions(report: &mut Report, config: &RobotConfig) {
+    let placing = [
+        ("base.height", config.base.height),
+        ("upper_arm.length", config.upper_arm.length),
+        ("lower_arm.length", config.lower_arm.length),
+    ];
+    for (key, value) in placing {
+        match (value, &config.kinematics) {
+            (Some(value), None) => report.positive(key, value),
+            (None, None) => report.add(
+                key,
+                Rule::Required,
+                format!("{key} is required unless a kinematics table places the joints"),
+            ),
+            (Some(_), Some(_)) => report.add(
+                key,
+                Rule::Placement,
+                format!("the kinematics table places the joints, so {key} can not be given"),
+            ),
+            (None, Some(_)) => {}
+        }
+    }
+
+    let lengths = [
+        ("base.radius_bottom", config.base.radius_bottom),
+        ("base.radius_top", config.base.radius_top),
+        ("column.height", config.column.height),
+        ("column.width", config.column.width),
+        ("column.thickness", config.column.thickness),
+        ("upper_arm.width", config.upper_arm.width),
+        ("upper_arm.thickness", config.upper_arm.thickness),
+        ("lower_arm.width", config.lower_arm.width),
+        ("lower_arm.thickness", config.lower_arm.thickness),
+        ("swing_joint.radius", config.swing_joint.radius),
+        ("swing_joint.height", config.swing_joint.height),
+        ("lift_joint.radius", config.lift_joint.radius),
+        ("lift_joint.height", config.lift_joint.height),
+        ("elbow_joint.radius", config.elbow_joint.radius),
+        ("elbow_joint.height", config.elbow_joint.height),
+        ("wrist_joint.radius", config.wrist_joint.radius),
+        ("wrist_joint.height", config.wrist_joint.height),
+        ("gripper.length", config.gripper.length),
+        ("gripper.width", config.gripper.width),
+        ("gripper.thickness", config.gripper.thickness),
+        ("gripper.max_open", config.gripper.max_open),
+    ];
+    for (key, value) in lengths {
+        report.positive(key, value);
+    }
+}
+
+fn limits(report: &mut Report, config: &RobotConfig) {
+    let limits = &config.limits;
+    for joint in Joint::ALL {
+        let (min, max) = limits.bounds(joint);
+
+        if limits.continuous.contains(&joint) {
+            if !joint.is_revolute() {
+                report.add(
+                    "limits.continuous",
+                    Rule::Continuous,
+                    format!("the {joint} joint is prismatic and can not be continuous"),
+                );
+            } else if min.is_some() || max.is_some() {
+                report.add(
+                    format!("limits.{joint}_min"),
+                    Rule::Continuous,
+                    format!("the {joint} joint is continuous and can not declare {joint}_min or {joint}_max"),
+                );
+            }
+            continue;
+        }
+
+        let (Some(min), Some(max)) = (min, max) else {
+            let key = match min {
+                Some(_) => format!("limits.{joint}_max"),
+                None => format!("limits.{joint}_min"),
+            };
+            report.add(
+                key,
+                Rule::BoundsRequired,
+                format!("the {joint} joint requires both {joint}_min and {joint}_max, or to be listed as continuous"),
+            );
+            continue;
+        };
+
+        let min_finite = report.finite(format!("limits.{joint}_min"), min);
+        if !(min_finite & report.finite(format!("limits.{joint}_max"), max)) {
+            continue;
+        }
+        if min > max {
+            report.add(
+                format!("limits.{joint}_min"),
+                Rule::MinAboveMax,
+                format!("{joint}_min ({min}) is above {joint}_max ({max})"),
+            );
+        }
+
+        let (rule, length, part) = match joint {
+            Joint::Lift => (Rule::WithinColumn, config.column.height, "column.height"),
+            Joint::Gripper => (
+                Rule::WithinGripper,
+                config.gripper.max_open,
+                "gripper.max_open",
+            ),
+            _ => continue,
+        };
+        if min < 0. {
+            report.add(
+                format!("limits.{joint}_min"),
+                rule,
+                format!("{joint}_min ({min}) can not be negative"),
+            );
+        } else if min > length {
+            report.add(
+                format!("limits.{joint}_min"),
+                rule,
+                format!("{joint}_min ({min} m) exceeds {part} ({length} m)"),
+            );
+        }
+        if max > length {
+            report.add(
+                format!("limits.{joint}_max"),
+                rule,
+                format!("{joint}_max ({max} m) exceeds {part} ({length} m)"),
+            );
+        }
+    }
+}
+
+fn motion(report: &mut Report, config: &RobotConfig) {
+    for joint in Joint::ALL {
+        let Some(dynamics) = config.motion.joint(joint) else {
+            continue;
+        };
+        report.positive(
+            &format!("motion.{joint}.max_velocity"),
+            dynamics.max_velocity,
+        );
+        report.positive(
+            &format!("motion.{joint}.max_acceleration"),
+            dynamics.max_acceleration,
+        );
+        report.positive(&format!("motion.{joint}.max_jerk"), dynamics.max_jerk);
+        if let Some(jog_increment) = dynamics.jog_increment {
+            report.positive(&format!("motion.{joint}.jog_increment"), jog_increment);
+        }
+    }
+}
+
+fn kinematics(report: &mut Report, config: &RobotConfig) {
+    let Some(table) = &config.kinematics else {
+        return;
+    };
+
+    let mut finite = true;
+    for (row, parameters) in table.rows() {
+        let values = [
+            ("a", parameters.a),
+            ("alpha", parameters.alpha),
+            ("d", parameters.d),
+            ("theta", parameters.theta),
+        ];
+        let mut row_finite = true;
+        for (field, value) in values {
+            row_finite &= report.finite(format!("kinematics.{row}.{field}"), value);
+        }
+        finite &= row_finite;
+        if !row_finite {
+            continue;
+        }
+        if parameters.alpha != 0. {
+            report.add(
+                format!("kinematics.{row}.alpha"),
+                Rule::Placement,
+                "the joints of the crane all move about or along the vertical, so alpha must be 0",
+            );
+        }
+        if parameters.theta != 0. {
+            report.add(
+                format!("kinematics.{row}.theta"),
+                Rule::Placement,
+                "the crane measures its joint angles along the arm, so theta must be 0",
+            );
+        }
+    }
+    // the joints can not be placed from values that are not numbers
+    if !finite {
+        return;
+    }
+    if table.convention == DhConvention::Modified && table.swing.a != 0. {
+        report.add(
+            "kinematics.swing.a",
+            Rule::Placement,
+            "the swing axis passes through the origin of the crane, so a must be 0",
+        );
+    }
+
+    let dimensions = super::dimensions(config);
+    let offsets = LinkOffsets::from(&dimensions);
+    if offsets.column.y <= 0. {
+        report.add(
+            "kinematics.swing.d",
+            Rule::Positive,
+            format!(
+                "the swing joint must sit above the floor, not at {} m",
+                offsets.column.y
+            ),
+        );
+    }
+    let reaches = [
+        ("elbow", "swing axis", offsets.elbow.x),
+        ("wrist", "elbow", offsets.wrist.x),
+    ];
+    for (joint, from, reach) in reaches {
+        if reach <= 0. {
+            report.add(
+                "kinematics",
+                Rule::Positive,
+                format!("the {joint} must sit out from the {from}, not {reach} m from it"),
+            );
+        }
+    }
+    if offsets.elbow.x > 0. && dimensions.upper_arm_length <= 0. {
+        report.add(
+            "kinematics",
+            Rule::Positive,
+            format!(
+                "the elbow is {} m from the swing axis, too close to the column for the upper arm to reach",
+                offsets.elbow.x
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::storage::{load_robot, load_robot_configs, load_toml, urdf, ChainConfig};
+
+    const CRANE: &str = r#"
+id = "crane"
+
+[base]
+height = 0.1
+radius_bottom = 0.35
+radius_top = 0.25
+
+[column]
+height = 2.0
+width = 0.18
+thickness = 0.05
+
+[upper_arm]
+length = 0.75
+width = 0.18
+thickness = 0.04
+
+[lower_arm]
+length = 0.5
+width = 0.12
+thickness = 0.03
+
+[swing_joint]
+radius = 0.13
+height = 0.1
+
+[lift_joint]
+radius = 0.09
+height = 0.08
+
+[elbow_joint]
+radius = 0.08
+height = 0.04
+
+[wrist_joint]
+radius = 0.07
+height = 0.02
+
+[gripper]
+length = 0.2
+width = 0.08
+thickness = 0.05
+max_open = 0.2
+
+[limits]
+continuous = ["swing"]
+lift_min = 0.2
+lift_max = 1.7
+elbow_min = -180
+elbow_max = 180
+wrist_min = -180
+wrist_max = 180
+gripper_min = 0
+gripper_max = 0.2
+"#;
+
+    const CHAIN: &str = r#"
+id = "chain"
+kind = "chain"
+
+[[joints]]
+name = "shoulder"
+type = "revolute"
+dh = { alpha = 90.0, d = 0.4 }
+min = -170
+max = 170
+
+[[joints]]
+name = "carriage"
+type = "prismatic"
+origin = { xyz = [0.0, 0.0, 0.2] }
+axis = [1.0, 0.0, 0.0]
+min = 0
+max = 0.8
+"#;
+
+    fn crane() -> RobotConfig {
+        toml::from_str(CRANE).unwrap()
+    }
+
+    fn chain() -> ChainConfig {
+        toml::from_str(CHAIN).unwrap()
+    }
+
+    fn check(config: &RobotConfig) -> Vec<(String, Rule)> {
+        found(validate(Path::new("crane.toml"), config))
+    }
+
+    fn check_chain(config: &ChainConfig) -> Vec<(String, Rule)> {
+        found(validate_chain(Path::new("chain.toml"), config))
+    }
+
+    fn found(diagnostics: Vec<Diagnostic>) -> Vec<(String, Rule)> {
+        diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.key, diagnostic.rule))
+            .collect()
+    }
+
+    fn load(content: &str) -> Vec<(String, Rule)> {
+        match load_toml(Path::new("robot.toml"), content) {
+            Ok(_) => Vec::new(),
+            Err(invalid) => found(invalid.diagnostics),
+        }
+    }
+
+    fn key_rule(key: &str, rule: Rule) -> Vec<(String, Rule)> {
+        vec![(key.to_string(), rule)]
+    }
+
+    /// A directory of its own for a test to write configs to
+    fn config_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("robotix-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn the_sample_configs_are_valid() {
+        assert_eq!(check(&crane()), Vec::new());
+        assert_eq!(check_chain(&chain()), Vec::new());
+    }
+
+    #[test]
+    fn unreadable() {
+        let error = load_robot(Path::new("/nonexistent/robot.toml")).unwrap_err();
+        assert_eq!(found(error.diagnostics), key_rule("", Rule::Unreadable));
+    }
+
+    #[test]
+    fn syntax() {
+        assert_eq!(load("id = "), key_rule("", Rule::Syntax));
+        let missing = CRANE.replace("[gripper]", "[grip]");
+        assert_eq!(load(&missing), key_rule("", Rule::Syntax));
+    }
+
+    #[test]
+    fn required() {
+        let mut config = crane();
+        config.id = " ".to_string();
+        assert_eq!(check(&config), key_rule("id", Rule::Required));
+
+        let mut config = chain();
+        config.joints.clear();
+        assert_eq!(check_chain(&config), key_rule("joints", Rule::Required));
+    }
+
+    #[test]
+    fn invalid_id() {
+        let mut config = crane();
+        config.id = "../crane".to_string();
+        assert_eq!(check(&config), key_rule("id", Rule::InvalidId));
+    }
+
+    #[test]
+    fn positive() {
+        let mut config = crane();
+        config.column.width = 0.;
+        assert_eq!(check(&config), key_rule("column.width", Rule::Positive));
+    }
+
+    #[test]
+    fn min_above_max() {
+        let mut config = crane();
+        config.limits.elbow_min = Some(90.);
+        config.limits.elbow_max = Some(-90.);
+        assert_eq!(
+            check(&config),
+            key_rule("limits.elbow_min", Rule::MinAboveMax)
+        );
+    }
+
+    #[test]
+    fn bounds_required() {
+        let mut config = crane();
+        config.limits.wrist_max = None;
+        assert_eq!(
+            check(&config),
+            key_rule("limits.wrist_max", Rule::BoundsRequired)
+        );
+    }
+
+    #[test]
+    fn continuous() {
+        let mut config = crane();
+        config.limits.continuous.push(Joint::Lift);
+        assert_eq!(
+            check(&config),
+            key_rule("limits.continuous", Rule::Continuous)
+        );
+    }
+
+    #[test]
+    fn within_column() {
+        let mut config = crane();
+        config.limits.lift_max = Some(2.5);
+        assert_eq!(
+            check(&config),
+            key_rule("limits.lift_max", Rule::WithinColumn)
+        );
+    }
+
+    #[test]
+    fn lift_min_within_column() {
+        let mut config = crane();
+        config.limits.lift_min = Some(2.2);
+        config.limits.lift_max = Some(2.2);
+        assert_eq!(
+            check(&config),
+            vec![
+                ("limits.lift_min".to_string(), Rule::WithinColumn),
+                ("limits.lift_max".to_string(), Rule::WithinColumn),
+            ]
+        );
+    }
+
+    #[test]
+    fn finite() {
+        let mut config = crane();
+        config.limits.elbow_max = Some(f64::NAN);
+        assert_eq!(check(&config), key_rule("limits.elbow_max", Rule::Finite));
+
+        let mut config = chain();
+        config.joints[0].min = Some(f64::NAN);
+        assert_eq!(
+            check_chain(&config),
+            key_rule("joints[0].min", Rule::Finite)
+        );
+
+        let table = "\n[kinematics]\nswing = { d = 0.1 }\nlift = { a = nan }\nelbow = { a = 0.43 }\nwrist = { a = 0.085 }\n";
+        let mut diagnostics = load(&format!("{CRANE}{table}"));
+        // the dimensions the table replaces are reported as well
+        diagnostics.retain(|(_, rule)| *rule == Rule::Finite);
+        assert_eq!(diagnostics, key_rule("kinematics.lift.a", Rule::Finite));
+    }
+
+    #[test]
+    fn positive_dimensions_and_motion() {
+        let mut config = crane();
+        config.upper_arm.width = f64::NAN;
+        assert_eq!(check(&config), key_rule("upper_arm.width", Rule::Positive));
+
+        let motion =
+            "\n[motion.wrist]\nmax_velocity = inf\nmax_acceleration = 1.0\nmax_jerk = 10.0\n";
+        assert_eq!(
+            load(&format!("{CRANE}{motion}")),
+            key_rule("motion.wrist.max_velocity", Rule::Positive)
+        );
+
+        let mut config = chain();
+        config.joints[1].origin.as_mut().unwrap().xyz[2] = f64::NAN;
+        assert_eq!(
+            check_chain(&config),
+            key_rule("joints[1].origin.xyz", Rule::Finite)
+        );
+    }
+
+    #[test]
+    fn within_gripper() {
+        let mut config = crane();
+        config.limits.gripper_max = Some(0.3);
+        assert_eq!(
+            check(&config),
+            key_rule("limits.gripper_max", Rule::WithinGripper)
+        );
+    }
+
+    #[test]
+    fn duplicate_id() {
+        let dir = config_dir("duplicate-id");
+        fs::write(dir.join("a.toml"), CRANE).unwrap();
+        fs::write(dir.join("b.toml"), CRANE).unwrap();
+
+        let (robots, diagnostics) = load_robot_configs(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(robots.len(), 1);
+        assert_eq!(found(diagnostics), key_rule("id", Rule::DuplicateId));
+    }
+
+    #[test]
+    fn topology() {
+        let content = r#"<robot name="urdf"><link name="base"/></robot>"#;
+        let error = urdf::import(Path::new("robot.urdf"), content).unwrap_err();
+        // a robot without joints has no chain to map the crane onto
+        assert_eq!(found(error.diagnostics)[0], (String::new(), Rule::Topology));
+    }
+
+    #[test]
+    fn unmapped() {
+        let content = r#"<robot name="urdf"><link name="base"/><transmission name="t"/></robot>"#;
+        let error = urdf::import(Path::new("robot.urdf"), content).unwrap_err();
+        let unmapped = error
+            .diagnostics
+            .iter()
+            .find(|diagnostic| diagnostic.rule == Rule::Unmapped)
+            .unwrap();
+        assert_eq!(unmapped.severity, Severity::Warning);
+    }
+
+    #[test]
+    fn duplicate_name() {
+        let mut config = chain();
+        config.joints[1].name = "shoulder".to_string();
+        assert_eq!(
+            check_chain(&config),
+            key_rule("joints[1].name", Rule::DuplicateName)
+        );
+    }
+
+    #[test]
+    fn placement() {
+        let mut config = chain();
+        config.joints[0].axis = Some([0., 0., 1.]);
+        assert_eq!(
+            check_chain(&config),
+            key_rule("joints[0].axis", Rule::Placement)
+        );
+
+        let table = "\n[kinematics]\nswing = { d = 0.1 }\nlift = { a = 0.785 }\nelbow = { a = 0.43 }\nwrist = { a = 0.085 }\n";
+        let diagnostics = load(&format!("{CRANE}{table}"));
+        assert_eq!(
+            diagnostics,
+            vec![
+                ("base.height".to_string(), Rule::Placement),
+                ("upper_arm.length".to_string(), Rule::Placement),
+                ("lower_arm.length".to_string(), Rule::Placement),
+            ]
+        );
+    }
+}
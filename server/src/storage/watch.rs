@@ -0,0 +1,92 @@
+//! # watch
+//!
+//! watches the robot config directory and re-parses the `*.toml` and
+//! `*.urdf` files that change in it, so that robots can be added, updated
+//! and removed without restarting the server.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+use crate::robot::Robot;
+
+/// How long to wait for further changes once a file changes, editors
+/// often write a file in more than one go
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+#[derive(Debug)]
+pub enum ConfigEvent {
+    /// a config file was added or changed and describes this robot
+    Changed(Box<Robot>),
+    /// a config file was removed
+    Removed(PathBuf),
+}
+
+pub struct ConfigWatcher {
+    // dropping the watcher stops it
+    _watcher: RecommendedWatcher,
+    paths: UnboundedReceiver<PathBuf>,
+}
+
+impl ConfigWatcher {
+    pub fn watch(config_dir: &str) -> Result<Self> {
+        let (sender, paths) = mpsc::unbounded_channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                match event {
+                    // reading the files reports access events of its own
+                    Ok(event) if event.kind.is_access() => {}
+                    Ok(event) => event.paths.into_iter().for_each(|path| {
+                        let _ = sender.send(path);
+                    }),
+                    Err(e) => tracing::warn!("error watching the robot config directory: {e}"),
+                }
+            })
+            .context("Failed to set up the config directory watcher")?;
+        watcher
+            .watch(Path::new(config_dir), RecursiveMode::NonRecursive)
+            .with_context(|| format!("Failed to watch config directory: {config_dir}"))?;
+        tracing::info!("watching {config_dir} for robot config changes");
+
+        Ok(Self {
+            _watcher: watcher,
+            paths,
+        })
+    }
+
+    /// Waits for config files to change, returning what became of them.
+    /// Files that no longer parse are reported and otherwise left out so
+    /// that the robot keeps its last good config.
+    pub async fn next(&mut self) -> Option<Vec<ConfigEvent>> {
+        let mut changed = BTreeSet::new();
+        changed.insert(self.paths.recv().await?);
+        tokio::time::sleep(DEBOUNCE).await;
+        while let Ok(path) = self.paths.try_recv() {
+            changed.insert(path);
+        }
+
+        let events = changed
+            .into_iter()
+            .filter(|path| super::is_config_file(path))
+            .filter_map(|path| {
+                if !path.exists() {
+                    return Some(ConfigEvent::Removed(path));
+                }
+                match super::load_robot(&path) {
+                    Ok(robot) => Some(ConfigEvent::Changed(Box::new(robot))),
+                    Err(e) => {
+                        for diagnostic in e.diagnostics {
+                            tracing::warn!("ignoring config changes: {diagnostic}");
+                        }
+                        None
+                    }
+                }
+            })
+            .collect();
+        Some(events)
+    }
+}